@@ -2,8 +2,8 @@
 
 use bevy::{color::palettes::tailwind::*, prelude::*, window::PrimaryWindow};
 use bevy_pathfinding::{
-    components::*, debug::resources::DbgOptions, events::InitializeFlowFieldEv, grid::Grid, utils,
-    BevyPathfindingPlugin,
+    components::*, debug::resources::DbgOptions, events::MoveOrderEvent, fvo::FvoPlugin,
+    grid::Grid, spawn::FvoAgentBundle, utils, BevyPathfindingPlugin,
 };
 
 const CELL_SIZE: f32 = 10.0; // size of each cell in the grid
@@ -22,11 +22,12 @@ fn main() {
     app.insert_resource(Grid::new(BUCKETS, MAP_GRID, CELL_SIZE)) // ADD THIS!
         .add_plugins((
             DefaultPlugins,
-            BevyPathfindingPlugin, // ADD THIS!
+            FvoPlugin::new().with_apply_translation(), // ADD THIS! (optional, moves units for you)
+            BevyPathfindingPlugin,                     // ADD THIS!
         ))
         .add_systems(Startup, (camera, setup, spawn_units))
         .add_systems(PostStartup, spawn_obstacles)
-        .add_systems(Update, (set_unit_destination, move_unit))
+        .add_systems(Update, set_unit_destination)
         .run();
 }
 
@@ -83,15 +84,16 @@ fn spawn_units(
             preferred_speed: 50.0,
             max_speed: 60.0,
             max_accel: 120.0,
-            horizon: 3.0,
+            time_horizon_agents: 3.0,
             radius: 2.5,
             sensor_range: 10.0,
+            ..default()
         };
         (
             Mesh3d(meshes.add(Cuboid::new(5.0, 5.0, 5.0))),
             MeshMaterial3d(materials.add(StandardMaterial::from_color(BLUE_500))),
-            Transform::from_translation(pos),
-            FvoAgent::new(settings), // ADD THIS!
+            FvoAgentBundle::new(pos, settings), // ADD THIS!
+            FvoUpdaterSync,                     // follow the debug panel settings
             Name::new("Unit"),
         )
     };
@@ -185,24 +187,10 @@ fn set_unit_destination(
         // get the destination position in world space using the 'get_world_pos' function
         let destination_pos = utils::get_world_pos(map_tf, cam_transform, cam, cursor_pos);
 
-        // create a flowfield and assign the units and destination position it it
-        cmds.trigger(InitializeFlowFieldEv {
-            entities: units,
-            destination_pos,
+        // order the units to the destination, the flowfield is found or created for them
+        cmds.trigger(MoveOrderEvent {
+            units,
+            destination: destination_pos,
         });
     }
 }
-
-// ADD THIS!
-// moves all units that have a destination toward it using the FVO velocity
-// if you are using a physics engine, you would want to swap out the 'Transform' here
-fn move_unit(
-    mut q_units: Query<(&mut Transform, &FvoAgent), With<Destination>>,
-    time: Res<Time>,
-) {
-    let delta_secs = time.delta_secs();
-
-    for (mut tf, agent) in q_units.iter_mut() {
-        tf.translation += agent.velocity * delta_secs;
-    }
-}
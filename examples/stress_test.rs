@@ -3,8 +3,8 @@
 
 use bevy::{color::palettes::tailwind::*, prelude::*, window::PrimaryWindow};
 use bevy_pathfinding::{
-    components::*, debug::resources::DbgOptions, events::InitializeFlowFieldEv, grid::Grid, utils,
-    BevyPathfindingPlugin,
+    components::*, debug::resources::DbgOptions, events::InitializeFlowFieldEv, fvo::FvoPlugin,
+    grid::Grid, utils, BevyPathfindingPlugin,
 };
 
 const CELL_SIZE: f32 = 10.0; // size of each cell in the grid
@@ -23,10 +23,11 @@ fn main() {
     app.insert_resource(Grid::new(BUCKETS, MAP_GRID, CELL_SIZE)) // ADD THIS!
         .add_plugins((
             DefaultPlugins,
-            BevyPathfindingPlugin, // ADD THIS!
+            FvoPlugin::new().with_apply_translation(), // moves units for you
+            BevyPathfindingPlugin,                     // ADD THIS!
         ))
         .add_systems(Startup, (camera, setup, spawn_units))
-        .add_systems(Update, (set_unit_destination, move_unit))
+        .add_systems(Update, set_unit_destination)
         .run();
 }
 
@@ -83,15 +84,17 @@ fn spawn_units(
             preferred_speed: 115.0,
             max_speed: 150.0,
             max_accel: 220.0,
-            horizon: 2.5,
+            time_horizon_agents: 2.5,
             radius: 3.0,
             sensor_range: 12.0,
+            ..default()
         };
         (
             Mesh3d(meshes.add(Cuboid::new(5.0, 5.0, 5.0))),
             MeshMaterial3d(materials.add(StandardMaterial::from_color(BLUE_500))),
             Transform::from_translation(pos),
             FvoAgent::new(settings),
+            FvoUpdaterSync, // follow the debug panel settings
             Name::new("Unit"),
         )
     };
@@ -167,20 +170,7 @@ fn set_unit_destination(
         cmds.trigger(InitializeFlowFieldEv {
             entities: units,
             destination_pos,
+            extra_destinations: Vec::new(),
         });
     }
 }
-
-// ADD THIS!
-// moves all units that have a destination toward it using the FVO velocity
-// if you are using a physics engine, you would want to swap out the 'Transform' here
-fn move_unit(
-    mut q_units: Query<(&mut Transform, &FvoAgent), With<Destination>>,
-    time: Res<Time>,
-) {
-    let delta_secs = time.delta_secs();
-
-    for (mut tf, agent) in q_units.iter_mut() {
-        tf.translation += agent.velocity * delta_secs;
-    }
-}
@@ -87,15 +87,17 @@ fn spawn_units(
             preferred_speed: 110.0,
             max_speed: 140.0,
             max_accel: 200.0,
-            horizon: 3.0,
+            time_horizon_agents: 3.0,
             radius: 3.5,
             sensor_range: 12.0,
+            ..default()
         };
         (
             Mesh3d(meshes.add(Cuboid::new(5.0, 5.0, 5.0))),
             MeshMaterial3d(materials.add(StandardMaterial::from_color(BLUE_500))),
             Transform::from_translation(pos),
             FvoAgent::new(settings),
+            FvoUpdaterSync, // follow the debug panel settings
             Name::new("Unit"),
         )
     };
@@ -171,6 +173,7 @@ fn set_unit_destination(
         cmds.trigger(InitializeFlowFieldEv {
             entities: units,
             destination_pos,
+            extra_destinations: Vec::new(),
         });
     }
 }
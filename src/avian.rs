@@ -0,0 +1,67 @@
+//! Syncs the solver with `avian3d`. Enabled with the `avian` feature and wired up by the
+//! [`FvoPlugin`](crate::fvo::FvoPlugin).
+//!
+//! Agents with a [`LinearVelocity`] get the solved velocity written into it, and the velocity the physics step
+//! actually produced is read back into [`FvoAgent::velocity`] before the next solve. Static bodies get an
+//! [`Obstacle`] sized from their collider, so walls placed for physics also block the flowfield. Do not also enable
+//! [`FvoPlugin::apply_translation`](crate::fvo::FvoPlugin::apply_translation), that would move the agents twice.
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::plane::Plane;
+
+/// Replaces [`FvoAgent::velocity`] with the physics-corrected velocity of the last step.
+pub fn read_avian_velocity(
+    plane: Res<Plane>,
+    mut q_agents: Query<(&mut FvoAgent, &LinearVelocity)>,
+) {
+    for (mut agent, velocity) in q_agents.iter_mut() {
+        agent.velocity = match agent.space {
+            NavigationSpace::Planar => plane.flatten(velocity.0),
+            NavigationSpace::Volumetric => velocity.0,
+        };
+    }
+}
+
+/// Writes the solved velocity into the [`LinearVelocity`] of every agent that still has a [`Destination`] or settled
+/// with [`Arrived`]. Planar agents keep the velocity of their body away from the [`Plane`], so gravity still applies.
+pub fn write_avian_velocity(
+    plane: Res<Plane>,
+    mut q_agents: Query<(&FvoAgent, &mut LinearVelocity), Or<(With<Destination>, With<Arrived>)>>,
+) {
+    for (agent, mut velocity) in q_agents.iter_mut() {
+        velocity.0 = match agent.space {
+            NavigationSpace::Planar => {
+                let up = plane.up();
+                plane.flatten(agent.velocity) + up * velocity.0.dot(up)
+            }
+            NavigationSpace::Volumetric => agent.velocity,
+        };
+    }
+}
+
+/// Inserts an [`Obstacle`] covering the XZ extents of the collider of every new static body.
+pub fn derive_avian_obstacles(
+    mut cmds: Commands,
+    q_bodies: Query<
+        (Entity, &RigidBody, &Collider, &Transform),
+        (
+            Without<Obstacle>,
+            Without<FvoAgent>,
+            Or<(Added<Collider>, Added<RigidBody>)>,
+        ),
+    >,
+) {
+    for (ent, body, collider, tf) in q_bodies.iter() {
+        if !body.is_static() {
+            continue;
+        }
+
+        // the grid rotates the footprint itself, and the collider is only scaled by the next physics step
+        let aabb = collider.aabb(Vec3::ZERO, Quat::IDENTITY);
+        let size = (aabb.max - aabb.min) * tf.scale;
+        cmds.entity(ent).insert(Obstacle(Vec2::new(size.x, size.z)));
+    }
+}
@@ -0,0 +1,220 @@
+use bevy::prelude::*;
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    components::{NavLayer, TraversalProfile},
+    events::InitializeFlowFieldEv,
+    flowfield::FlowField,
+    grid::Grid,
+};
+
+// destination cell, layer, clearance and profile of a flowfield
+type FieldKey = (IVec2, NavLayer, u16, Option<TraversalProfile>);
+
+/// Reuses flowfields for move orders to the same destination cell instead of integrating them again.
+///
+/// An [`InitializeFlowFieldEv`] with a single destination joins the flowfield already serving that cell on the same
+/// [`NavLayer`] and with the same [`FlowField::min_clearance`] and [`FlowField::profile`], or one still being built
+/// for it. Once that flowfield is gone, its integrated field is kept here, up to `capacity` of them with the least
+/// recently used evicted first, and the next order to the cell clones it. Cost edits keep the kept fields up to date
+/// through [`CostFieldChangedEv`], an [`UpdateCostEv`] drops them.
+///
+/// # Example
+///
+/// ```
+/// fn order_to_rally_point(
+///     mut cmds: Commands,
+///     mut cache: ResMut<FlowFieldCache>,
+///     grid: Res<Grid>,
+///     q_selected: Query<Entity, With<Selected>>,
+///     rally: Res<RallyPoint>,
+/// ) {
+///     let units: Vec<Entity> = q_selected.iter().collect();
+///     let flowfield = cache.request_flowfield(&mut cmds, &grid, rally.0, units, NavLayer::Ground);
+///     cmds.entity(flowfield).insert(Name::new("Rally"));
+/// }
+/// ```
+///
+/// [`CostFieldChangedEv`]: crate::events::CostFieldChangedEv
+/// [`UpdateCostEv`]: crate::events::UpdateCostEv
+#[derive(Resource, Clone)]
+pub struct FlowFieldCache {
+    capacity: usize,
+    /// Integrated fields without units, most recently used last.
+    fields: VecDeque<(FieldKey, FlowField)>,
+    /// The flowfield entity serving each destination cell, layer, clearance and profile.
+    live: HashMap<FieldKey, Entity>,
+    /// Entities handed out by `request_flowfield` for flowfields not built yet.
+    reserved: HashMap<(IVec2, NavLayer), Entity>,
+}
+
+impl Default for FlowFieldCache {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl FlowFieldCache {
+    /// Creates a cache keeping up to `capacity` integrated fields, `0` only dedupes live flowfields.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            fields: VecDeque::new(),
+            live: HashMap::new(),
+            reserved: HashMap::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the capacity, evicting the least recently used fields that no longer fit.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Number of integrated fields kept.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Drops every kept field. Live flowfields are still deduped.
+    pub fn clear(&mut self) {
+        self.fields.clear();
+    }
+
+    /// Returns the flowfield entity currently serving the destination cell `idx` on `layer` with `min_clearance` and
+    /// `profile`, see [`FlowField::min_clearance`] and [`FlowField::profile`].
+    pub fn live(
+        &self,
+        idx: IVec2,
+        layer: NavLayer,
+        min_clearance: u16,
+        profile: Option<&TraversalProfile>,
+    ) -> Option<Entity> {
+        self.live
+            .get(&(idx, layer, min_clearance, profile.cloned()))
+            .copied()
+    }
+
+    /// Orders `units` to `destination` and returns the flowfield entity they will follow. Orders to the same
+    /// destination cell return the same entity for as long as it has units. The flowfield itself is inserted once
+    /// built, see [`FlowFieldReady`](crate::events::FlowFieldReady). `layer` must be the [`NavLayer`] of the first
+    /// unit, which the flowfield is built for. With [`FlowFieldConfig::size_classes`] the units are split by size and
+    /// the entity returned is the flowfield of the smallest class.
+    ///
+    /// [`FlowFieldConfig::size_classes`]: crate::flowfield::FlowFieldConfig::size_classes
+    pub fn request_flowfield(
+        &mut self,
+        cmds: &mut Commands,
+        grid: &Grid,
+        destination: Vec3,
+        units: Vec<Entity>,
+        layer: NavLayer,
+    ) -> Entity {
+        let idx = grid.get_cell_from_world_position(destination).idx;
+        let live = self
+            .live
+            .iter()
+            .filter(|((cell, cell_layer, ..), _)| *cell == idx && *cell_layer == layer)
+            .min_by_key(|((_, _, min_clearance, _), _)| *min_clearance)
+            .map(|(_, &ent)| ent);
+        let ff_ent = live.unwrap_or_else(|| {
+            *self
+                .reserved
+                .entry((idx, layer))
+                .or_insert_with(|| cmds.spawn_empty().id())
+        });
+
+        cmds.trigger(InitializeFlowFieldEv {
+            entities: units,
+            destination_pos: destination,
+            extra_destinations: Vec::new(),
+        });
+
+        ff_ent
+    }
+
+    pub(crate) fn set_live(
+        &mut self,
+        idx: IVec2,
+        layer: NavLayer,
+        min_clearance: u16,
+        profile: Option<&TraversalProfile>,
+        ff_ent: Entity,
+    ) {
+        self.live
+            .insert((idx, layer, min_clearance, profile.cloned()), ff_ent);
+    }
+
+    pub(crate) fn remove_live(&mut self, ff_ent: Entity) {
+        self.live.retain(|_, ent| *ent != ff_ent);
+        self.reserved.retain(|_, ent| *ent != ff_ent);
+    }
+
+    /// Hands the entity reserved for `idx` on `layer` to the first flowfield built for it.
+    pub(crate) fn take_reserved(&mut self, idx: IVec2, layer: NavLayer) -> Option<Entity> {
+        self.reserved.remove(&(idx, layer))
+    }
+
+    /// Returns a copy of the field kept for `idx` on `layer` with `min_clearance` and `profile`, and marks it as most
+    /// recently used.
+    pub(crate) fn get(
+        &mut self,
+        idx: IVec2,
+        layer: NavLayer,
+        min_clearance: u16,
+        profile: Option<&TraversalProfile>,
+    ) -> Option<FlowField> {
+        let pos =
+            self.fields
+                .iter()
+                .position(|((cell, cell_layer, clearance, cell_profile), _)| {
+                    (*cell, *cell_layer, *clearance, cell_profile.as_ref())
+                        == (idx, layer, min_clearance, profile)
+                })?;
+        let entry = self.fields.remove(pos)?;
+        let ff = entry.1.clone();
+        self.fields.push_back(entry);
+        Some(ff)
+    }
+
+    /// Keeps the integrated field of `ff` for its destination cell, layer, clearance and profile, without its units.
+    pub(crate) fn insert(&mut self, idx: IVec2, ff: &FlowField) {
+        let key = (idx, ff.layer, ff.min_clearance, ff.profile.clone());
+        self.fields.retain(|(kept, _)| *kept != key);
+
+        let mut template = ff.clone();
+        template.units.clear();
+        template.steering_map.clear();
+        template.weights.clear();
+        template.arrived_units.clear();
+        template.goal_slots.clear();
+        template.arrived = false;
+        self.fields.push_back((key, template));
+
+        self.evict();
+    }
+
+    /// Marks `region` dirty on every kept field, re-integrated once one of them is reused.
+    pub(crate) fn mark_dirty(&mut self, region: IRect) {
+        for (_, ff) in self.fields.iter_mut() {
+            ff.dirty_region = Some(match ff.dirty_region {
+                Some(dirty) => dirty.union(region),
+                None => region,
+            });
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.fields.len() > self.capacity {
+            self.fields.pop_front();
+        }
+    }
+}
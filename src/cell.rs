@@ -3,22 +3,50 @@ use std::u16;
 
 use crate::grid_direction::GridDirection;
 
-#[derive(Clone, Default, Copy, Debug, PartialEq, Reflect)]
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cell {
     pub best_cost: u16,
     pub best_direction: GridDirection,
     pub cost: u8,
+    /// Extra cost of stepping out of this cell to the north, east, south and west, on top of the cost of the cell
+    /// stepped into. `u8::MAX` forbids the step, see [`Grid::set_one_way`](crate::grid::Grid::set_one_way).
+    pub exit_costs: [u8; 4],
     pub idx: IVec2,
+    /// Set on flowfield cells with an unobstructed straight line of open ground to their closest goal. Units in
+    /// these cells head directly for the goal.
+    pub los: bool,
+    /// Scales the preferred speed of agents standing on this cell. `> 1.0` for roads, `< 1.0` for mud.
+    pub speed_multiplier: f32,
+    /// Center of the cell, `y` is the terrain height once the grid sampled a heightmap or terrain mesh.
     pub world_pos: Vec3,
 }
 
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            best_cost: 0,
+            best_direction: GridDirection::default(),
+            cost: 0,
+            exit_costs: [0; 4],
+            idx: IVec2::ZERO,
+            los: false,
+            speed_multiplier: 1.0,
+            world_pos: Vec3::ZERO,
+        }
+    }
+}
+
 impl Cell {
     pub fn new(world_position: Vec3, grid_idx: IVec2) -> Self {
         Cell {
             best_cost: u16::MAX,
             best_direction: GridDirection::None,
             cost: 1,
+            exit_costs: [0; 4],
             idx: grid_idx,
+            los: false,
+            speed_multiplier: 1.0,
             world_pos: world_position,
         }
     }
@@ -43,6 +71,27 @@ impl Cell {
         .collect()
     }
 
+    /// Extra cost of stepping out of this cell towards `direction`. A diagonal step costs the larger of its two
+    /// cardinal steps, so it can't sneak past a one-way edge.
+    pub fn exit_cost(&self, direction: GridDirection) -> u8 {
+        match direction {
+            GridDirection::None => 0,
+            GridDirection::North => self.exit_costs[0],
+            GridDirection::East => self.exit_costs[1],
+            GridDirection::South => self.exit_costs[2],
+            GridDirection::West => self.exit_costs[3],
+            GridDirection::NorthEast => self.exit_costs[0].max(self.exit_costs[1]),
+            GridDirection::SouthEast => self.exit_costs[2].max(self.exit_costs[1]),
+            GridDirection::SouthWest => self.exit_costs[2].max(self.exit_costs[3]),
+            GridDirection::NorthWest => self.exit_costs[0].max(self.exit_costs[3]),
+        }
+    }
+
+    /// Whether units may step out of this cell towards `direction`.
+    pub fn can_exit(&self, direction: GridDirection) -> bool {
+        self.exit_cost(direction) != u8::MAX
+    }
+
     pub fn increase_cost(&mut self, amount: u8) {
         if self.cost == u8::MAX {
             return;
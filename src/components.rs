@@ -1,32 +1,117 @@
+use bevy::math::curve::{Curve, EaseFunction};
 use bevy::prelude::*;
 
+use crate::{lod::FvoLod, preferred::PreferredVelocity};
+
 /// A marker component for the map base. Insert this into your base map entity.
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct MapBase;
 
 /// A marker component for the primary camera. Insert this into your camera entity.
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct GameCamera;
 
 /// Destination marker. This is dynamically added to every agent entity when the flowfield is initialized.
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct Destination;
 
+/// Marks an agent that settled at its goal in place of its [`Destination`]. Settled agents stop requesting a
+/// preferred velocity but are still solved and moved, so they give way to units pushing into the crowd like a soft
+/// obstacle and the crowd packs into a stable blob. Removed again when the agent gets a new order.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct Arrived;
+
+/// What an agent is up to, kept up to date after every solve for gameplay code and animations. Every change triggers
+/// an [`AgentStateChangedEvent`](crate::events::AgentStateChangedEvent).
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[reflect(Component)]
+pub enum AgentState {
+    /// Nothing provides a preferred velocity, the agent has no order.
+    #[default]
+    Idle,
+    /// Heading where it wants to go at (close to) its preferred speed.
+    Moving,
+    /// Wants to go noticeably slower than its preferred speed, e.g. when approaching its goal.
+    Slowing,
+    /// Settled at its goal, see [`Arrived`].
+    Arrived,
+    /// Wants to move but neighbors or walls have held it back to a crawl for a moment.
+    Blocked,
+}
+
 /// Obstacle marker. Insert this into any entity that you want to be considered an obstacle by the flowfield(s).
+/// The cost field is carved as soon as it is inserted and freed again when it is removed or the entity despawned, the
+/// cells get back the cost they had before. The flowfields around it are re-integrated in the same frame. Insert it again after moving the entity.
 /// # Parameters
 /// - `Vec2`: The size of the obstacles mesh. Only the x and z values are used.
-#[derive(Component, Default)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Obstacle(pub Vec2);
 
+/// A door or gate, insert it next to an [`Obstacle`] (or [`ObstacleAuto`]). Closed, its footprint is carved into the
+/// cost field like any obstacle, so flowfields route around it and agents avoid its cells as walls. Open, the cells
+/// get back the cost they have without it, walls overlapping the gate stay. Flip `open` at any time, the cells are freed or carved again and the flowfields around the gate are
+/// re-integrated in the same frame.
+///
+/// # Example
+///
+/// ```
+/// cmds.spawn((Gate { open: false }, Obstacle(Vec2::new(8.0, 2.0)), Transform::from_xyz(40.0, 0.0, 12.0)));
+///
+/// fn open_gates(mut q_gates: Query<&mut Gate>) {
+///     for mut gate in q_gates.iter_mut() {
+///         gate.open = true;
+///     }
+/// }
+/// ```
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[reflect(Component)]
+pub struct Gate {
+    pub open: bool,
+}
+
+/// Derives the [`Obstacle`] size instead of entering it by hand. The XZ extent is taken from the physics collider
+/// (with the `avian` feature), else the entity's `Aabb` or its `Mesh3d`, scaled by the transform. Retried every frame
+/// until one of them is available, e.g. once the mesh asset is loaded.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct ObstacleAuto;
+
+/// Group membership. Agents sharing the same id are pulled toward each other when
+/// [`FvoSettings::cohesion_weight`] is greater than zero.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[reflect(Component)]
+pub struct GroupId(pub u32);
+
+/// Moving obstacle that is not driven by the solver, e.g. a patrolling NPC or a vehicle. Agents avoid it like another
+/// agent, but it never yields, so they take the whole avoidance. Keep `velocity` up to date from your own movement.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq)]
+#[reflect(Component)]
+pub struct DynamicObstacle {
+    pub velocity: Vec3,
+    pub radius: f32,
+}
+
 /// FVO agent that steers using a feasible-velocity-obstacle solver.
-#[derive(Component, Debug)]
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+#[require(PreferredVelocity, AgentState, FvoLod)]
 pub struct FvoAgent {
     /// Last chosen steering / velocity vector in world space.
     pub steering: Vec3,
     /// Current linear velocity used by the solver.
     pub velocity: Vec3,
+    /// Facing direction on the XZ plane, turned toward the velocity at up to
+    /// [`FvoSettings::max_angular_speed`].
+    pub heading: Vec3,
     /// Tunable parameters for the solver.
     pub settings: FvoSettings,
+    /// Whether the agent avoids on the XZ plane or in full 3D.
+    pub space: NavigationSpace,
 }
 
 impl Default for FvoAgent {
@@ -34,7 +119,9 @@ impl Default for FvoAgent {
         Self {
             steering: Vec3::ZERO,
             velocity: Vec3::ZERO,
+            heading: Vec3::NEG_Z,
             settings: FvoSettings::default(),
+            space: NavigationSpace::Planar,
         }
     }
 }
@@ -45,13 +132,342 @@ impl FvoAgent {
         Self {
             steering: Vec3::ZERO,
             velocity: Vec3::ZERO,
+            heading: Vec3::NEG_Z,
             settings,
+            space: NavigationSpace::Planar,
+        }
+    }
+
+    /// Creates a flying agent that avoids others in full 3D.
+    pub fn volumetric(settings: FvoSettings) -> Self {
+        Self {
+            space: NavigationSpace::Volumetric,
+            ..Self::new(settings)
+        }
+    }
+}
+
+/// Turns the agent toward its velocity at up to `turn_speed` radians per second, so you don't have to write the
+/// rotation yourself. Planar agents only turn around the Y axis. Takes over the rotation from
+/// [`apply_steering`](crate::fvo::apply_steering), agents that stand still keep facing the same way.
+///
+/// # Example
+///
+/// ```
+/// cmds.spawn((FvoAgent::default(), FaceVelocity::new(6.0)));
+/// ```
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct FaceVelocity {
+    /// `f32::INFINITY` turns instantly.
+    pub turn_speed: f32,
+}
+
+impl Default for FaceVelocity {
+    fn default() -> Self {
+        Self::new(8.0)
+    }
+}
+
+impl FaceVelocity {
+    pub fn new(turn_speed: f32) -> Self {
+        Self { turn_speed }
+    }
+}
+
+/// Keeps the agent on the ground of sloped maps, the solver only moves it on XZ. Its Y is set every frame from the
+/// terrain height of the grid (see [`Grid::height_at`](crate::grid::Grid::height_at)) once the grid sampled a
+/// heightmap or terrain mesh, else from the plane of the [`MapBase`] under it. Volumetric agents are left alone.
+///
+/// # Example
+///
+/// ```
+/// // a capsule 2 units tall stands on its feet
+/// cmds.spawn((FvoAgentBundle::new(pos, settings), TerrainFollow::new(1.0)));
+/// ```
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct TerrainFollow {
+    /// Height of the agent's origin above the ground.
+    pub offset: f32,
+}
+
+impl TerrainFollow {
+    pub fn new(offset: f32) -> Self {
+        Self { offset }
+    }
+}
+
+/// Space an [`FvoAgent`] avoids in.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Reflect)]
+pub enum NavigationSpace {
+    /// Agents are discs on the XZ plane and the solved velocity has no Y component.
+    #[default]
+    Planar,
+    /// Agents are spheres and the solved velocity may climb or descend. Grid walls are ignored, the flow field
+    /// still drives the horizontal direction.
+    Volumetric,
+}
+
+/// Avoidance filtering between agents. An agent only builds constraints against neighbors whose `memberships` share a
+/// bit with its own `filter`. Agents without the component are on every layer and avoid everything.
+///
+/// # Example
+///
+/// ```
+/// const INFANTRY: u32 = 1 << 0;
+/// const PROJECTILE: u32 = 1 << 1;
+///
+/// // projectiles fly straight through infantry but still avoid each other
+/// let projectile = AvoidanceLayers::new(PROJECTILE, PROJECTILE);
+/// ```
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub struct AvoidanceLayers {
+    /// Layers this agent is on.
+    pub memberships: u32,
+    /// Layers this agent avoids.
+    pub filter: u32,
+}
+
+impl Default for AvoidanceLayers {
+    fn default() -> Self {
+        Self {
+            memberships: u32::MAX,
+            filter: u32::MAX,
+        }
+    }
+}
+
+impl AvoidanceLayers {
+    pub fn new(memberships: u32, filter: u32) -> Self {
+        Self {
+            memberships,
+            filter,
+        }
+    }
+
+    /// Returns `true` if an agent with these layers should steer around `other`.
+    pub fn avoids(&self, other: &AvoidanceLayers) -> bool {
+        self.filter & other.memberships != 0
+    }
+}
+
+/// Team of an agent. Agents of the same team are allies, agents of another team enemies and agents without a team
+/// neutral. How an agent avoids each of them is set by its [`TeamRules`].
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[reflect(Component)]
+pub struct Team(pub u8);
+
+/// How an agent avoids a neighbor, see [`TeamRules`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum TeamAvoidance {
+    /// Both sides take their share of the avoidance, split by [`FvoSettings::priority`] and [`FvoSettings::mass`].
+    #[default]
+    Reciprocal,
+    /// The agent takes the whole avoidance, like against a [`DynamicObstacle`], without relying on the neighbor to
+    /// give way.
+    Obstacle,
+    /// No constraint at all, the agent walks through the neighbor.
+    Ignore,
+}
+
+/// Per-agent avoidance behavior by the [`Team`] relationship to the neighbor. Only applies to agents with a `Team`,
+/// agents without the component avoid everyone reciprocally. Checked after [`AvoidanceLayers`].
+///
+/// # Example
+///
+/// ```
+/// // make room for allies, never count on enemies to give way and walk through neutrals
+/// cmds.spawn((
+///     FvoAgent::default(),
+///     Team(1),
+///     TeamRules::new(TeamAvoidance::Reciprocal, TeamAvoidance::Obstacle, TeamAvoidance::Ignore),
+/// ));
+/// ```
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[reflect(Component)]
+pub struct TeamRules {
+    /// Neighbors of the same team.
+    pub allies: TeamAvoidance,
+    /// Neighbors of another team.
+    pub enemies: TeamAvoidance,
+    /// Neighbors without a team.
+    pub neutrals: TeamAvoidance,
+}
+
+impl TeamRules {
+    pub fn new(allies: TeamAvoidance, enemies: TeamAvoidance, neutrals: TeamAvoidance) -> Self {
+        Self {
+            allies,
+            enemies,
+            neutrals,
+        }
+    }
+
+    /// How an agent of team `own` avoids a neighbor of team `other`.
+    pub fn avoidance(&self, own: Option<Team>, other: Option<Team>) -> TeamAvoidance {
+        match (own, other) {
+            (None, _) => TeamAvoidance::Reciprocal,
+            (Some(_), None) => self.neutrals,
+            (Some(own), Some(other)) if own == other => self.allies,
+            (Some(_), Some(_)) => self.enemies,
+        }
+    }
+}
+
+/// The cost layer of the [`Grid`](crate::grid::Grid) an agent navigates on. Flowfields are integrated over the layer
+/// of their units and agents only treat the walls of their own layer as obstacles, so boats stay off land and fliers
+/// ignore walls. Agents without it are on [`NavLayer::Ground`].
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[reflect(Component)]
+pub enum NavLayer {
+    /// The cell costs of the grid, carved by every `Obstacle`.
+    #[default]
+    Ground,
+    /// Impassable until painted with [`Grid::set_layer_cost`](crate::grid::Grid::set_layer_cost).
+    Water,
+    /// Free everywhere until painted, walls on the ground do not block it.
+    Air,
+    /// The cheaper of `Ground` and `Water` in every cell, it can't be painted itself.
+    Amphibious,
+}
+
+/// Terrain tag of a grid cell, painted with [`Grid::set_terrain`](crate::grid::Grid::set_terrain). `0` is untagged.
+pub type TerrainTag = u8;
+
+/// How a kind of unit weighs the terrain, so wheeled, tracked and infantry units share one grid and still take their
+/// own routes. Every distinct profile gets flowfields of its own, integrated over the costs of its [`NavLayer`] scaled
+/// by the [`TerrainTag`] of each cell. Agents without it take the layer costs as they are.
+///
+/// # Example
+///
+/// ```
+/// const ROAD: TerrainTag = 1;
+/// const FOREST: TerrainTag = 2;
+/// const SWAMP: TerrainTag = 3;
+///
+/// let wheeled = TraversalProfile::new()
+///     .with_multiplier(ROAD, 0.5)
+///     .with_multiplier(FOREST, 4.0)
+///     .with_impassable(SWAMP);
+/// cmds.spawn((FvoAgentBundle::new(pos, settings), wheeled));
+/// ```
+#[derive(Component, Debug, Default, Clone, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[reflect(Component)]
+pub struct TraversalProfile {
+    /// Multiplier on the cell cost per terrain tag, tags not listed keep their cost.
+    pub multipliers: Vec<(TerrainTag, f32)>,
+    /// Terrain tags the units never enter.
+    pub impassable: Vec<TerrainTag>,
+}
+
+impl TraversalProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scales the cost of cells tagged `tag` by `multiplier`, replacing an earlier multiplier of the tag.
+    pub fn with_multiplier(mut self, tag: TerrainTag, multiplier: f32) -> Self {
+        self.multipliers.retain(|(other, _)| *other != tag);
+        self.multipliers.push((tag, multiplier.max(0.0)));
+        self
+    }
+
+    /// Makes cells tagged `tag` impassable.
+    pub fn with_impassable(mut self, tag: TerrainTag) -> Self {
+        if !self.impassable.contains(&tag) {
+            self.impassable.push(tag);
         }
+        self
     }
+
+    /// The cost of a cell of `cost` tagged `tag` for these units. Walls stay walls and open cells never become free
+    /// or walls through a multiplier.
+    pub fn cost(&self, tag: TerrainTag, cost: u8) -> u8 {
+        if cost == u8::MAX || self.impassable.contains(&tag) {
+            return u8::MAX;
+        }
+
+        match self.multipliers.iter().find(|(other, _)| *other == tag) {
+            Some(&(_, multiplier)) => (cost as f32 * multiplier).round().clamp(1.0, 254.0) as u8,
+            None => cost,
+        }
+    }
+}
+
+// profiles key flowfields, multipliers compare by their bits
+impl PartialEq for TraversalProfile {
+    fn eq(&self, other: &Self) -> bool {
+        let bits = |profile: &Self| {
+            profile
+                .multipliers
+                .iter()
+                .map(|(tag, multiplier)| (*tag, multiplier.to_bits()))
+                .collect::<Vec<_>>()
+        };
+        bits(self) == bits(other) && self.impassable == other.impassable
+    }
+}
+
+impl Eq for TraversalProfile {}
+
+impl std::hash::Hash for TraversalProfile {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        for (tag, multiplier) in &self.multipliers {
+            tag.hash(state);
+            multiplier.to_bits().hash(state);
+        }
+        self.impassable.hash(state);
+    }
+}
+
+/// Opt-in marker for agents whose settings should follow the debug panel's
+/// [`FvoUpdater`](crate::debug::resources::FvoUpdater) and the tuning file (`ron` feature). Agents without it keep
+/// the settings they were spawned with.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct FvoUpdaterSync;
+
+/// Per-frame solver diagnostics. Insert this on an agent to have
+/// [`calculate_fvo_steering`](crate::fvo::calculate_fvo_steering) fill it in, agents without it skip the bookkeeping
+/// entirely.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct FvoDebugInfo {
+    /// Number of neighbors considered for avoidance.
+    pub neighbor_count: usize,
+    /// Number of half-plane constraints built from those neighbors.
+    pub constraint_count: usize,
+    /// `true` if no velocity satisfied every constraint and the solver fell back to a best effort.
+    pub infeasible: bool,
+    /// Preferred velocity fed into the solver, after arrival and cohesion.
+    pub preferred_velocity: Vec3,
+    /// Final velocity after separation and acceleration limits.
+    pub solved_velocity: Vec3,
+}
+
+/// Locomotion of an agent for animation blending, e.g. walk/run blends by `speed_ratio` and leaning into turns by
+/// `turning_rate`. Insert this on an agent to have [`calculate_fvo_steering`](crate::fvo::calculate_fvo_steering)
+/// update it with every solve.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq)]
+#[reflect(Component)]
+pub struct LocomotionState {
+    /// Current speed relative to [`FvoSettings::preferred_speed`], `1.0` at cruise speed.
+    pub speed_ratio: f32,
+    /// How fast the heading turns in radians per second, positive to the left (counterclockwise seen from above).
+    pub turning_rate: f32,
 }
 
 /// Parameters for the feasible velocity obstacle solver.
 #[derive(Debug, Copy, Clone, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
 pub struct FvoSettings {
     /// Desired cruise speed along the flow field direction.
     pub preferred_speed: f32,
@@ -59,12 +475,44 @@ pub struct FvoSettings {
     pub max_speed: f32,
     /// Maximum linear acceleration applied per second.
     pub max_accel: f32,
-    /// Lookahead time window for predicting collisions.
-    pub horizon: f32,
+    /// Lookahead time window for predicting collisions with other agents.
+    pub time_horizon_agents: f32,
+    /// Lookahead time window for walls. Usually longer than `time_horizon_agents` since walls never move out of
+    /// the way.
+    pub time_horizon_obstacles: f32,
     /// Physical radius of the agent in world units.
     pub radius: f32,
     /// Maximum neighbor distance considered for avoidance.
     pub sensor_range: f32,
+    /// Minimum number of neighbors to consider. If fewer are within `sensor_range` the search widens (up to a fixed
+    /// cap) and the closest agents found are used, with constraints weakened by their distance. `0` disables this.
+    pub min_neighbors: usize,
+    /// Only the `max_neighbors` closest neighbors are used to build constraints. `0` disables the cap.
+    pub max_neighbors: usize,
+    /// Strength of the pull toward the centroid of nearby agents with the same [`GroupId`]. `0.0` disables cohesion.
+    pub cohesion_weight: f32,
+    /// Group members farther away than this are ignored, the pull reaches full strength at this distance.
+    pub cohesion_max_distance: f32,
+    /// Approach deceleration and stopping distance. A flowfield's [`FlowField::arrival`] takes precedence.
+    ///
+    /// [`FlowField::arrival`]: crate::flowfield::FlowField::arrival
+    pub arrival: ArrivalSettings,
+    /// Blends the solved velocity with the previous one to damp oscillation between passing agents, in `[0, 1)`.
+    /// `0.0` disables smoothing.
+    pub steering_smoothing: f32,
+    /// Time constant in seconds of an exponential low-pass on the solved velocity, removing the high-frequency jitter
+    /// of dense crowds independent of the frame rate. Only [`FvoAgent::velocity`] is filtered, the raw solve stays in
+    /// [`FvoAgent::steering`] for physics. `0.0` disables the filter.
+    pub velocity_smoothing_time: f32,
+    /// Share of the avoidance burden relative to neighbors. Agents with a higher priority than their neighbor take
+    /// less of the avoidance (down to none), agents with a lower priority yield more (up to twice). Equal priorities
+    /// each take the full avoidance, so an agent still clears a neighbor that does not react.
+    pub priority: f32,
+    /// Weights the mutual avoidance and separation like `priority`, so a heavy unit plows gently through light ones
+    /// that get out of its way. Both factors multiply.
+    pub mass: f32,
+    /// Maximum turn rate of the heading in radians per second. `f32::INFINITY` turns instantly.
+    pub max_angular_speed: f32,
 }
 
 impl Default for FvoSettings {
@@ -73,9 +521,20 @@ impl Default for FvoSettings {
             preferred_speed: 50.0,
             max_speed: 60.0,
             max_accel: 100.0,
-            horizon: 3.0,
+            time_horizon_agents: 3.0,
+            time_horizon_obstacles: 3.0,
             radius: 2.5,
             sensor_range: 8.0,
+            min_neighbors: 0,
+            max_neighbors: 0,
+            cohesion_weight: 0.0,
+            cohesion_max_distance: 20.0,
+            arrival: ArrivalSettings::default(),
+            steering_smoothing: 0.0,
+            velocity_smoothing_time: 0.0,
+            priority: 1.0,
+            mass: 1.0,
+            max_angular_speed: f32::INFINITY,
         }
     }
 }
@@ -85,7 +544,7 @@ impl FvoSettings {
         preferred_speed: f32,
         max_speed: f32,
         max_accel: f32,
-        horizon: f32,
+        time_horizon_agents: f32,
         radius: f32,
         sensor_range: f32,
     ) -> Self {
@@ -93,9 +552,91 @@ impl FvoSettings {
             preferred_speed,
             max_speed,
             max_accel,
-            horizon,
+            time_horizon_agents,
             radius,
             sensor_range,
+            ..default()
+        }
+    }
+}
+
+/// How an agent decelerates when it approaches its goal and where it comes to a halt.
+#[derive(Debug, Copy, Clone, PartialEq, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct ArrivalSettings {
+    /// Distance from the goal at which agents start to slow down. `None` slows down from twice the agent's
+    /// [`FvoSettings::sensor_range`].
+    pub slow_radius: Option<f32>,
+    /// Distance from the goal at which the preferred speed reaches zero. Leave some room for crowds, not every
+    /// agent fits on the goal itself.
+    pub stop_radius: f32,
+    /// Shape of the slow-down between `slow_radius` and `stop_radius`.
+    pub curve: ArrivalCurve,
+}
+
+impl Default for ArrivalSettings {
+    fn default() -> Self {
+        Self {
+            slow_radius: None,
+            stop_radius: 0.0,
+            curve: ArrivalCurve::Linear,
+        }
+    }
+}
+
+impl ArrivalSettings {
+    pub fn new(slow_radius: f32, stop_radius: f32, curve: ArrivalCurve) -> Self {
+        Self {
+            slow_radius: Some(slow_radius),
+            stop_radius,
+            curve,
+        }
+    }
+
+    /// The slow radius of an agent with `sensor_range`, see [`ArrivalSettings::slow_radius`].
+    pub fn slow_radius_for(&self, sensor_range: f32) -> f32 {
+        self.slow_radius.unwrap_or((sensor_range * 2.0).max(0.1))
+    }
+
+    /// Returns the speed scale for `distance` from the goal of an agent with `sensor_range`. `0.0` within
+    /// `stop_radius`, `1.0` at or beyond the slow radius.
+    pub fn speed_scale(&self, distance: f32, sensor_range: f32) -> f32 {
+        let slow_radius = self.slow_radius_for(sensor_range);
+        if distance >= slow_radius {
+            return 1.0;
+        }
+
+        let span = (slow_radius - self.stop_radius).max(f32::EPSILON);
+        self.curve
+            .ease(((distance - self.stop_radius) / span).clamp(0.0, 1.0))
+    }
+}
+
+/// Deceleration profile used when an agent approaches its goal.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum ArrivalCurve {
+    /// Speed falls off linearly with the distance to the goal.
+    #[default]
+    Linear,
+    /// Brakes early, then creeps in to the goal.
+    Quadratic,
+    /// Smooth start and end of the deceleration.
+    SmoothStep,
+    /// Any of Bevy's easing functions, e.g. `EaseFunction::ExponentialOut` to keep speed until the very end.
+    Custom(EaseFunction),
+}
+
+impl ArrivalCurve {
+    /// Maps the progress `t` through the slow-down zone, `0.0` at the stop radius and `1.0` at the slow radius, to a
+    /// speed scale.
+    pub fn ease(&self, t: f32) -> f32 {
+        match self {
+            ArrivalCurve::Linear => t,
+            ArrivalCurve::Quadratic => t * t,
+            ArrivalCurve::SmoothStep => t * t * (3.0 - 2.0 * t),
+            ArrivalCurve::Custom(ease) => ease.sample_clamped(t),
         }
     }
 }
@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+
+use crate::{
+    components::FvoAgent, events::CostFieldChangedEv, flowfield::FlowFieldConfig, grid::Grid,
+};
+
+/// Feeds the agent density back into the integration cost, see
+/// [`FlowFieldConfig::congestion`](crate::flowfield::FlowFieldConfig::congestion).
+///
+/// Every `refresh_interval` the agents per cell are counted into [`Grid::congestion`] and the flowfields are
+/// re-integrated where it changed, so units ordered later route around the jam. Units already in it weigh their own
+/// cells as well, keep `cost_per_agent` low enough that a column does not push itself off its path.
+#[derive(Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct CongestionSettings {
+    /// Cost added to a cell per agent standing on it.
+    pub cost_per_agent: f32,
+    /// Upper bound of the added cost per cell.
+    pub max_cost: u8,
+    /// Seconds between two density samples.
+    pub refresh_interval: f32,
+}
+
+impl Default for CongestionSettings {
+    fn default() -> Self {
+        Self {
+            cost_per_agent: 4.0,
+            max_cost: 50,
+            refresh_interval: 0.5,
+        }
+    }
+}
+
+// samples the agent density into the congestion overlay and marks the cells whose cost changed dirty
+pub(crate) fn update_congestion(
+    mut cmds: Commands,
+    time: Res<Time>,
+    config: Res<FlowFieldConfig>,
+    mut grid: ResMut<Grid>,
+    q_agents: Query<&Transform, With<FvoAgent>>,
+    mut since_refresh: Local<f32>,
+) {
+    // the overlay is not part of the cost field, keep sector graph and debug draw from reacting to it
+    let grid = grid.bypass_change_detection();
+    let full = IRect::from_corners(IVec2::ZERO, grid.size - IVec2::ONE);
+
+    let Some(settings) = config.congestion else {
+        // turned off, drop what is left of the overlay
+        if !grid.congestion.is_empty() {
+            grid.congestion.clear();
+            cmds.trigger(CostFieldChangedEv { region: full });
+        }
+        return;
+    };
+
+    *since_refresh += time.delta_secs();
+    if *since_refresh < settings.refresh_interval {
+        return;
+    }
+    *since_refresh = 0.0;
+
+    let cell_count = (grid.size.x * grid.size.y) as usize;
+    let mut density = vec![0u32; cell_count];
+    for tf in q_agents.iter() {
+        let idx = grid.get_cell_from_world_position(tf.translation).idx;
+        if let Some(count) = density.get_mut((idx.y * grid.size.x + idx.x) as usize) {
+            *count += 1;
+        }
+    }
+
+    if grid.congestion.len() != cell_count {
+        grid.congestion = vec![0; cell_count];
+    }
+
+    let mut region: Option<IRect> = None;
+    for (i, count) in density.into_iter().enumerate() {
+        let cost = (count as f32 * settings.cost_per_agent)
+            .round()
+            .min(settings.max_cost as f32) as u8;
+        if grid.congestion[i] == cost {
+            continue;
+        }
+
+        grid.congestion[i] = cost;
+        let idx = IVec2::new(i as i32 % grid.size.x, i as i32 / grid.size.x);
+        region = Some(match region {
+            Some(region) => region.union_point(idx),
+            None => IRect::from_corners(idx, idx),
+        });
+    }
+
+    if let Some(region) = region {
+        cmds.trigger(CostFieldChangedEv { region });
+    }
+}
@@ -100,3 +100,9 @@ pub struct IndexMarker;
 
 #[derive(Component, Clone, Copy)]
 pub struct FlowFieldMarker;
+
+#[derive(Component, Clone, Copy)]
+pub struct HeatmapMarker;
+
+#[derive(Component, Clone, Copy)]
+pub struct DensityMarker;
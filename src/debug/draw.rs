@@ -1,23 +1,42 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    image::ImageSampler,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
 use std::collections::HashMap;
 
 use super::components::*;
 use super::resources::*;
 use crate::*;
 use grid::Grid;
+use spatial_hash::SpatialHash;
 
 const BASE_SCALE: f32 = 0.2;
+/// Upper bound on the gizmo arrows drawn for [`DbgOptions::flowfield_gizmos`], larger grids skip cells to stay below.
+const MAX_GIZMO_ARROWS: usize = 4096;
 
 pub struct DrawPlugin;
 
 impl Plugin for DrawPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(PostStartup, draw_on_startup)
+            .add_systems(
+                Update,
+                (
+                    draw_flowfield_gizmos.run_if(|dbg: Res<DbgOptions>| dbg.flowfield_gizmos),
+                    update_agent_density.run_if(|dbg: Res<DbgOptions>| {
+                        dbg.draw_mode_1 == DrawMode::Density || dbg.draw_mode_2 == DrawMode::Density
+                    }),
+                ),
+            )
             .add_observer(trigger_events)
             .add_observer(draw_grid)
             .add_observer(set_active_dbg_flowfield)
             .add_observer(draw_costfield)
             .add_observer(draw_flowfield)
             .add_observer(draw_integration_field)
+            .add_observer(draw_heatmap)
+            .add_observer(draw_density)
             .add_observer(draw_index);
     }
 }
@@ -54,6 +73,8 @@ fn trigger_events(_trigger: On<DrawAllEv>, mut cmds: Commands, dbg: Res<DbgOptio
     cmds.trigger(DrawCostFieldEv);
     cmds.trigger(DrawFlowFieldEv);
     cmds.trigger(DrawIntegrationFieldEv);
+    cmds.trigger(DrawHeatmapEv);
+    cmds.trigger(DrawDensityEv);
 
     dbg.print("trigger_events() end");
 }
@@ -77,27 +98,27 @@ fn draw_grid(
 
     dbg.print("\ndraw_grid() start");
 
-    let line_length_x = grid.size.x as f32 * grid.cell_diameter; // Length of horizontal lines
-    let line_length_y = grid.size.y as f32 * grid.cell_diameter; // Length of vertical lines
+    let line_length_x = grid.world_size().x; // Length of horizontal lines
+    let line_length_y = grid.world_size().y; // Length of vertical lines
     let mut row_instances = HashMap::new();
     let mut column_instances = HashMap::new();
 
     let row_count = grid.grid.len();
     let col_count = grid.grid[0].len();
 
-    let grid_origin_x = Vec3::new(-(grid.cell_diameter * col_count as f32) / 2.0, 0.0, 0.0);
-    let grid_origin_y = Vec3::new(0.0, 0.0, -(grid.cell_diameter * row_count as f32) / 2.0);
+    let grid_origin_x = Vec3::new(-(grid.cell_size.x * col_count as f32) / 2.0, 0.0, 0.0);
+    let grid_origin_y = Vec3::new(0.0, 0.0, -(grid.cell_size.y * row_count as f32) / 2.0);
 
     // Horizontal lines (rows)
     for row in 0..=row_count {
-        let z = row as f32 * grid.cell_diameter;
+        let z = row as f32 * grid.cell_size.y;
 
         let mut instance_data = Vec::new();
         instance_data.push(debug::shader::InstanceData {
             id: 0,
-            position: Vec3::new(0.0, 0.1, z) + grid_origin_y,
+            position: grid.local_to_world(Vec3::new(0.0, 0.1, z) + grid_origin_y),
             scale: 1.0,
-            rotation: Quat::IDENTITY.into(),
+            rotation: grid.rotation().into(),
             color: [1.0, 1.0, 1.0, 1.0],
             texture: -4,
         });
@@ -107,14 +128,14 @@ fn draw_grid(
 
     // Vertical lines (columns)
     for col in 0..=col_count {
-        let x = col as f32 * grid.cell_diameter;
+        let x = col as f32 * grid.cell_size.x;
 
         let mut instance_data = Vec::new();
         instance_data.push(debug::shader::InstanceData {
             id: 0,
-            position: Vec3::new(x, 0.1, 0.0) + grid_origin_x,
+            position: grid.local_to_world(Vec3::new(x, 0.1, 0.0) + grid_origin_x),
             scale: 1.0,
-            rotation: Quat::IDENTITY.into(),
+            rotation: grid.rotation().into(),
             color: [1.0, 1.0, 1.0, 1.0],
             texture: -4,
         });
@@ -157,6 +178,11 @@ pub fn draw_flowfield(
         return;
     };
 
+    // drawn every frame by `draw_flowfield_gizmos` instead
+    if dbg.flowfield_gizmos {
+        return;
+    }
+
     let mut marker_scale = 0.6;
     if (dbg.draw_mode_1 == DrawMode::None || dbg.draw_mode_2 == DrawMode::None)
         || (dbg.draw_mode_1 == DrawMode::FlowField && dbg.draw_mode_2 == DrawMode::FlowField)
@@ -164,10 +190,11 @@ pub fn draw_flowfield(
         marker_scale = 1.0;
     }
 
-    let offset = calculate_offset(grid.cell_diameter, &dbg, DrawMode::FlowField);
+    let offset = calculate_offset(grid.cell_size.y, &dbg, DrawMode::FlowField);
     let Some(offset) = offset else {
         return;
     };
+    let offset = grid.rotation() * offset;
 
     dbg.print("\ndraw_flowfield() start");
 
@@ -176,12 +203,15 @@ pub fn draw_flowfield(
 
     for cell_row in active_dbg_ff.grid.iter() {
         for cell in cell_row.iter() {
-            let is_destination_cell = active_dbg_ff.destination_cell.idx == cell.idx;
+            let is_destination_cell = active_dbg_ff
+                .destination_cells
+                .iter()
+                .any(|dest| dest.idx == cell.idx);
             let id = cell.idx_to_id(grid.grid.len());
 
             let mut instance_data = Vec::new();
 
-            let flatten = Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+            let flatten = grid.rotation() * Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
             let heading = Quat::from_rotation_z(cell.best_direction.to_angle());
             let rotation = flatten * heading;
 
@@ -202,7 +232,7 @@ pub fn draw_flowfield(
                         rotation: rotation.into(),
                         color,
                         texture: -1,
-                        id: id,
+                        id,
                     });
                 }
 
@@ -211,7 +241,7 @@ pub fn draw_flowfield(
                 instance_data.push(debug::shader::InstanceData {
                     position: cell.world_pos + offset,
                     scale: marker_scale * 0.65,
-                    rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2).into(),
+                    rotation: flatten.into(),
                     color,
                     texture: -3,
                     id,
@@ -225,13 +255,68 @@ pub fn draw_flowfield(
     // spawn arrow marker
     cmds.spawn((
         FlowFieldMarker,
-        Mesh3d(meshes.add(Rectangle::new(grid.cell_diameter, grid.cell_diameter))),
+        Mesh3d(meshes.add(Rectangle::from_length(grid.cell_size.min_element()))),
         debug::shader::InstanceMaterialData(instances),
     ));
 
     dbg.print("draw_flowfield() end");
 }
 
+fn draw_flowfield_gizmos(
+    dbg: Res<DbgOptions>,
+    grid: Res<Grid>,
+    active_dbg_flowfield: Res<ActiveDbgFlowfield>,
+    mut gizmos: Gizmos,
+) {
+    let Some(active_dbg_ff) = &active_dbg_flowfield.0 else {
+        return;
+    };
+
+    let Some(offset) = calculate_offset(grid.cell_size.y, &dbg, DrawMode::FlowField) else {
+        return;
+    };
+    let offset = grid.rotation() * offset;
+
+    // every `step`-th cell along both axes, one arrow then stands for a `step` x `step` block
+    let cell_count = active_dbg_ff.size.x.max(0) as usize * active_dbg_ff.size.y.max(0) as usize;
+    let step = ((cell_count as f32 / MAX_GIZMO_ARROWS as f32).sqrt().ceil() as usize).max(1);
+    let half = grid.cell_size.min_element() * 0.4 * step as f32;
+    let up = grid.rotation() * Vec3::Y;
+
+    for cell_row in active_dbg_ff.grid.iter().step_by(step) {
+        for cell in cell_row.iter().step_by(step) {
+            let pos = cell.world_pos + offset;
+
+            if active_dbg_ff
+                .destination_cells
+                .iter()
+                .any(|dest| dest.idx == cell.idx)
+            {
+                let iso = Isometry3d::new(pos, Quat::from_rotation_arc(Vec3::Z, up));
+                gizmos.circle(iso, half * 0.5, Color::WHITE);
+                continue;
+            }
+
+            if cell.cost == u8::MAX {
+                let a = grid.rotation() * Vec3::new(half, 0.0, half) * 0.5;
+                let b = grid.rotation() * Vec3::new(half, 0.0, -half) * 0.5;
+                gizmos.line(pos - a, pos + a, Color::WHITE);
+                gizmos.line(pos - b, pos + b, Color::WHITE);
+                continue;
+            }
+
+            let dir = cell.best_direction.vector().as_vec2().normalize_or_zero();
+            if dir == Vec2::ZERO {
+                continue;
+            }
+
+            let dir = grid.direction_to_world(dir);
+            let dir = grid.plane.from_2d(dir) * half;
+            gizmos.arrow(pos - dir, pos + dir, Color::WHITE);
+        }
+    }
+}
+
 fn draw_costfield(
     _trigger: On<DrawCostFieldEv>,
     dbg: Res<DbgOptions>,
@@ -245,14 +330,14 @@ fn draw_costfield(
         cmds.entity(cost_entity).despawn();
     }
 
-    let base_offset = calculate_offset(grid.cell_diameter, &dbg, DrawMode::CostField);
+    let base_offset = calculate_offset(grid.cell_size.y, &dbg, DrawMode::CostField);
     let Some(base_offset) = base_offset else {
         return;
     };
 
     dbg.print("\ndraw_costfield() start");
 
-    let base_digit_spacing = grid.cell_diameter * 0.275;
+    let base_digit_spacing = grid.cell_size.min_element() * 0.275;
 
     let mut instances = HashMap::new();
 
@@ -262,7 +347,7 @@ fn draw_costfield(
 
             // Calculate spacing and scale based on digit count
             let (digit_spacing, scale_factor) = calculate_digit_spacing_and_scale(
-                grid.cell_diameter,
+                grid.cell_size.min_element(),
                 digits_vec.len(),
                 base_digit_spacing,
                 BASE_SCALE,
@@ -287,9 +372,11 @@ fn draw_costfield(
                 offset.x += x_offset + i as f32 * digit_spacing;
 
                 instance_data.push(debug::shader::InstanceData {
-                    position: cell.world_pos + offset,
+                    position: cell.world_pos + grid.rotation() * offset,
                     scale: marker_scale,
-                    rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2).into(),
+                    rotation: (grid.rotation()
+                        * Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2))
+                    .into(),
                     color: [1.0, 1.0, 1.0, 1.0],
                     texture: digit as i32,
                     id,
@@ -302,7 +389,7 @@ fn draw_costfield(
 
     cmds.spawn((
         CostMarker,
-        Mesh3d(meshes.add(Rectangle::new(grid.cell_diameter, grid.cell_diameter))),
+        Mesh3d(meshes.add(Rectangle::from_length(grid.cell_size.min_element()))),
         debug::shader::InstanceMaterialData(instances),
     ));
 
@@ -327,14 +414,14 @@ fn draw_integration_field(
         return;
     };
 
-    let base_offset = calculate_offset(grid.cell_diameter, &dbg, DrawMode::IntegrationField);
+    let base_offset = calculate_offset(grid.cell_size.y, &dbg, DrawMode::IntegrationField);
     let Some(base_offset) = base_offset else {
         return;
     };
 
     dbg.print("\ndraw_integration_field() start");
 
-    let base_digit_spacing = grid.cell_diameter * 0.275;
+    let base_digit_spacing = grid.cell_size.min_element() * 0.275;
 
     let mut instances = HashMap::new();
 
@@ -344,7 +431,7 @@ fn draw_integration_field(
 
             // Calculate spacing and scale based on digit count
             let (digit_spacing, scale_factor) = calculate_digit_spacing_and_scale(
-                grid.cell_diameter,
+                grid.cell_size.min_element(),
                 digits_vec.len(),
                 base_digit_spacing,
                 BASE_SCALE,
@@ -369,9 +456,11 @@ fn draw_integration_field(
                 offset.x += x_offset + i as f32 * digit_spacing;
 
                 instance_data.push(debug::shader::InstanceData {
-                    position: cell.world_pos + offset,
+                    position: cell.world_pos + grid.rotation() * offset,
                     scale: marker_scale,
-                    rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2).into(),
+                    rotation: (grid.rotation()
+                        * Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2))
+                    .into(),
                     color: [1.0, 1.0, 1.0, 1.0],
                     texture: digit as i32,
                     id,
@@ -384,13 +473,212 @@ fn draw_integration_field(
 
     cmds.spawn((
         BestCostMarker,
-        Mesh3d(meshes.add(Rectangle::new(grid.cell_diameter, grid.cell_diameter))),
+        Mesh3d(meshes.add(Rectangle::from_length(grid.cell_size.min_element()))),
         debug::shader::InstanceMaterialData(instances),
     ));
 
     dbg.print("draw_integration_field() end");
 }
 
+#[allow(clippy::too_many_arguments)]
+fn draw_heatmap(
+    _trigger: On<DrawHeatmapEv>,
+    dbg: Res<DbgOptions>,
+    active_dbg_flowfield: Res<ActiveDbgFlowfield>,
+    grid: Res<Grid>,
+    q_heatmap: Query<Entity, With<HeatmapMarker>>,
+    mut heatmap: ResMut<IntegrationHeatmap>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cmds: Commands,
+) {
+    for heatmap_entity in &q_heatmap {
+        cmds.entity(heatmap_entity).despawn();
+    }
+
+    let Some(ff) = &active_dbg_flowfield.0 else {
+        return;
+    };
+
+    dbg.print("\ndraw_heatmap() start");
+
+    // normalize against the most expensive reachable cell
+    let max_cost = ff
+        .grid
+        .iter()
+        .flatten()
+        .filter(|cell| cell.best_cost != u16::MAX)
+        .map(|cell| cell.best_cost)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut data = Vec::with_capacity(ff.size.x as usize * ff.size.y as usize * 4);
+    for cell_row in &ff.grid {
+        for cell in cell_row {
+            let rgba = if cell.cost == u8::MAX {
+                [60, 60, 60, 200]
+            } else if cell.best_cost == u16::MAX {
+                [0, 0, 0, 200]
+            } else {
+                let t = cell.best_cost as f32 / max_cost as f32;
+                let [r, g, b, _] = Color::hsl(120.0 * (1.0 - t), 1.0, 0.5)
+                    .to_srgba()
+                    .to_u8_array();
+                [r, g, b, 160]
+            };
+            data.extend_from_slice(&rgba);
+        }
+    }
+
+    let image = field_image(ff.size, data);
+    match images.get_mut(&heatmap.0) {
+        Some(existing) => *existing = image,
+        None => heatmap.0 = images.add(image),
+    }
+
+    if dbg.draw_mode_1 != DrawMode::Heatmap && dbg.draw_mode_2 != DrawMode::Heatmap {
+        return;
+    }
+
+    cmds.spawn((
+        HeatmapMarker,
+        field_overlay(&grid, heatmap.0.clone(), &mut meshes, &mut materials),
+    ));
+
+    dbg.print("draw_heatmap() end");
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_density(
+    _trigger: On<DrawDensityEv>,
+    dbg: Res<DbgOptions>,
+    grid: Res<Grid>,
+    q_density: Query<Entity, With<DensityMarker>>,
+    mut density: ResMut<AgentDensity>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cmds: Commands,
+) {
+    for density_entity in &q_density {
+        cmds.entity(density_entity).despawn();
+    }
+
+    if dbg.draw_mode_1 != DrawMode::Density && dbg.draw_mode_2 != DrawMode::Density {
+        return;
+    }
+
+    // filled in by `update_agent_density` from the next frame on
+    if images.get(&density.image).is_none() {
+        let blank = vec![0; grid.size.x.max(0) as usize * grid.size.y.max(0) as usize * 4];
+        density.image = images.add(field_image(grid.size, blank));
+    }
+
+    cmds.spawn((
+        DensityMarker,
+        field_overlay(&grid, density.image.clone(), &mut meshes, &mut materials),
+    ));
+}
+
+// counts the agents per cell from the spatial hash, the positions the solver buckets agents by
+fn update_agent_density(
+    time: Res<Time>,
+    grid: Res<Grid>,
+    spatial: Res<SpatialHash>,
+    mut density: ResMut<AgentDensity>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let cell_count = grid.size.x.max(0) as usize * grid.size.y.max(0) as usize;
+    if density.size != grid.size || density.counts.len() != cell_count {
+        density.size = grid.size;
+        density.counts = vec![0; cell_count];
+        density.average = vec![0.0; cell_count];
+    }
+
+    density.counts.fill(0);
+    for (_, pos) in spatial.positions() {
+        let pos = grid.plane.from_plane(pos);
+        if !grid.contains(pos) {
+            continue;
+        }
+
+        let idx = grid.get_cell_from_world_position(pos).idx;
+        density.counts[(idx.y * grid.size.x + idx.x) as usize] += 1;
+    }
+
+    let density = &mut *density;
+    let blend = 1.0 - (-time.delta_secs() / DENSITY_SMOOTHING).exp();
+    for (average, &count) in density.average.iter_mut().zip(&density.counts) {
+        *average += (count as f32 - *average) * blend;
+    }
+
+    // yellow for a single agent, red for the most crowded cell
+    let max = density.average.iter().copied().fold(1.0, f32::max);
+    let mut data = Vec::with_capacity(cell_count * 4);
+    for &average in &density.average {
+        let rgba = if average < 0.05 {
+            [0, 0, 0, 0]
+        } else {
+            let t = ((average - 1.0) / (max - 1.0).max(f32::EPSILON)).clamp(0.0, 1.0);
+            let [r, g, b, _] = Color::hsl(60.0 * (1.0 - t), 1.0, 0.5)
+                .to_srgba()
+                .to_u8_array();
+            [r, g, b, (80.0 + 120.0 * average.min(1.0)) as u8]
+        };
+        data.extend_from_slice(&rgba);
+    }
+
+    let image = field_image(density.size, data);
+    match images.get_mut(&density.image) {
+        Some(existing) => *existing = image,
+        None => density.image = images.add(image),
+    }
+}
+
+/// Time constant of [`AgentDensity::average`] in seconds.
+const DENSITY_SMOOTHING: f32 = 0.5;
+
+// one pixel per cell, row `0` at the top so it lines up with the plane's UVs
+fn field_image(size: IVec2, data: Vec<u8>) -> Image {
+    let mut image = Image::new(
+        Extent3d {
+            width: size.x.max(0) as u32,
+            height: size.y.max(0) as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.sampler = ImageSampler::nearest();
+    image
+}
+
+// laid flat over the grid just above the ground, terrain heights are not followed
+fn field_overlay(
+    grid: &Grid,
+    image: Handle<Image>,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) -> impl Bundle {
+    let world_size = grid.world_size();
+    (
+        Mesh3d(meshes.add(Plane3d::default().mesh().size(world_size.x, world_size.y))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color_texture: Some(image),
+            unlit: true,
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        })),
+        grid.transform
+            * Transform::from_rotation(grid.plane.rotation())
+            * Transform::from_xyz(0.0, 0.02, 0.0),
+    )
+}
+
 fn draw_index(
     _trigger: On<DrawAllEv>,
     dbg: Res<DbgOptions>,
@@ -408,14 +696,14 @@ fn draw_index(
         return;
     }
 
-    let base_offset = calculate_offset(grid.cell_diameter, &dbg, DrawMode::Index);
+    let base_offset = calculate_offset(grid.cell_size.y, &dbg, DrawMode::Index);
     let Some(base_offset) = base_offset else {
         return;
     };
 
     dbg.print("\ndraw_index() start");
 
-    let base_digit_spacing = grid.cell_diameter * 0.275; // TODO: Consider moving to a constant
+    let base_digit_spacing = grid.cell_size.min_element() * 0.275; // TODO: Consider moving to a constant
     let mut instances = HashMap::new();
 
     for cell_row in grid.grid.iter() {
@@ -427,7 +715,7 @@ fn draw_index(
 
             // Calculate spacing and scale based on digit count
             let (digit_spacing, scale_factor) = calculate_digit_spacing_and_scale(
-                grid.cell_diameter,
+                grid.cell_size.min_element(),
                 digits_vec.len(),
                 base_digit_spacing,
                 BASE_SCALE,
@@ -456,9 +744,11 @@ fn draw_index(
                 offset.x += x_offset + i as f32 * digit_spacing;
 
                 instance_data.push(debug::shader::InstanceData {
-                    position: cell.world_pos + offset,
+                    position: cell.world_pos + grid.rotation() * offset,
                     scale: marker_scale,
-                    rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2).into(),
+                    rotation: (grid.rotation()
+                        * Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2))
+                    .into(),
                     color: [1.0, 1.0, 1.0, 1.0],
                     texture: digit as i32,
                     id,
@@ -472,18 +762,14 @@ fn draw_index(
     cmds.spawn((
         // TODO: Remove?
         IndexMarker,
-        Mesh3d(meshes.add(Rectangle::new(grid.cell_diameter, grid.cell_diameter))),
+        Mesh3d(meshes.add(Rectangle::from_length(grid.cell_size.min_element()))),
         debug::shader::InstanceMaterialData(instances),
     ));
 
     dbg.print("draw_index() end");
 }
 
-fn calculate_offset(
-    cell_diameter: f32,
-    dbg: &Res<DbgOptions>,
-    draw_mode: DrawMode,
-) -> Option<Vec3> {
+fn calculate_offset(cell_depth: f32, dbg: &Res<DbgOptions>, draw_mode: DrawMode) -> Option<Vec3> {
     let mode = if dbg.draw_mode_1 == draw_mode {
         Some(1)
     } else if dbg.draw_mode_2 == draw_mode {
@@ -504,8 +790,8 @@ fn calculate_offset(
         offset.z = 0.0;
     } else {
         match mode {
-            Some(1) => offset.z = -cell_diameter * 0.25,
-            Some(2) => offset.z = cell_diameter * 0.25,
+            Some(1) => offset.z = -cell_depth * 0.25,
+            Some(2) => offset.z = cell_depth * 0.25,
             _ => (),
         };
     }
@@ -0,0 +1,312 @@
+//! Live tuning panel built with `bevy_egui`. Enabled with the `egui` feature and added by the
+//! [`DebugPlugin`](crate::debug::DebugPlugin).
+//!
+//! The panel edits [`FvoUpdater`] and [`DbgOptions`] in place, lists every flowfield with its units and goal, and
+//! inspects a single agent picked from a dropdown. Agents with an [`FvoDebugInfo`] also show their last solve.
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPlugin, EguiPrimaryContextPass};
+use std::ops::RangeInclusive;
+
+use super::resources::*;
+use crate::{
+    cache::FlowFieldCache,
+    components::{ArrivalCurve, Destination, FvoAgent, FvoDebugInfo, NavLayer},
+    events::DrawAllEv,
+    flowfield::FlowField,
+};
+
+pub struct EguiPanelPlugin;
+
+impl Plugin for EguiPanelPlugin {
+    fn build(&self, app: &mut App) {
+        // the game may already use egui itself
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin::default());
+        }
+
+        app.add_systems(EguiPrimaryContextPass, draw_egui_panel);
+    }
+}
+
+const DRAW_MODES: [DrawMode; 7] = [
+    DrawMode::None,
+    DrawMode::CostField,
+    DrawMode::FlowField,
+    DrawMode::IntegrationField,
+    DrawMode::Heatmap,
+    DrawMode::Density,
+    DrawMode::Index,
+];
+
+fn draw_egui_panel(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut fvo_updater: ResMut<FvoUpdater>,
+    mut dbg: ResMut<DbgOptions>,
+    cache: Option<Res<FlowFieldCache>>,
+    q_flowfields: Query<(Entity, &FlowField)>,
+    q_agents: Query<(
+        Entity,
+        &FvoAgent,
+        Option<&Name>,
+        Option<&NavLayer>,
+        Option<&FvoDebugInfo>,
+        Has<Destination>,
+    )>,
+    mut inspected: Local<Option<Entity>>,
+) -> Result {
+    let ctx = contexts.ctx_mut()?;
+
+    egui::Window::new("Pathfinding").show(ctx, |ui| {
+        egui::CollapsingHeader::new("FVO")
+            .default_open(true)
+            .show(ui, |ui| fvo_updater_ui(ui, &mut fvo_updater));
+
+        egui::CollapsingHeader::new("Debug").show(ui, |ui| {
+            if dbg_options_ui(ui, &mut dbg) {
+                cmds.trigger(DrawAllEv);
+            }
+        });
+
+        egui::CollapsingHeader::new("Flowfields").show(ui, |ui| {
+            flowfield_stats_ui(ui, &q_flowfields, cache.as_deref());
+        });
+
+        egui::CollapsingHeader::new("Agent").show(ui, |ui| {
+            let label = |ent: Entity, name: Option<&Name>| match name {
+                Some(name) => format!("{name} ({ent})"),
+                None => ent.to_string(),
+            };
+
+            let selected = inspected
+                .and_then(|ent| q_agents.get(ent).ok())
+                .map(|(ent, _, name, ..)| label(ent, name))
+                .unwrap_or_else(|| String::from("None"));
+            egui::ComboBox::from_label("Inspect")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut *inspected, None, "None");
+                    for (ent, _, name, ..) in q_agents.iter() {
+                        ui.selectable_value(&mut *inspected, Some(ent), label(ent, name));
+                    }
+                });
+
+            let Some(Ok((ent, agent, _, layer, info, moving))) =
+                inspected.map(|ent| q_agents.get(ent))
+            else {
+                return;
+            };
+
+            let flowfields = q_flowfields
+                .iter()
+                .filter(|(_, ff)| ff.units.contains(&ent))
+                .count();
+
+            egui::Grid::new("fvo_agent").num_columns(2).show(ui, |ui| {
+                row(ui, "Velocity", format!("{:.2}", agent.velocity));
+                row(ui, "Speed", format!("{:.2}", agent.velocity.length()));
+                row(ui, "Heading", format!("{:.2}", agent.heading));
+                row(ui, "Radius", format!("{:.2}", agent.settings.radius));
+                row(
+                    ui,
+                    "Nav layer",
+                    format!("{:?}", layer.copied().unwrap_or_default()),
+                );
+                row(ui, "Has destination", moving.to_string());
+                row(ui, "Flowfields", flowfields.to_string());
+
+                if let Some(info) = info {
+                    row(ui, "Neighbors", info.neighbor_count.to_string());
+                    row(ui, "Constraints", info.constraint_count.to_string());
+                    row(ui, "Infeasible", info.infeasible.to_string());
+                    row(
+                        ui,
+                        "Preferred velocity",
+                        format!("{:.2}", info.preferred_velocity),
+                    );
+                }
+            });
+
+            if info.is_none() {
+                ui.label("Insert `FvoDebugInfo` on the agent to see its solve.");
+            }
+        });
+    });
+
+    // keep clicks on the panel from reaching the game, like the built-in debug menu does
+    if ctx.is_pointer_over_area() {
+        dbg.hover = true;
+    }
+
+    Ok(())
+}
+
+// only marks the resource changed when a value was actually edited, so agents aren't re-synced every frame
+fn fvo_updater_ui(ui: &mut egui::Ui, fvo_updater: &mut ResMut<FvoUpdater>) {
+    let updater = fvo_updater.bypass_change_detection();
+    let mut changed = false;
+
+    let mut slider =
+        |ui: &mut egui::Ui, label: &str, value: &mut f32, range: RangeInclusive<f32>| {
+            changed |= ui
+                .add(egui::Slider::new(value, range).text(label))
+                .changed();
+        };
+    slider(
+        ui,
+        "Preferred speed",
+        &mut updater.preferred_speed,
+        0.0..=200.0,
+    );
+    slider(ui, "Max speed", &mut updater.max_speed, 0.0..=200.0);
+    slider(ui, "Max accel", &mut updater.max_accel, 0.0..=500.0);
+    slider(
+        ui,
+        "Horizon agents",
+        &mut updater.time_horizon_agents,
+        0.1..=10.0,
+    );
+    slider(
+        ui,
+        "Horizon obstacles",
+        &mut updater.time_horizon_obstacles,
+        0.1..=10.0,
+    );
+    slider(ui, "Radius", &mut updater.radius, 0.1..=20.0);
+    slider(ui, "Sensor range", &mut updater.sensor_range, 0.0..=50.0);
+    slider(
+        ui,
+        "Arrival radius",
+        &mut updater.arrival_radius,
+        0.0..=100.0,
+    );
+    slider(
+        ui,
+        "Steering smoothing",
+        &mut updater.steering_smoothing,
+        0.0..=1.0,
+    );
+    slider(
+        ui,
+        "Velocity smoothing time",
+        &mut updater.velocity_smoothing_time,
+        0.0..=1.0,
+    );
+
+    egui::ComboBox::from_label("Arrival curve")
+        .selected_text(format!("{:?}", updater.arrival_curve))
+        .show_ui(ui, |ui| {
+            for curve in [
+                ArrivalCurve::Linear,
+                ArrivalCurve::Quadratic,
+                ArrivalCurve::SmoothStep,
+            ] {
+                let label = format!("{curve:?}");
+                changed |= ui
+                    .selectable_value(&mut updater.arrival_curve, curve, label)
+                    .changed();
+            }
+        });
+
+    if changed {
+        fvo_updater.set_changed();
+    }
+}
+
+// returns `true` if anything that affects the drawn debug fields was edited
+fn dbg_options_ui(ui: &mut egui::Ui, dbg: &mut ResMut<DbgOptions>) -> bool {
+    let options = dbg.bypass_change_detection();
+    let mut changed = false;
+
+    changed |= ui.checkbox(&mut options.draw_grid, "Grid").changed();
+    changed |= ui
+        .checkbox(&mut options.draw_spatial_grid, "Spatial grid")
+        .changed();
+    changed |= ui
+        .checkbox(
+            &mut options.draw_spatial_hashing_grid,
+            "Spatial hashing grid",
+        )
+        .changed();
+    changed |= ui.checkbox(&mut options.draw_radius, "Radius").changed();
+    changed |= ui
+        .checkbox(&mut options.draw_velocities, "Velocities")
+        .changed();
+    changed |= ui
+        .checkbox(&mut options.draw_neighbor_links, "Neighbor links")
+        .changed();
+    changed |= ui
+        .checkbox(&mut options.flowfield_gizmos, "Flowfield gizmos")
+        .changed();
+
+    for (label, mode) in [
+        ("Mode 1", &mut options.draw_mode_1),
+        ("Mode 2", &mut options.draw_mode_2),
+    ] {
+        egui::ComboBox::from_label(label)
+            .selected_text(DbgOptions::draw_mode_to_string(*mode))
+            .show_ui(ui, |ui| {
+                for option in DRAW_MODES {
+                    let txt = DbgOptions::draw_mode_to_string(option);
+                    changed |= ui.selectable_value(mode, option, txt).changed();
+                }
+            });
+    }
+
+    let print = ui
+        .checkbox(&mut options.print_statements, "Print statements")
+        .changed();
+
+    if changed || print {
+        dbg.set_changed();
+    }
+
+    changed
+}
+
+fn flowfield_stats_ui(
+    ui: &mut egui::Ui,
+    q_flowfields: &Query<(Entity, &FlowField)>,
+    cache: Option<&FlowFieldCache>,
+) {
+    let units: usize = q_flowfields.iter().map(|(_, ff)| ff.units.len()).sum();
+    ui.label(format!("Flowfields: {}", q_flowfields.iter().count()));
+    ui.label(format!("Units following: {units}"));
+    if let Some(cache) = cache {
+        ui.label(format!(
+            "Cached fields: {}/{}",
+            cache.len(),
+            cache.capacity()
+        ));
+    }
+
+    egui::Grid::new("flowfields")
+        .num_columns(4)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Entity");
+            ui.strong("Goal");
+            ui.strong("Units");
+            ui.strong("Layer");
+            ui.end_row();
+
+            for (ent, ff) in q_flowfields.iter() {
+                ui.label(ent.to_string());
+                ui.label(format!("{}", ff.destination_cell.idx));
+                ui.label(format!(
+                    "{} ({} arrived)",
+                    ff.units.len(),
+                    ff.arrived_units.len()
+                ));
+                ui.label(format!("{:?}", ff.layer));
+                ui.end_row();
+            }
+        });
+}
+
+fn row(ui: &mut egui::Ui, label: &str, value: String) {
+    ui.label(label);
+    ui.label(value);
+    ui.end_row();
+}
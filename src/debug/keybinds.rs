@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+
+use super::components::*;
+use super::resources::*;
+use crate::events::DrawAllEv;
+
+pub struct KeybindsPlugin;
+
+impl Plugin for KeybindsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DbgKeybinds>()
+            .register_type::<DbgKeybinds>()
+            .add_systems(
+                Update,
+                handle_dbg_keybinds.run_if(|keybinds: Res<DbgKeybinds>| keybinds.enabled),
+            );
+    }
+}
+
+/// Keys toggling the debug options without going through the debug menu. Unbind a single toggle with `None`, or
+/// stop all debug input handling with [`DbgKeybinds::disabled`].
+///
+/// | Key | Action |
+/// |-----|--------|
+/// | F1 | cycle [`DbgOptions::draw_mode_1`] |
+/// | F2 | toggle [`DbgOptions::draw_spatial_grid`] |
+/// | F3 | cycle [`DbgOptions::draw_mode_2`] |
+/// | F4 | toggle [`DbgOptions::draw_grid`] |
+/// | F5 | toggle [`DbgOptions::draw_radius`] |
+/// | F6 | toggle [`DbgOptions::draw_velocities`] |
+/// | F7 | toggle [`DbgOptions::draw_neighbor_links`] |
+/// | F8 | toggle [`DbgOptions::flowfield_gizmos`] |
+///
+/// # Example
+///
+/// ```
+/// app.insert_resource(DbgKeybinds {
+///     cycle_draw_mode_1: Some(KeyCode::Backquote),
+///     toggle_radius: None,
+///     ..default()
+/// });
+/// ```
+#[derive(Resource, Reflect, Debug, Clone, Copy)]
+#[reflect(Resource)]
+pub struct DbgKeybinds {
+    /// Handle the keys at all.
+    pub enabled: bool,
+    pub cycle_draw_mode_1: Option<KeyCode>,
+    pub cycle_draw_mode_2: Option<KeyCode>,
+    pub toggle_grid: Option<KeyCode>,
+    pub toggle_spatial_grid: Option<KeyCode>,
+    pub toggle_radius: Option<KeyCode>,
+    pub toggle_velocities: Option<KeyCode>,
+    pub toggle_neighbor_links: Option<KeyCode>,
+    pub toggle_flowfield_gizmos: Option<KeyCode>,
+}
+
+impl Default for DbgKeybinds {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cycle_draw_mode_1: Some(KeyCode::F1),
+            toggle_spatial_grid: Some(KeyCode::F2),
+            cycle_draw_mode_2: Some(KeyCode::F3),
+            toggle_grid: Some(KeyCode::F4),
+            toggle_radius: Some(KeyCode::F5),
+            toggle_velocities: Some(KeyCode::F6),
+            toggle_neighbor_links: Some(KeyCode::F7),
+            toggle_flowfield_gizmos: Some(KeyCode::F8),
+        }
+    }
+}
+
+impl DbgKeybinds {
+    /// No debug keys are handled, e.g. when the game uses the function keys itself.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..default()
+        }
+    }
+}
+
+fn handle_dbg_keybinds(
+    mut cmds: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<DbgKeybinds>,
+    mut dbg: ResMut<DbgOptions>,
+    mut q_txt: Query<(&mut Text, &DrawTxt)>,
+) {
+    let pressed = |key: Option<KeyCode>| key.is_some_and(|key| input.just_pressed(key));
+
+    let mut changed = false;
+    let mut toggle = |key: Option<KeyCode>, flag: &mut bool| {
+        if pressed(key) {
+            *flag = !*flag;
+            changed = true;
+        }
+    };
+
+    let options = dbg.bypass_change_detection();
+    toggle(keybinds.toggle_grid, &mut options.draw_grid);
+    toggle(keybinds.toggle_spatial_grid, &mut options.draw_spatial_grid);
+    toggle(keybinds.toggle_radius, &mut options.draw_radius);
+    toggle(keybinds.toggle_velocities, &mut options.draw_velocities);
+    toggle(
+        keybinds.toggle_neighbor_links,
+        &mut options.draw_neighbor_links,
+    );
+    toggle(
+        keybinds.toggle_flowfield_gizmos,
+        &mut options.flowfield_gizmos,
+    );
+
+    let mut mode_changed = false;
+    if pressed(keybinds.cycle_draw_mode_1) {
+        options.draw_mode_1 = options.draw_mode_1.next();
+        mode_changed = true;
+    }
+    if pressed(keybinds.cycle_draw_mode_2) {
+        options.draw_mode_2 = options.draw_mode_2.next();
+        mode_changed = true;
+    }
+
+    if !changed && !mode_changed {
+        return;
+    }
+
+    // keep the debug menu in sync with the keys
+    for (mut txt, txt_type) in q_txt.iter_mut() {
+        txt.0 = match txt_type {
+            DrawTxt::Grid => format!("Grid: {}", dbg.draw_grid),
+            DrawTxt::SpatialGrid => format!("Spatial Grid: {}", dbg.draw_spatial_grid),
+            DrawTxt::Radius => format!("Radius: {}", dbg.draw_radius),
+        };
+    }
+
+    if mode_changed {
+        cmds.trigger(UpdateDropdownOptionEv);
+    }
+
+    dbg.set_changed();
+    cmds.trigger(DrawAllEv);
+}
@@ -1,20 +1,42 @@
 use bevy::prelude::*;
 
 use draw::DrawPlugin;
+#[cfg(feature = "egui")]
+use egui::EguiPanelPlugin;
+use keybinds::KeybindsPlugin;
 use resources::ResourcesPlugin;
 use shader::ShaderPlugin;
+#[cfg(feature = "ron")]
+use tuning::TuningPlugin;
 use ui::UiPlugin;
 
 mod components;
 pub mod draw;
+#[cfg(feature = "egui")]
+pub mod egui;
+pub mod keybinds;
 pub mod resources;
 mod shader;
+#[cfg(feature = "ron")]
+mod tuning;
 mod ui;
 
 pub struct DebugPlugin;
 
 impl Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((DrawPlugin, UiPlugin, ResourcesPlugin, ShaderPlugin));
+        app.add_plugins((
+            DrawPlugin,
+            UiPlugin,
+            ResourcesPlugin,
+            ShaderPlugin,
+            KeybindsPlugin,
+        ));
+
+        #[cfg(feature = "egui")]
+        app.add_plugins(EguiPanelPlugin);
+
+        #[cfg(feature = "ron")]
+        app.add_plugins(TuningPlugin);
     }
 }
@@ -1,7 +1,7 @@
 use bevy::{image::*, prelude::*, render::render_resource::*};
 use image::ImageFormat;
 
-use crate::components::{FvoAgent, FvoSettings};
+use crate::components::{ArrivalCurve, FvoAgent, FvoSettings, FvoUpdaterSync};
 
 const DBG_ICON: &[u8] = include_bytes!("../../assets/imgs/dbg_icon.png");
 
@@ -11,6 +11,8 @@ impl Plugin for ResourcesPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<DbgOptions>()
             .init_resource::<DbgIcon>()
+            .init_resource::<IntegrationHeatmap>()
+            .init_resource::<AgentDensity>()
             .register_type::<DbgOptions>()
             .add_systems(PreStartup, init_fvo_updater)
             .add_systems(Startup, load_dbg_icon)
@@ -21,17 +23,58 @@ impl Plugin for ResourcesPlugin {
 #[derive(Resource, Default)]
 pub struct DbgIcon(pub Handle<Image>);
 
+/// The integration field of the active debug flowfield as a color gradient, one pixel per cell with row `0` at the top.
+/// Cheap paths are green, expensive ones red, unreachable cells black and walls gray. Rewritten whenever the debug
+/// fields are redrawn, whether or not [`DrawMode::Heatmap`] is shown, so it can also be displayed in your own UI.
+///
+/// # Example
+///
+/// ```
+/// fn show_heatmap(mut cmds: Commands, heatmap: Res<IntegrationHeatmap>) {
+///     cmds.spawn(ImageNode::new(heatmap.0.clone()));
+/// }
+/// ```
+#[derive(Resource, Default)]
+pub struct IntegrationHeatmap(pub Handle<Image>);
+
+/// Agents per grid cell, counted from the positions in the [`SpatialHash`](crate::spatial_hash::SpatialHash) every
+/// frame while [`DrawMode::Density`] is shown. `average` smooths the counts over about a second, so choke points that
+/// stay crowded stand out from agents just passing through.
+#[derive(Resource, Default, Debug, Clone)]
+pub struct AgentDensity {
+    pub size: IVec2,
+    /// Agents in every cell this frame, row-major.
+    pub counts: Vec<u32>,
+    /// Smoothed agent count of every cell, row-major.
+    pub average: Vec<f32>,
+    /// The smoothed density as a color gradient, one pixel per cell, transparent where no agent has been.
+    pub image: Handle<Image>,
+}
+
 #[derive(Reflect, Resource, Clone, Copy)]
 #[reflect(Resource)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
 pub struct DbgOptions {
+    #[cfg_attr(feature = "serialize", serde(skip))]
     pub fvo_settings: FvoSettings,
     pub draw_grid: bool,
     pub draw_spatial_grid: bool,
     pub draw_spatial_hashing_grid: bool,
     pub draw_radius: bool,
+    /// Draws the solved velocity (blue) and the preferred flow velocity (green) of every agent as arrows, scaled to
+    /// the distance covered in a tenth of a second.
+    pub draw_velocities: bool,
+    /// Draws a line from every agent to each neighbor it built an avoidance constraint for, to check that
+    /// `sensor_range` and the bucket search cover the expected agents.
+    pub draw_neighbor_links: bool,
+    /// Draws [`DrawMode::FlowField`] with gizmo arrows instead of textured markers. Needs no textures, reads from any
+    /// camera angle and large grids are thinned out to every n-th cell.
+    pub flowfield_gizmos: bool,
     pub draw_mode_1: DrawMode,
     pub draw_mode_2: DrawMode,
     pub hide: bool,
+    #[cfg_attr(feature = "serialize", serde(skip))]
     pub hover: bool,
     pub print_statements: bool,
 }
@@ -44,6 +87,9 @@ impl Default for DbgOptions {
             draw_spatial_grid: false,
             draw_spatial_hashing_grid: false,
             draw_radius: false,
+            draw_velocities: false,
+            draw_neighbor_links: false,
+            flowfield_gizmos: false,
             draw_mode_1: DrawMode::FlowField,
             draw_mode_2: DrawMode::None,
             hide: false,
@@ -60,6 +106,8 @@ impl DbgOptions {
             DrawMode::CostField => String::from("CostField"),
             DrawMode::FlowField => String::from("FlowField"),
             DrawMode::IntegrationField => String::from("IntegrationField"),
+            DrawMode::Heatmap => String::from("Heatmap"),
+            DrawMode::Density => String::from("Density"),
             DrawMode::Index => String::from("Index"),
         }
     }
@@ -88,21 +136,41 @@ impl DbgOptions {
 }
 
 #[derive(Reflect, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub enum DrawMode {
     None,
     CostField,
     FlowField,
     IntegrationField,
+    /// The [`IntegrationHeatmap`] laid over the grid.
+    Heatmap,
+    /// Agents per cell, see [`AgentDensity`].
+    Density,
     Index,
 }
 
 impl DrawMode {
+    /// The mode after this one in the draw mode dropdown, wrapping around to [`DrawMode::None`].
+    pub fn next(self) -> Self {
+        match self {
+            DrawMode::None => DrawMode::IntegrationField,
+            DrawMode::IntegrationField => DrawMode::Heatmap,
+            DrawMode::Heatmap => DrawMode::Density,
+            DrawMode::Density => DrawMode::FlowField,
+            DrawMode::FlowField => DrawMode::CostField,
+            DrawMode::CostField => DrawMode::Index,
+            DrawMode::Index => DrawMode::None,
+        }
+    }
+
     pub fn cast(mode: String) -> Self {
         match mode.as_str() {
             "None" => DrawMode::None,
             "CostField" => DrawMode::CostField,
             "FlowField" => DrawMode::FlowField,
             "IntegrationField" => DrawMode::IntegrationField,
+            "Heatmap" => DrawMode::Heatmap,
+            "Density" => DrawMode::Density,
             "Index" => DrawMode::Index,
             _ => DrawMode::None,
         }
@@ -115,9 +183,14 @@ pub struct FvoUpdater {
     pub preferred_speed: f32,
     pub max_speed: f32,
     pub max_accel: f32,
-    pub horizon: f32,
+    pub time_horizon_agents: f32,
+    pub time_horizon_obstacles: f32,
     pub radius: f32,
     pub sensor_range: f32,
+    pub arrival_radius: f32,
+    pub arrival_curve: ArrivalCurve,
+    pub steering_smoothing: f32,
+    pub velocity_smoothing_time: f32,
 }
 
 impl Default for FvoUpdater {
@@ -126,9 +199,14 @@ impl Default for FvoUpdater {
             preferred_speed: 50.0,
             max_speed: 60.0,
             max_accel: 100.0,
-            horizon: 3.0,
+            time_horizon_agents: 3.0,
+            time_horizon_obstacles: 3.0,
             radius: 2.5,
             sensor_range: 8.0,
+            arrival_radius: 16.0,
+            arrival_curve: ArrivalCurve::Linear,
+            steering_smoothing: 0.0,
+            velocity_smoothing_time: 0.0,
         }
     }
 }
@@ -138,7 +216,7 @@ impl FvoUpdater {
         preferred_speed: f32,
         max_speed: f32,
         max_accel: f32,
-        horizon: f32,
+        time_horizon_agents: f32,
         radius: f32,
         sensor_range: f32,
     ) -> Self {
@@ -146,9 +224,29 @@ impl FvoUpdater {
             preferred_speed,
             max_speed,
             max_accel,
-            horizon,
+            time_horizon_agents,
             radius,
             sensor_range,
+            arrival_radius: sensor_range * 2.0,
+            ..default()
+        }
+    }
+}
+
+impl From<FvoSettings> for FvoUpdater {
+    fn from(settings: FvoSettings) -> Self {
+        Self {
+            preferred_speed: settings.preferred_speed,
+            max_speed: settings.max_speed,
+            max_accel: settings.max_accel,
+            time_horizon_agents: settings.time_horizon_agents,
+            time_horizon_obstacles: settings.time_horizon_obstacles,
+            radius: settings.radius,
+            sensor_range: settings.sensor_range,
+            arrival_radius: settings.arrival.slow_radius_for(settings.sensor_range),
+            arrival_curve: settings.arrival.curve,
+            steering_smoothing: settings.steering_smoothing,
+            velocity_smoothing_time: settings.velocity_smoothing_time,
         }
     }
 }
@@ -159,14 +257,27 @@ fn init_fvo_updater(mut cmds: Commands, fvo_updater: Option<Res<FvoUpdater>>) {
     }
 }
 
-fn update_fvo(mut q_agents: Query<&mut FvoAgent>, fvo_updater: Res<FvoUpdater>) {
-    for mut agent in q_agents.iter_mut() {
+// only agents marked with `FvoUpdaterSync` follow the panel, everything else keeps its own settings
+fn update_fvo(
+    mut q_agents: Query<(&mut FvoAgent, Ref<FvoUpdaterSync>)>,
+    fvo_updater: Res<FvoUpdater>,
+) {
+    for (mut agent, sync) in q_agents.iter_mut() {
+        if !fvo_updater.is_changed() && !sync.is_added() {
+            continue;
+        }
+
         agent.settings.preferred_speed = fvo_updater.preferred_speed;
         agent.settings.max_speed = fvo_updater.max_speed;
         agent.settings.max_accel = fvo_updater.max_accel;
-        agent.settings.horizon = fvo_updater.horizon;
+        agent.settings.time_horizon_agents = fvo_updater.time_horizon_agents;
+        agent.settings.time_horizon_obstacles = fvo_updater.time_horizon_obstacles;
         agent.settings.radius = fvo_updater.radius;
         agent.settings.sensor_range = fvo_updater.sensor_range;
+        agent.settings.arrival.slow_radius = Some(fvo_updater.arrival_radius);
+        agent.settings.arrival.curve = fvo_updater.arrival_curve;
+        agent.settings.steering_smoothing = fvo_updater.steering_smoothing;
+        agent.settings.velocity_smoothing_time = fvo_updater.velocity_smoothing_time;
     }
 }
 
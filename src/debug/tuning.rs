@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+
+use super::components::*;
+use super::resources::*;
+use crate::{
+    events::{DrawAllEv, FvoTuningAppliedEv},
+    tuning::FvoTuning,
+};
+
+pub struct TuningPlugin;
+
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(apply_dbg_tuning);
+    }
+}
+
+// the debug half of a tuning file: the panel values and the debug flags
+fn apply_dbg_tuning(
+    trigger: On<FvoTuningAppliedEv>,
+    mut cmds: Commands,
+    tunings: Res<Assets<FvoTuning>>,
+    mut fvo_updater: ResMut<FvoUpdater>,
+    mut dbg: ResMut<DbgOptions>,
+    mut q_txt: Query<(&mut Text, &DrawTxt)>,
+) {
+    let Some(tuning) = tunings.get(trigger.event().0) else {
+        return;
+    };
+
+    // agents with `FvoUpdaterSync` already got the full settings, this only keeps the panel in sync
+    *fvo_updater = FvoUpdater::from(tuning.settings);
+
+    let Some(options) = tuning.debug else {
+        return;
+    };
+
+    *dbg = DbgOptions {
+        fvo_settings: tuning.settings,
+        hover: dbg.hover,
+        ..options
+    };
+
+    // keep the debug menu in sync with the file
+    for (mut txt, txt_type) in q_txt.iter_mut() {
+        txt.0 = match txt_type {
+            DrawTxt::Grid => format!("Grid: {}", dbg.draw_grid),
+            DrawTxt::SpatialGrid => format!("Spatial Grid: {}", dbg.draw_spatial_grid),
+            DrawTxt::Radius => format!("Radius: {}", dbg.draw_radius),
+        };
+    }
+
+    cmds.trigger(UpdateDropdownOptionEv);
+    cmds.trigger(DrawAllEv);
+}
@@ -125,7 +125,10 @@ enum FvoOption {
     MaxSpeed,
     MaxAccel,
     Horizon,
+    ObstacleHorizon,
     SensorRange,
+    ArrivalRadius,
+    Smoothing,
     Radius,
 }
 
@@ -485,7 +488,7 @@ fn draw_ui_box(
             Node {
                 margin,
                 padding: UiRect::all(Val::Px(5.0)),
-                border: border,
+                border,
                 ..default()
             },
             Name::new("Draw Grid Button"),
@@ -649,16 +652,34 @@ fn draw_ui_box(
         ),
         (
             "Horizon",
-            fvo_updater.horizon,
+            fvo_updater.time_horizon_agents,
             FvoOption::Horizon,
             None,
         ),
+        (
+            "Obstacle Horizon",
+            fvo_updater.time_horizon_obstacles,
+            FvoOption::ObstacleHorizon,
+            None,
+        ),
         (
             "Sensor Range",
             fvo_updater.sensor_range,
             FvoOption::SensorRange,
             None,
         ),
+        (
+            "Arrival Radius",
+            fvo_updater.arrival_radius,
+            FvoOption::ArrivalRadius,
+            None,
+        ),
+        (
+            "Smoothing",
+            fvo_updater.steering_smoothing,
+            FvoOption::Smoothing,
+            None,
+        ),
         (
             "Radius",
             fvo_updater.radius,
@@ -786,6 +807,16 @@ fn draw_ui_box(
                 .with_children(|btn| {
                     btn.spawn(option_txt("> IntegrationField".to_string()));
                 });
+            options
+                .spawn(btn_option(OptionsSet::One, "Heatmap".to_string(), None))
+                .with_children(|btn| {
+                    btn.spawn(option_txt("> Heatmap".to_string()));
+                });
+            options
+                .spawn(btn_option(OptionsSet::One, "Density".to_string(), None))
+                .with_children(|btn| {
+                    btn.spawn(option_txt("> Density".to_string()));
+                });
             options
                 .spawn(btn_option(OptionsSet::One, "FlowField".to_string(), None))
                 .with_children(|btn| {
@@ -836,6 +867,16 @@ fn draw_ui_box(
                 .with_children(|btn| {
                     btn.spawn(option_txt("> IntegrationField".to_string()));
                 });
+            options
+                .spawn(btn_option(OptionsSet::Two, "Heatmap".to_string(), None))
+                .with_children(|btn| {
+                    btn.spawn(option_txt("> Heatmap".to_string()));
+                });
+            options
+                .spawn(btn_option(OptionsSet::Two, "Density".to_string(), None))
+                .with_children(|btn| {
+                    btn.spawn(option_txt("> Density".to_string()));
+                });
             options
                 .spawn(btn_option(OptionsSet::Two, "FlowField".to_string(), None))
                 .with_children(|btn| {
@@ -926,15 +967,20 @@ fn handle_slider_arrow_interaction(
                         FvoOption::PreferredSpeed => fvo_updater.preferred_speed,
                         FvoOption::MaxSpeed => fvo_updater.max_speed,
                         FvoOption::MaxAccel => fvo_updater.max_accel,
-                        FvoOption::Horizon => fvo_updater.horizon,
+                        FvoOption::Horizon => fvo_updater.time_horizon_agents,
+                        FvoOption::ObstacleHorizon => fvo_updater.time_horizon_obstacles,
                         FvoOption::SensorRange => fvo_updater.sensor_range,
+                        FvoOption::ArrivalRadius => fvo_updater.arrival_radius,
+                        FvoOption::Smoothing => fvo_updater.steering_smoothing,
                         FvoOption::Radius => fvo_updater.radius,
                     };
 
                     let step = match info {
                         FvoOption::PreferredSpeed | FvoOption::MaxSpeed => 1.0,
                         FvoOption::MaxAccel => 5.0,
-                        FvoOption::Horizon => 0.1,
+                        FvoOption::ArrivalRadius => 1.0,
+                        FvoOption::Smoothing => 0.1,
+                        FvoOption::Horizon | FvoOption::ObstacleHorizon => 0.1,
                         FvoOption::SensorRange | FvoOption::Radius => 0.1,
                     };
 
@@ -950,8 +996,13 @@ fn handle_slider_arrow_interaction(
                         FvoOption::PreferredSpeed => fvo_updater.preferred_speed = val,
                         FvoOption::MaxSpeed => fvo_updater.max_speed = val,
                         FvoOption::MaxAccel => fvo_updater.max_accel = val,
-                        FvoOption::Horizon => fvo_updater.horizon = val.max(0.1),
+                        FvoOption::Horizon => fvo_updater.time_horizon_agents = val.max(0.1),
+                        FvoOption::ObstacleHorizon => {
+                            fvo_updater.time_horizon_obstacles = val.max(0.1)
+                        }
                         FvoOption::SensorRange => fvo_updater.sensor_range = val,
+                        FvoOption::ArrivalRadius => fvo_updater.arrival_radius = val,
+                        FvoOption::Smoothing => fvo_updater.steering_smoothing = val.min(0.95),
                         FvoOption::Radius => fvo_updater.radius = val,
                     }
                     txt.0 = format!("{:.1}", val);
@@ -998,8 +1049,11 @@ fn slider_drag_start_end(
                         FvoOption::PreferredSpeed => fvo_updater.preferred_speed,
                         FvoOption::MaxSpeed => fvo_updater.max_speed,
                         FvoOption::MaxAccel => fvo_updater.max_accel,
-                        FvoOption::Horizon => fvo_updater.horizon,
+                        FvoOption::Horizon => fvo_updater.time_horizon_agents,
+                        FvoOption::ObstacleHorizon => fvo_updater.time_horizon_obstacles,
                         FvoOption::SensorRange => fvo_updater.sensor_range,
+                        FvoOption::ArrivalRadius => fvo_updater.arrival_radius,
+                        FvoOption::Smoothing => fvo_updater.steering_smoothing,
                         FvoOption::Radius => fvo_updater.radius,
                     };
                     cmds.insert_resource(DragState {
@@ -1039,8 +1093,11 @@ fn slider_drag_update(
         FvoOption::PreferredSpeed => fvo_updater.preferred_speed = new_val.max(0.0),
         FvoOption::MaxSpeed => fvo_updater.max_speed = new_val.max(0.0),
         FvoOption::MaxAccel => fvo_updater.max_accel = new_val.max(0.0),
-        FvoOption::Horizon => fvo_updater.horizon = new_val.max(0.1),
+        FvoOption::Horizon => fvo_updater.time_horizon_agents = new_val.max(0.1),
+        FvoOption::ObstacleHorizon => fvo_updater.time_horizon_obstacles = new_val.max(0.1),
         FvoOption::SensorRange => fvo_updater.sensor_range = new_val.max(0.0),
+        FvoOption::ArrivalRadius => fvo_updater.arrival_radius = new_val.max(0.0),
+        FvoOption::Smoothing => fvo_updater.steering_smoothing = new_val.clamp(0.0, 0.95),
         FvoOption::Radius => fvo_updater.radius = new_val.max(0.0),
     }
 
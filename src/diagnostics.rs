@@ -0,0 +1,87 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+};
+use std::time::Duration;
+
+/// Publishes [`FvoDiagnostics`] to Bevy's `DiagnosticsStore`, e.g. to show them with the `LogDiagnosticsPlugin`. The
+/// solver only collects the numbers while this plugin, or the resource alone, is added.
+///
+/// # Example
+///
+/// ```
+/// app.add_plugins((BevyPathfindingPlugin, FvoDiagnosticsPlugin, LogDiagnosticsPlugin::default()));
+/// ```
+pub struct FvoDiagnosticsPlugin;
+
+impl Plugin for FvoDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FvoDiagnostics>()
+            .register_type::<FvoDiagnostics>()
+            .register_diagnostic(Diagnostic::new(FvoDiagnostics::BUCKET_BUILD).with_suffix("ms"))
+            .register_diagnostic(Diagnostic::new(FvoDiagnostics::NEIGHBOR_GATHER).with_suffix("ms"))
+            .register_diagnostic(
+                Diagnostic::new(FvoDiagnostics::CONSTRAINT_BUILD).with_suffix("ms"),
+            )
+            .register_diagnostic(Diagnostic::new(FvoDiagnostics::SOLVE).with_suffix("ms"))
+            .register_diagnostic(Diagnostic::new(FvoDiagnostics::AGENTS))
+            .register_diagnostic(Diagnostic::new(FvoDiagnostics::NEIGHBORS_AVG))
+            .register_diagnostic(Diagnostic::new(FvoDiagnostics::NEIGHBORS_MAX))
+            .register_diagnostic(Diagnostic::new(FvoDiagnostics::CONSTRAINTS))
+            .register_diagnostic(Diagnostic::new(FvoDiagnostics::DEFERRED))
+            .add_systems(Last, publish_fvo_diagnostics);
+    }
+}
+
+/// Timings and counts of the last solver tick. Filled by the [`FvoPlugin`](crate::fvo::FvoPlugin) while the resource
+/// exists.
+///
+/// The neighbor gather, constraint build and solve run per agent on all threads, their timings are the time summed
+/// over every agent and can exceed the frame time.
+#[derive(Resource, Reflect, Debug, Default, Clone, Copy)]
+#[reflect(Resource)]
+pub struct FvoDiagnostics {
+    /// Filing moved agents into the [`SpatialHash`](crate::spatial_hash::SpatialHash). Kept from the last rebuild
+    /// while [`FvoConfig::rebuild_interval`](crate::fvo::FvoConfig::rebuild_interval) skips it.
+    pub bucket_build: Duration,
+    pub neighbor_gather: Duration,
+    pub constraint_build: Duration,
+    /// Linear program, fallback sampling and acceleration limits.
+    pub solve: Duration,
+    /// Agents solved.
+    pub agents: usize,
+    pub neighbors_avg: f32,
+    pub neighbors_max: usize,
+    /// Constraints built over every agent.
+    pub constraints: usize,
+    /// Agents left for the next tick by [`FvoConfig::solve_budget`](crate::fvo::FvoConfig::solve_budget).
+    pub deferred: usize,
+}
+
+impl FvoDiagnostics {
+    pub const BUCKET_BUILD: DiagnosticPath = DiagnosticPath::const_new("fvo/bucket_build");
+    pub const NEIGHBOR_GATHER: DiagnosticPath = DiagnosticPath::const_new("fvo/neighbor_gather");
+    pub const CONSTRAINT_BUILD: DiagnosticPath = DiagnosticPath::const_new("fvo/constraint_build");
+    pub const SOLVE: DiagnosticPath = DiagnosticPath::const_new("fvo/solve");
+    pub const AGENTS: DiagnosticPath = DiagnosticPath::const_new("fvo/agents");
+    pub const NEIGHBORS_AVG: DiagnosticPath = DiagnosticPath::const_new("fvo/neighbors_avg");
+    pub const NEIGHBORS_MAX: DiagnosticPath = DiagnosticPath::const_new("fvo/neighbors_max");
+    pub const CONSTRAINTS: DiagnosticPath = DiagnosticPath::const_new("fvo/constraints");
+    pub const DEFERRED: DiagnosticPath = DiagnosticPath::const_new("fvo/deferred");
+}
+
+fn publish_fvo_diagnostics(fvo: Res<FvoDiagnostics>, mut diagnostics: Diagnostics) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+    diagnostics.add_measurement(&FvoDiagnostics::BUCKET_BUILD, || ms(fvo.bucket_build));
+    diagnostics.add_measurement(&FvoDiagnostics::NEIGHBOR_GATHER, || ms(fvo.neighbor_gather));
+    diagnostics.add_measurement(&FvoDiagnostics::CONSTRAINT_BUILD, || {
+        ms(fvo.constraint_build)
+    });
+    diagnostics.add_measurement(&FvoDiagnostics::SOLVE, || ms(fvo.solve));
+    diagnostics.add_measurement(&FvoDiagnostics::AGENTS, || fvo.agents as f64);
+    diagnostics.add_measurement(&FvoDiagnostics::NEIGHBORS_AVG, || fvo.neighbors_avg as f64);
+    diagnostics.add_measurement(&FvoDiagnostics::NEIGHBORS_MAX, || fvo.neighbors_max as f64);
+    diagnostics.add_measurement(&FvoDiagnostics::CONSTRAINTS, || fvo.constraints as f64);
+    diagnostics.add_measurement(&FvoDiagnostics::DEFERRED, || fvo.deferred as f64);
+}
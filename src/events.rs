@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::flowfield::FlowField;
+use crate::{components::AgentState, flowfield::FlowField};
 
 /// Event to initialize the flowfield. This event is used to set the destination position for the flowfield and the entities that will be affected by it.
 ///
@@ -57,6 +57,7 @@ use crate::flowfield::FlowField;
 ///     cmds.trigger(InitializeFlowFieldEv {
 ///         entities: units,
 ///         destination_pos,
+///         extra_destinations: Vec::new(),
 ///     });
 /// }
 /// ```
@@ -64,6 +65,143 @@ use crate::flowfield::FlowField;
 pub struct InitializeFlowFieldEv {
     pub entities: Vec<Entity>,
     pub destination_pos: Vec3,
+    /// Further goals, e.g. every dropoff point. Each unit flows to whichever goal is cheapest to reach from where it
+    /// stands, all from a single flowfield.
+    pub extra_destinations: Vec<Vec3>,
+}
+
+/// Moves an existing flowfield to a new destination. The entity and its units stay, units that already arrived
+/// get their `Destination` back. With [`FlowFieldConfig::async_compute`] units keep following the old goal until the
+/// new field is swapped in and a [`FlowFieldReady`] is triggered.
+///
+/// # Example
+///
+/// ```
+/// fn follow_leader(mut cmds: Commands, q_leader: Query<(&Transform, &Squad), Changed<Transform>>) {
+///     for (tf, squad) in q_leader.iter() {
+///         cmds.trigger(RetargetFlowFieldEv {
+///             flowfield: squad.flowfield,
+///             destination_pos: tf.translation,
+///             extra_destinations: Vec::new(),
+///         });
+///     }
+/// }
+/// ```
+///
+/// [`FlowFieldConfig::async_compute`]: crate::flowfield::FlowFieldConfig::async_compute
+#[derive(Event)]
+pub struct RetargetFlowFieldEv {
+    pub flowfield: Entity,
+    pub destination_pos: Vec3,
+    /// Further goals, see [`InitializeFlowFieldEv::extra_destinations`].
+    pub extra_destinations: Vec<Vec3>,
+}
+
+/// Orders `units` to `destination`, the everyday RTS move command. The flowfield already heading for that cell is
+/// reused (see [`FlowFieldCache`](crate::cache::FlowFieldCache)) or a new one is built, and the units leave the
+/// flowfields they followed before. Units on different [`NavLayer`](crate::components::NavLayer)s get a flowfield
+/// per layer, so boats and ground units ordered together both find a way. Entities without an
+/// [`FvoAgent`](crate::components::FvoAgent) are ignored. Trigger an [`InitializeFlowFieldEv`] instead for several
+/// goals at once.
+///
+/// # Example
+///
+/// ```
+/// fn move_selection(mut cmds: Commands, q_selected: Query<Entity, With<Selected>>) {
+///     cmds.trigger(MoveOrderEvent {
+///         units: q_selected.iter().collect(),
+///         destination: Vec3::new(80.0, 0.0, 25.0),
+///     });
+/// }
+/// ```
+#[derive(Event, Debug, Clone)]
+pub struct MoveOrderEvent {
+    pub units: Vec<Entity>,
+    pub destination: Vec3,
+}
+
+/// Triggered by the solver when two agents overlap, i.e. they hit the "already colliding" branch of the constraint
+/// builder. Sent once per overlapping pair and frame. Observe it for crush damage, impact sounds or analytics.
+///
+/// # Example
+///
+/// ```
+/// app.add_observer(|trigger: On<AgentCollisionEvent>| {
+///     let ev = trigger.event();
+///     info!("{} hit {} ({:.2} deep)", ev.a, ev.b, ev.penetration);
+/// });
+/// ```
+#[derive(Event, Debug, Clone, Copy)]
+pub struct AgentCollisionEvent {
+    pub a: Entity,
+    pub b: Entity,
+    /// Overlap of the two agents' radii in world units.
+    pub penetration: f32,
+}
+
+/// Triggered after a solve changed the [`AgentState`] of an agent.
+///
+/// # Example
+///
+/// ```
+/// app.add_observer(|trigger: On<AgentStateChangedEvent>| {
+///     let ev = trigger.event();
+///     if ev.to == AgentState::Blocked {
+///         info!("{} is stuck, was {:?}", ev.agent, ev.from);
+///     }
+/// });
+/// ```
+#[derive(Event, Debug, Clone, Copy)]
+pub struct AgentStateChangedEvent {
+    pub agent: Entity,
+    pub from: AgentState,
+    pub to: AgentState,
+}
+
+/// Triggered when an agent with a [`StuckDetection`](crate::stuck::StuckDetection) covered far less distance than its
+/// preferred velocity asked for.
+///
+/// # Example
+///
+/// ```
+/// app.add_observer(|trigger: On<AgentStuckEvent>| {
+///     let ev = trigger.event();
+///     warn!("{} moved {:.1} of {:.1}", ev.agent, ev.displacement, ev.expected);
+/// });
+/// ```
+#[derive(Event, Debug, Clone, Copy)]
+pub struct AgentStuckEvent {
+    pub agent: Entity,
+    /// Distance covered on the XZ plane during the last window.
+    pub displacement: f32,
+    /// Distance the preferred velocity asked for during the same window.
+    pub expected: f32,
+}
+
+/// Triggered once per unit when it comes within [`FlowField::arrival_radius`] of one of the flowfield's destination
+/// cells. With [`FlowField::remove_arrived`] set, the unit is also dropped from the flowfield.
+///
+/// # Example
+///
+/// ```
+/// app.add_observer(|trigger: On<AgentArrivedEvent>| {
+///     let ev = trigger.event();
+///     info!("{} reached the goal of {}", ev.agent, ev.flowfield);
+/// });
+/// ```
+#[derive(Event, Debug, Clone, Copy)]
+pub struct AgentArrivedEvent {
+    pub agent: Entity,
+    pub flowfield: Entity,
+}
+
+/// Triggered when a flowfield finished building and was swapped in, both for new flowfields and rebuilds after
+/// [`UpdateCostEv`]. With [`FlowFieldConfig::async_compute`] this happens a few frames after the request.
+///
+/// [`FlowFieldConfig::async_compute`]: crate::flowfield::FlowFieldConfig::async_compute
+#[derive(Event, Debug, Clone, Copy)]
+pub struct FlowFieldReady {
+    pub flowfield: Entity,
 }
 
 #[derive(Event)]
@@ -72,9 +210,99 @@ pub struct SetActiveFlowfieldEv(pub Option<FlowField>);
 #[derive(Event)]
 pub struct DrawCostFieldEv;
 
+/// Rebuilds every flowfield from the current cost field. Prefer [`CostFieldChangedEv`] for local edits.
 #[derive(Event)]
 pub struct UpdateCostEv;
 
+/// Replaces the [`Grid`] with an empty one of `size` cells of `cell_size` each, e.g. for procedurally generated or
+/// streamed maps. Every `Obstacle` is stamped into the new grid again and every flowfield is rebuilt for the same
+/// destinations, see [`Grid::rebuild`].
+///
+/// # Example
+///
+/// ```
+/// fn load_level(mut cmds: Commands, level: Res<Level>) {
+///     cmds.trigger(RebuildGridEv {
+///         size: level.size,
+///         cell_size: Vec2::splat(level.cell_size),
+///     });
+/// }
+/// ```
+///
+/// [`Grid`]: crate::grid::Grid
+/// [`Grid::rebuild`]: crate::grid::Grid::rebuild
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RebuildGridEv {
+    pub size: IVec2,
+    pub cell_size: Vec2,
+}
+
+/// Trigger after editing the cost field with [`Grid::set_cost`] or [`Grid::add_cost_region`]. Marks the region dirty
+/// on every flowfield, only it and the paths running through it are re-integrated, once per frame no matter how many
+/// edits were made. Also triggered when an `Obstacle` is added or removed and when the congestion cost changes.
+///
+/// # Example
+///
+/// ```
+/// fn set_fire(mut cmds: Commands, mut grid: ResMut<Grid>) {
+///     let region = IRect::new(10, 10, 14, 14);
+///     grid.add_cost_region(region, 50);
+///     cmds.trigger(CostFieldChangedEv { region });
+/// }
+/// ```
+///
+/// [`Grid::set_cost`]: crate::grid::Grid::set_cost
+/// [`Grid::add_cost_region`]: crate::grid::Grid::add_cost_region
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CostFieldChangedEv {
+    /// Cell indices that were edited.
+    pub region: IRect,
+}
+
+/// Field written by an [`ExportFieldEvent`].
+#[cfg(feature = "debug")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportField {
+    /// Cost of every cell of the [`Grid`](crate::grid::Grid) in grayscale, from white for open ground to dark gray
+    /// for expensive cells. Walls are black.
+    CostField,
+    /// Integration field of the flowfield entity, from green next to the goal to red for the most expensive cell.
+    /// Unreachable cells are black, walls dark gray and goals white.
+    IntegrationField(Entity),
+    /// Direction of every cell of the flowfield entity as a hue, on the grid's local axes: red points east (+X),
+    /// yellow-green north (-Z), cyan west and violet south. Walls are black, cells without a direction gray and goals
+    /// white.
+    FlowField(Entity),
+}
+
+/// Writes a field to a PNG, one pixel per cell with row `0` at the top, e.g. to attach to a bug report. Failures are
+/// logged.
+///
+/// # Example
+///
+/// ```
+/// fn export_on_f12(mut cmds: Commands, input: Res<ButtonInput<KeyCode>>, squad: Res<Squad>) {
+///     if input.just_pressed(KeyCode::F12) {
+///         cmds.trigger(ExportFieldEvent {
+///             mode: ExportField::IntegrationField(squad.flowfield),
+///             path: "integration.png".into(),
+///         });
+///     }
+/// }
+/// ```
+#[cfg(feature = "debug")]
+#[derive(Event, Debug, Clone)]
+pub struct ExportFieldEvent {
+    pub mode: ExportField,
+    pub path: std::path::PathBuf,
+}
+
+/// Triggered after the [`FvoTuningFile`](crate::tuning::FvoTuningFile) was applied, once it is loaded and after every
+/// hot reload.
+#[cfg(feature = "ron")]
+#[derive(Event, Debug, Clone, Copy)]
+pub struct FvoTuningAppliedEv(pub AssetId<crate::tuning::FvoTuning>);
+
 #[derive(Event)]
 pub struct DrawAllEv;
 
@@ -86,3 +314,9 @@ pub struct DrawIntegrationFieldEv;
 
 #[derive(Event)]
 pub struct DrawFlowFieldEv;
+
+#[derive(Event)]
+pub struct DrawHeatmapEv;
+
+#[derive(Event)]
+pub struct DrawDensityEv;
@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+use image::RgbaImage;
+
+use crate::{
+    cell::Cell,
+    events::{ExportField, ExportFieldEvent},
+    flowfield::FlowField,
+    grid::Grid,
+    grid_direction::GridDirection,
+};
+
+/// Writes the requested field to a PNG, see [`ExportFieldEvent`].
+pub fn export_field(trigger: On<ExportFieldEvent>, grid: Res<Grid>, q_ff: Query<&FlowField>) {
+    let ev = trigger.event();
+
+    let pixels = match ev.mode {
+        ExportField::CostField => field_pixels(&grid.grid, cost_color),
+        ExportField::IntegrationField(ff_ent) | ExportField::FlowField(ff_ent) => {
+            let Ok(ff) = q_ff.get(ff_ent) else {
+                warn!("Can't export {:?}, {} has no flowfield", ev.mode, ff_ent);
+                return;
+            };
+
+            let goals: Vec<IVec2> = ff.destination_cells.iter().map(|cell| cell.idx).collect();
+            if matches!(ev.mode, ExportField::FlowField(_)) {
+                field_pixels(&ff.grid, |cell| {
+                    if goals.contains(&cell.idx) {
+                        [255, 255, 255, 255]
+                    } else {
+                        direction_color(cell)
+                    }
+                })
+            } else {
+                // normalize against the most expensive reachable cell
+                let max_cost = ff
+                    .grid
+                    .iter()
+                    .flatten()
+                    .filter(|cell| cell.best_cost != u16::MAX)
+                    .map(|cell| cell.best_cost)
+                    .max()
+                    .unwrap_or(0)
+                    .max(1);
+                field_pixels(&ff.grid, |cell| {
+                    if goals.contains(&cell.idx) {
+                        [255, 255, 255, 255]
+                    } else {
+                        integration_color(cell, max_cost)
+                    }
+                })
+            }
+        }
+    };
+
+    let Some((width, height, data)) = pixels else {
+        warn!("Can't export {:?}, the field is empty", ev.mode);
+        return;
+    };
+
+    let Some(image) = RgbaImage::from_raw(width, height, data) else {
+        error!(
+            "Can't export {:?}, the field rows differ in length",
+            ev.mode
+        );
+        return;
+    };
+
+    if let Err(err) = image.save(&ev.path) {
+        error!(
+            "Failed to export {:?} to {}: {}",
+            ev.mode,
+            ev.path.display(),
+            err
+        );
+    }
+}
+
+// one RGBA pixel per cell, rows top to bottom
+fn field_pixels(
+    cells: &[Vec<Cell>],
+    color: impl Fn(&Cell) -> [u8; 4],
+) -> Option<(u32, u32, Vec<u8>)> {
+    let width = cells.first()?.len();
+    if width == 0 {
+        return None;
+    }
+
+    let data = cells.iter().flatten().flat_map(color).collect();
+    Some((width as u32, cells.len() as u32, data))
+}
+
+fn cost_color(cell: &Cell) -> [u8; 4] {
+    match cell.cost {
+        u8::MAX => [0, 0, 0, 255],
+        cost => {
+            // keep expensive cells distinguishable from walls
+            let shade = 255 - (cost.saturating_sub(1) as f32 * 200.0 / 253.0) as u8;
+            [shade, shade, shade, 255]
+        }
+    }
+}
+
+fn integration_color(cell: &Cell, max_cost: u16) -> [u8; 4] {
+    if cell.cost == u8::MAX {
+        return [60, 60, 60, 255];
+    }
+
+    if cell.best_cost == u16::MAX {
+        return [0, 0, 0, 255];
+    }
+
+    let t = cell.best_cost as f32 / max_cost as f32;
+    Color::hsl(120.0 * (1.0 - t), 1.0, 0.5)
+        .to_srgba()
+        .to_u8_array()
+}
+
+fn direction_color(cell: &Cell) -> [u8; 4] {
+    if cell.cost == u8::MAX {
+        return [0, 0, 0, 255];
+    }
+
+    if cell.best_direction == GridDirection::None {
+        return [128, 128, 128, 255];
+    }
+
+    let hue = cell
+        .best_direction
+        .to_angle()
+        .to_degrees()
+        .rem_euclid(360.0);
+    Color::hsl(hue, 1.0, 0.5).to_srgba().to_u8_array()
+}
@@ -1,36 +1,298 @@
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
 
 use crate::components::*;
 use crate::events::*;
-use crate::{cell::*, grid::Grid, grid_direction::GridDirection, utils};
+#[cfg(feature = "debug")]
+use crate::export::export_field;
+use crate::{
+    cache::FlowFieldCache,
+    cell::*,
+    congestion::{update_congestion, CongestionSettings},
+    fvo::{FvoConfig, OutOfBoundsPolicy},
+    grid::Grid,
+    grid_direction::GridDirection,
+    plane::Plane,
+    preferred::PreferredVelocity,
+    sectors::SectorGraph,
+    smoothing::PathSmoothing,
+    spatial_hash::SpatialHash,
+    utils,
+};
 
 pub struct FlowfieldPlugin;
 
 impl Plugin for FlowfieldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, flowfield_group_stop_system)
+        app.init_resource::<FlowFieldConfig>()
+            .init_resource::<SectorGraph>()
+            .init_resource::<FlowFieldCache>()
+            .register_type::<FlowFieldConfig>()
+            .register_type::<DirectionSampling>()
+            .register_type::<IntegrationMethod>()
+            .register_type::<FlowFieldTarget>()
+            .register_type::<CongestionSettings>()
+            .register_type::<FlowField>()
+            .register_type::<DestinationRadius>()
+            .register_type::<Destination>()
+            .register_type::<GridDirection>()
+            .add_systems(
+                Update,
+                (
+                    update_sector_graph,
+                    follow_flowfield_targets,
+                    poll_flowfield_tasks,
+                    spread_flowfield_goals,
+                    flowfield_group_stop_system,
+                    extend_flowfield_corridors,
+                    update_congestion,
+                    refresh_dirty_flowfields,
+                )
+                    .chain(),
+            )
             .add_observer(update_fields)
-            .add_observer(initialize_flowfield);
+            .add_observer(mark_flowfields_dirty)
+            .add_observer(initialize_flowfield)
+            .add_observer(move_order)
+            .add_observer(retarget_flowfield)
+            .add_observer(spawn_built_flowfield)
+            .add_observer(forget_flowfield)
+            .add_observer(unsettle_on_order);
+
+        #[cfg(feature = "debug")]
+        app.add_observer(export_field);
     }
 }
 
-// TODO: Remove. This is just for visualizing the destination radius. (Dont think this is currently working as intended)
+/// Runtime configuration of the flowfield builder.
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct FlowFieldConfig {
+    /// Build new flowfields and full rebuilds on the [`AsyncComputeTaskPool`] instead of stalling the frame.
+    ///
+    /// Units keep following their previous flowfield until the new one is swapped in and a [`FlowFieldReady`] is
+    /// triggered. The frame that happens in depends on the machine, so turn this off for lockstep multiplayer (see
+    /// [`FvoConfig::deterministic`](crate::fvo::FvoConfig::deterministic)).
+    pub async_compute: bool,
+    /// Only integrate the sectors along a coarse portal route instead of the whole grid, see [`SectorGraph`].
+    /// Worth it from about 256x256 cells. Sectors are added on demand when a unit leaves the corridor.
+    pub hierarchical: bool,
+    /// Edge length of a sector in cells when `hierarchical` is set.
+    pub sector_size: i32,
+    /// Integration method of new flowfields. Change [`FlowField::integration`] to switch a single one, it takes
+    /// effect on its next rebuild.
+    pub integration: IntegrationMethod,
+    /// Let units with a clear straight line to the goal head directly there instead of following the grid, see
+    /// [`Cell::los`]. Off by default, it costs a line walk per cell on every build, cost edits only redo the lines
+    /// running through them.
+    pub line_of_sight: bool,
+    /// Keep new flowfields out of gaps too narrow for the widest unit ordered, see [`FlowField::min_clearance`]. Costs
+    /// a brushfire over the grid on every build and cost edit of those flowfields.
+    pub clearance: bool,
+    /// Upper radius bound of every size class, ascending, e.g. `vec![1.0, 3.0]` for small, medium and large units
+    /// (everything wider than the last bound). When set, an order with units of several classes builds a flowfield
+    /// per class, each walled off by the clearance of the widest agent its class may hold (of the widest unit for
+    /// the last class), and units of a class are only ever put on the flowfields of their class. Empty by default.
+    pub size_classes: Vec<f32>,
+    /// Give every unit of new flowfields its own free cell around the destination, see
+    /// [`FlowField::spread_goals`].
+    pub spread_goals: bool,
+    /// Make crowded cells more expensive so later units reroute around traffic jams. `None` disables it.
+    pub congestion: Option<CongestionSettings>,
+}
+
+impl Default for FlowFieldConfig {
+    fn default() -> Self {
+        Self {
+            async_compute: true,
+            hierarchical: false,
+            sector_size: 16,
+            integration: IntegrationMethod::default(),
+            line_of_sight: false,
+            clearance: false,
+            size_classes: Vec::new(),
+            spread_goals: false,
+            congestion: None,
+        }
+    }
+}
+
+impl FlowFieldConfig {
+    /// Index of the size class of an agent of `radius`, `size_classes.len()` for the widest class, see
+    /// [`FlowFieldConfig::size_classes`].
+    pub fn size_class(&self, radius: f32) -> usize {
+        self.size_classes
+            .iter()
+            .take_while(|&&max_radius| radius > max_radius)
+            .count()
+    }
+}
+
+/// How the integration field of a [`FlowField`] is computed from the cost field.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum IntegrationMethod {
+    /// Dijkstra over the 4-connected grid. Fast, but paths favor the grid axes, open areas are crossed in L-shapes
+    /// and diagonal staircases.
+    #[default]
+    Dijkstra,
+    /// Fast Marching solution of the eikonal equation with the cell cost as slowness. Gives close to straight-line
+    /// distances in every direction, roughly twice as expensive to build. Cost edits re-march the whole field
+    /// instead of just the changed region.
+    FastMarching,
+}
+
+/// How [`FlowField::sample_direction`] reads the flow direction between cell centers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum DirectionSampling {
+    /// Direction of the cell the point lies in. Cheapest, but agents visibly zig-zag along cell boundaries.
+    Nearest,
+    /// Blend of the four surrounding cells, skipping walls, so directions vary smoothly across cell boundaries.
+    #[default]
+    Bilinear,
+}
+
+/// A flowfield being built on the [`AsyncComputeTaskPool`]. Sits on the entity the flowfield is spawned on, or on
+/// the existing flowfield while it is rebuilt.
 #[derive(Component)]
+pub struct FlowFieldTask {
+    task: Task<FlowField>,
+    /// Units to move onto the new flowfield, `None` for a rebuild of an existing one.
+    units: Option<Vec<Entity>>,
+}
+
+/// Makes the flowfield on this entity chase `entity`. Whenever the target's cell drifts more than `retarget_cells`
+/// cells from the destination, a [`RetargetFlowFieldEv`] moves the flowfield after it, at most one rebuild in flight
+/// at a time. The flowfield is kept while the target lives, even once every unit caught up, so units set off again
+/// when it moves. Removed when the target despawns, the flowfield then ends as usual.
+///
+/// # Example
+///
+/// ```
+/// fn attack_boss(
+///     mut cmds: Commands,
+///     mut cache: ResMut<FlowFieldCache>,
+///     grid: Res<Grid>,
+///     q_boss: Query<(Entity, &Transform), With<Boss>>,
+///     q_army: Query<Entity, With<Unit>>,
+/// ) {
+///     let Ok((boss, boss_tf)) = q_boss.single() else {
+///         return;
+///     };
+///
+///     let units = q_army.iter().collect();
+///     let ff = cache.request_flowfield(&mut cmds, &grid, boss_tf.translation, units);
+///     cmds.entity(ff).insert(FlowFieldTarget::new(boss));
+/// }
+/// ```
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct FlowFieldTarget {
+    pub entity: Entity,
+    /// Cells (Chebyshev distance) the target may move away from the destination before the flowfield follows.
+    pub retarget_cells: i32,
+}
+
+impl FlowFieldTarget {
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            retarget_cells: 2,
+        }
+    }
+
+    pub fn with_retarget_cells(mut self, cells: i32) -> Self {
+        self.retarget_cells = cells;
+        self
+    }
+}
+
+// TODO: Remove. This is just for visualizing the destination radius. (Dont think this is currently working as intended)
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct DestinationRadius(pub u32);
 
-#[derive(Component, Clone, Default, PartialEq)]
+/// With the `serialize` feature the integrated field can be baked and saved with the map, then inserted on load
+/// instead of integrated at startup. Units, arrivals and other per-entity state are left out, entity ids do not
+/// survive a save.
+#[derive(Component, Reflect, Clone, Default, PartialEq)]
+#[reflect(Component)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct FlowField {
+    #[cfg_attr(feature = "serialize", serde(skip))]
     pub arrived: bool,
+    /// Overrides [`FvoSettings::arrival`] for the units on this flowfield, e.g. a looser stop for a large formation.
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    pub arrival: Option<ArrivalSettings>,
+    /// Distance to the destination cell at which a unit fires an [`AgentArrivedEvent`].
+    pub arrival_radius: f32,
+    /// Units that already fired an [`AgentArrivedEvent`] for this flowfield.
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    pub arrived_units: HashSet<Entity>,
     pub destination_grid_size: IVec2,
+    /// The goal cell of `InitializeFlowFieldEv::destination_pos`, also the first entry of `destination_cells`.
     pub destination_cell: Cell,
+    /// Every goal cell the integration field is seeded from. Agents flow to whichever is closest by path cost.
+    pub destination_cells: Vec<Cell>,
     pub destination_radius: f32,
+    /// Travel time to the closest goal for every cell, row-major, filled with [`IntegrationMethod::FastMarching`].
+    /// `best_cost` holds the same values rounded.
+    pub distances: Vec<f32>,
+    /// Cells whose cost changed since the last rebuild, re-integrated later in the frame.
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    pub dirty_region: Option<IRect>,
+    /// Target slot of every unit when [`FlowField::spread_goals`] is set, the world position of a free cell around the
+    /// destination. Units head for their slot instead of the destination once within `slot_radius` of it.
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    pub goal_slots: HashMap<Entity, Vec3>,
     pub grid: Vec<Vec<Cell>>,
+    /// Algorithm used to build the integration field, taken from [`FlowFieldConfig::integration`] on creation.
+    pub integration: IntegrationMethod,
+    /// Cost layer of the grid the field is integrated over, taken from the [`NavLayer`] of the first unit that has
+    /// one on creation.
+    pub layer: NavLayer,
+    /// Compute [`Cell::los`], letting units with a clear view of the goal head straight for it. Taken from
+    /// [`FlowFieldConfig::line_of_sight`] on creation.
+    pub line_of_sight: bool,
+    /// Cells with less [`Grid::clearance`] than this are impassable for the field, so units are never funneled
+    /// through gaps they don't fit through. `0` and `1` allow every open cell. Picked on creation from the widest
+    /// unit when [`FlowFieldConfig::clearance`] is set, or from the size class of the units when
+    /// [`FlowFieldConfig::size_classes`] are, see [`Grid::clearance_for_radius`].
+    pub min_clearance: u16,
     pub offset: Vec3,
+    /// How the units weigh the terrain, the field is integrated over the layer costs scaled by it. Taken from the
+    /// [`TraversalProfile`] of the first unit on creation, `None` takes the layer costs as they are.
+    pub profile: Option<TraversalProfile>,
+    /// Drop units from [`FlowField::units`] (and their [`Destination`]) once they fired an [`AgentArrivedEvent`].
+    pub remove_arrived: bool,
+    /// How directions are read between cell centers.
+    pub sampling: DirectionSampling,
+    /// Distance of the farthest goal slot from the destination.
+    pub slot_radius: f32,
+    /// Spread the units over distinct cells around the destination instead of all fighting for the same one. Slots
+    /// are (re)assigned whenever a unit has none. Taken from [`FlowFieldConfig::spread_goals`] on creation.
+    pub spread_goals: bool,
+    /// Sectors the integration field is restricted to, `None` covers the whole grid. See
+    /// [`FlowFieldConfig::hierarchical`].
+    pub sectors: Option<HashSet<IVec2>>,
+    pub sector_size: i32,
     pub size: IVec2,
+    #[cfg_attr(feature = "serialize", serde(skip))]
     pub steering_map: HashMap<Entity, Vec3>,
+    #[cfg_attr(feature = "serialize", serde(skip))]
     pub units: Vec<Entity>,
+    /// Per-unit membership weight used to blend this flowfield with others the unit belongs to. Missing entries
+    /// count as `1.0`.
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    pub weights: HashMap<Entity, f32>,
 }
 
 impl FlowField {
@@ -39,6 +301,7 @@ impl FlowField {
             units.iter().map(|&unit| (unit, Vec3::ZERO)).collect();
 
         FlowField {
+            arrival_radius: 5.0,
             destination_radius: (units.len() as f32 * unit_count).sqrt() * 3.0,
             offset,
             size,
@@ -54,30 +317,329 @@ impl FlowField {
 
         for y in 0..grid_size_y {
             for x in 0..grid_size_x {
-                let cell = &self.grid[y][x]; // Immutable borrow to get best_cost
-                let mut best_cost = cell.best_cost;
-                let mut best_direction = GridDirection::None;
-
-                // Get all possible directions
-                for direction in GridDirection::all_directions() {
-                    let delta = direction.vector();
-                    let nx = x as isize + delta.x as isize;
-                    let ny = y as isize + delta.y as isize;
-
-                    if nx >= 0 && nx < grid_size_x as isize && ny >= 0 && ny < grid_size_y as isize
-                    {
-                        let neighbor = &self.grid[ny as usize][nx as usize];
-                        if neighbor.best_cost < best_cost {
-                            best_cost = neighbor.best_cost;
-                            best_direction = direction;
-                        }
+                self.update_direction(x, y);
+            }
+        }
+
+        self.update_line_of_sight();
+    }
+
+    // flags every reachable cell with an unobstructed straight line to its closest goal
+    fn update_line_of_sight(&mut self) {
+        self.update_line_of_sight_where(|_, _| true);
+    }
+
+    // like `update_line_of_sight`, but only walks the lines of the cells `stale(cell, goal)` picks
+    fn update_line_of_sight_where(&mut self, stale: impl Fn(IVec2, IVec2) -> bool) {
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                let idx = IVec2::new(x, y);
+                let goal = self
+                    .destination_cells
+                    .iter()
+                    .map(|cell| cell.idx)
+                    .min_by_key(|goal| goal.distance_squared(idx));
+                if goal.is_some_and(|goal| !stale(idx, goal)) {
+                    continue;
+                }
+
+                let los = self.line_of_sight
+                    && self.grid[y as usize][x as usize].best_cost != u16::MAX
+                    && goal.is_some_and(|goal| self.line_clear(idx, goal));
+                self.grid[y as usize][x as usize].los = los;
+            }
+        }
+    }
+
+    // walks every cell the straight line between the two cell centers touches, squeezing between two diagonal
+    // blockers counts as blocked. Walls, anything costlier than open ground and any step with an exit cost block the
+    // line.
+    fn line_clear(&self, from: IVec2, to: IVec2) -> bool {
+        let blocked = |idx: IVec2| self.grid[idx.y as usize][idx.x as usize].cost > 1;
+        let exit_blocked = |idx: IVec2, delta: IVec2| {
+            GridDirection::from_vector2(delta)
+                .is_some_and(|dir| self.grid[idx.y as usize][idx.x as usize].exit_cost(dir) > 0)
+        };
+
+        let delta = to - from;
+        let step = delta.signum();
+        let n = delta.abs();
+        let mut cur = from;
+        let (mut ix, mut iy) = (0, 0);
+        while ix < n.x || iy < n.y {
+            let prev = cur;
+            let decision = (1 + 2 * ix) * n.y - (1 + 2 * iy) * n.x;
+            if decision == 0 {
+                if blocked(cur + IVec2::new(step.x, 0)) || blocked(cur + IVec2::new(0, step.y)) {
+                    return false;
+                }
+                cur += step;
+                ix += 1;
+                iy += 1;
+            } else if decision < 0 {
+                cur.x += step.x;
+                ix += 1;
+            } else {
+                cur.y += step.y;
+                iy += 1;
+            }
+
+            if blocked(cur) || exit_blocked(prev, cur - prev) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // points the cell at its cheapest neighbor
+    fn update_direction(&mut self, x: usize, y: usize) {
+        if self.integration == IntegrationMethod::FastMarching && !self.distances.is_empty() {
+            self.grid[y][x].best_direction = self.steepest_descent(IVec2::new(x as i32, y as i32));
+            return;
+        }
+
+        let grid_size_y = self.size.y as usize;
+        let grid_size_x = self.size.x as usize;
+
+        let cell = &self.grid[y][x]; // Immutable borrow to get best_cost
+        let mut best_cost = cell.best_cost;
+        let mut best_direction = GridDirection::None;
+
+        // Get all possible directions
+        for direction in GridDirection::all_directions() {
+            let delta = direction.vector();
+            let nx = x as isize + delta.x as isize;
+            let ny = y as isize + delta.y as isize;
+
+            if nx >= 0
+                && nx < grid_size_x as isize
+                && ny >= 0
+                && ny < grid_size_y as isize
+                && cell.can_exit(direction)
+            {
+                let neighbor = &self.grid[ny as usize][nx as usize];
+                if neighbor.best_cost < best_cost {
+                    best_cost = neighbor.best_cost;
+                    best_direction = direction;
+                }
+            }
+        }
+
+        // Now, set the best_direction for the cell
+        self.grid[y][x].best_direction = best_direction;
+    }
+
+    // points the cell at the neighbor the travel time drops fastest towards, per unit of distance
+    fn steepest_descent(&self, idx: IVec2) -> GridDirection {
+        let time = self.distances[self.distance_index(idx)];
+        let cell = &self.grid[idx.y as usize][idx.x as usize];
+        let mut best_slope = 0.0;
+        let mut best_direction = GridDirection::None;
+
+        for direction in GridDirection::all_directions() {
+            let delta = direction.vector();
+            let neighbor_idx = idx + delta;
+            if !self.in_bounds(neighbor_idx) || !cell.can_exit(direction) {
+                continue;
+            }
+
+            let slope = (time - self.distances[self.distance_index(neighbor_idx)])
+                / delta.as_vec2().length();
+            if slope > best_slope {
+                best_slope = slope;
+                best_direction = direction;
+            }
+        }
+
+        best_direction
+    }
+
+    fn distance_index(&self, idx: IVec2) -> usize {
+        (idx.y * self.size.x + idx.x) as usize
+    }
+
+    /// Re-integrates the cells inside `region` (inclusive cell indices) after their cost changed in `grid`, together
+    /// with every cell whose cheapest path ran through them. Gives the same field as a full rebuild while only
+    /// touching the part of the grid the edit can affect.
+    pub fn update_region(&mut self, grid: &Grid, region: IRect) {
+        // a new wall takes clearance from the cells up to `min_clearance - 1` cells around it
+        let reach = IVec2::splat(self.min_clearance.saturating_sub(1) as i32);
+        let min = (region.min - reach).max(IVec2::ZERO);
+        let max = (region.max + reach).min(self.size - IVec2::ONE);
+        if min.x > max.x || min.y > max.y {
+            return;
+        }
+
+        let (layer, min_clearance, width) = (self.layer, self.min_clearance, self.size.x);
+        let profile = self.profile.clone();
+        let clearance = (min_clearance > 1).then(|| grid.clearance(layer));
+        let cost_of = |idx: IVec2| match &clearance {
+            Some(clearance) if clearance[(idx.y * width + idx.x) as usize] < min_clearance => {
+                u8::MAX
+            }
+            _ => grid.integration_cost_for(idx, layer, profile.as_ref()),
+        };
+
+        // fast marching has no cheap way to find the cells that depend on the edit, march again from the goals
+        if self.integration == IntegrationMethod::FastMarching {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let is_destination = self.is_destination(IVec2::new(x, y));
+                    let cell = &mut self.grid[y as usize][x as usize];
+                    cell.exit_costs = grid.grid[y as usize][x as usize].exit_costs;
+                    if !is_destination {
+                        cell.cost = cost_of(IVec2::new(x, y));
                     }
                 }
+            }
+
+            self.fast_march();
+            self.create_flowfield();
+            return;
+        }
+
+        // pick up the new costs, goals stay free
+        let mut affected: Vec<IVec2> = Vec::new();
+        let mut in_affected: HashSet<IVec2> = HashSet::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let idx = IVec2::new(x, y);
+                let is_destination = self.is_destination(idx);
+                let cell = &mut self.grid[y as usize][x as usize];
+                cell.exit_costs = grid.grid[y as usize][x as usize].exit_costs;
+                if !is_destination {
+                    cell.cost = cost_of(idx);
+                }
+                affected.push(idx);
+                in_affected.insert(idx);
+            }
+        }
+
+        // any cell whose cheapest path ran through an affected cell may get more expensive, reset it as well
+        let mut i = 0;
+        while i < affected.len() {
+            let idx = affected[i];
+            i += 1;
+
+            let best_cost = self.grid[idx.y as usize][idx.x as usize].best_cost;
+            if best_cost == u16::MAX {
+                continue;
+            }
+
+            for direction in GridDirection::cardinal_directions() {
+                let neighbor_idx = idx + direction.vector();
+                if !self.in_bounds(neighbor_idx) || in_affected.contains(&neighbor_idx) {
+                    continue;
+                }
+
+                let neighbor = &self.grid[neighbor_idx.y as usize][neighbor_idx.x as usize];
+                let step = neighbor.exit_cost(direction.opposite());
+                if neighbor.cost != u8::MAX
+                    && step != u8::MAX
+                    && neighbor.best_cost
+                        == best_cost.saturating_add(neighbor.cost as u16 + step as u16)
+                {
+                    affected.push(neighbor_idx);
+                    in_affected.insert(neighbor_idx);
+                }
+            }
+        }
+
+        for &idx in &affected {
+            if !self.is_destination(idx) {
+                self.grid[idx.y as usize][idx.x as usize].best_cost = u16::MAX;
+            }
+        }
+
+        // re-seed from the goals and the untouched cells bordering the reset area
+        let mut cells_to_check: VecDeque<IVec2> = VecDeque::new();
+        let mut seeded: HashSet<IVec2> = HashSet::new();
+        for &idx in &affected {
+            if self.is_destination(idx) && seeded.insert(idx) {
+                cells_to_check.push_back(idx);
+            }
+
+            for direction in GridDirection::cardinal_directions() {
+                let neighbor_idx = idx + direction.vector();
+                if !self.in_bounds(neighbor_idx) || in_affected.contains(&neighbor_idx) {
+                    continue;
+                }
+
+                let neighbor = &self.grid[neighbor_idx.y as usize][neighbor_idx.x as usize];
+                if neighbor.best_cost != u16::MAX && seeded.insert(neighbor_idx) {
+                    cells_to_check.push_back(neighbor_idx);
+                }
+            }
+        }
 
-                // Now, set the best_direction for the cell
-                self.grid[y][x].best_direction = best_direction;
+        let mut changed = in_affected;
+        self.integrate(cells_to_check, |idx| {
+            changed.insert(idx);
+        });
+
+        // directions can change wherever a cell or one of its neighbors got a new cost
+        let mut redirect: HashSet<IVec2> = HashSet::new();
+        for &idx in &changed {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let idx = idx + IVec2::new(dx, dy);
+                    if self.in_bounds(idx) {
+                        redirect.insert(idx);
+                    }
+                }
             }
         }
+
+        for idx in redirect {
+            self.update_direction(idx.x as usize, idx.y as usize);
+        }
+
+        // only lines through the edit (or squeezing past its border) and cells it cut off or reconnected can change
+        if self.line_of_sight {
+            let dirty = Rect::from_corners(min.as_vec2() - 1.0, max.as_vec2() + 1.0);
+            self.update_line_of_sight_where(|idx, goal| {
+                changed.contains(&idx) || segment_hits_rect(idx.as_vec2(), goal.as_vec2(), dirty)
+            });
+        }
+    }
+
+    fn in_bounds(&self, idx: IVec2) -> bool {
+        idx.x >= 0 && idx.x < self.size.x && idx.y >= 0 && idx.y < self.size.y
+    }
+
+    /// Whether the cell `idx` lies inside the sectors this flowfield is integrated over.
+    pub fn in_corridor(&self, idx: IVec2) -> bool {
+        match &self.sectors {
+            Some(sectors) => sectors.contains(&idx.div_euclid(IVec2::splat(self.sector_size))),
+            None => true,
+        }
+    }
+
+    fn is_destination(&self, idx: IVec2) -> bool {
+        self.destination_cells.iter().any(|cell| cell.idx == idx)
+    }
+
+    /// Moves the flowfield to a new destination in place, keeping its entity and units. Integrates the whole grid
+    /// right away, trigger a [`RetargetFlowFieldEv`] instead to build in the background, keep a hierarchical corridor
+    /// and give units that already arrived their [`Destination`] back.
+    pub fn set_destination(&mut self, destination_pos: Vec3, grid: &Grid) {
+        let idx = grid.get_cell_from_world_position(destination_pos).idx;
+        self.sectors = None;
+        self.retarget(
+            grid.integration_cells_for(self.layer, self.min_clearance, self.profile.as_ref()),
+            &[idx],
+        );
+    }
+
+    // re-integrates towards new goals and forgets who arrived at the old ones
+    fn retarget(&mut self, grid: Vec<Vec<Cell>>, destination_idxs: &[IVec2]) {
+        self.create_integration_field(grid, destination_idxs);
+        self.create_flowfield();
+        self.dirty_region = None;
+        self.arrived = false;
+        self.arrived_units.clear();
+        self.goal_slots.clear();
     }
 
     pub fn add_unit(&mut self, unit: Entity) {
@@ -87,91 +649,327 @@ impl FlowField {
     pub fn remove_unit(&mut self, unit: Entity) {
         self.units.retain(|&u| u != unit);
         self.steering_map.retain(|&u, _| u != unit);
+        self.weights.remove(&unit);
+        self.arrived_units.remove(&unit);
+        self.goal_slots.remove(&unit);
+    }
+
+    /// Sets how strongly this flowfield pulls on `unit` when it belongs to several flowfields.
+    ///
+    /// The preferred velocities of all memberships are summed by weight and renormalized, so `3.0` here and `1.0`
+    /// on another flowfield heads mostly toward this destination while biasing slightly toward the other.
+    pub fn set_unit_weight(&mut self, unit: Entity, weight: f32) {
+        self.weights.insert(unit, weight.max(0.0));
+    }
+
+    /// Returns the membership weight of `unit`, `1.0` if none was set.
+    pub fn unit_weight(&self, unit: Entity) -> f32 {
+        self.weights.get(&unit).copied().unwrap_or(1.0)
+    }
+
+    /// Returns the last steering velocity solved for `unit`, or `None` if the unit is not part of this flowfield.
+    ///
+    /// The value is written during [`FvoSystemSet::Solve`](crate::fvo::FvoSystemSet::Solve), read it from a system
+    /// ordered after that set to get the current frame's result.
+    pub fn steering_of(&self, unit: Entity) -> Option<Vec3> {
+        self.steering_map.get(&unit).copied()
+    }
+
+    /// Returns the world position of the goal cell closest to `position`, the single destination unless the
+    /// flowfield has several goals.
+    pub fn nearest_destination(&self, position: Vec3) -> Vec3 {
+        self.destination_cells
+            .iter()
+            .map(|cell| cell.world_pos)
+            .min_by(|a, b| {
+                a.distance_squared(position)
+                    .total_cmp(&b.distance_squared(position))
+            })
+            .unwrap_or(self.destination_cell.world_pos)
+    }
+
+    /// Returns where `unit` at `position` is headed, its goal slot or else the closest destination.
+    pub fn goal_of(&self, unit: Entity, position: Vec3) -> Vec3 {
+        self.goal_slots
+            .get(&unit)
+            .copied()
+            .unwrap_or_else(|| self.nearest_destination(position))
+    }
+
+    /// Returns the cells from the one at `position` to a goal, following [`Cell::best_direction`]. Stops early at a
+    /// cell without a direction, e.g. a wall or a cell outside the integrated sectors.
+    pub fn trace_path(&self, position: Vec3, grid: &Grid) -> Vec<IVec2> {
+        let goals: Vec<IVec2> = self.destination_cells.iter().map(|cell| cell.idx).collect();
+        let mut idx = self.get_cell_from_world_position(position, grid).idx;
+        let mut path = vec![idx];
+
+        // a valid field never loops, cap the walk at the cell count anyway
+        for _ in 0..self.size.x * self.size.y {
+            if goals.contains(&idx) {
+                break;
+            }
+
+            let direction = self.grid[idx.y as usize][idx.x as usize].best_direction;
+            if direction == GridDirection::None {
+                break;
+            }
+
+            idx += direction.vector();
+            if idx.x < 0 || idx.y < 0 || idx.x >= self.size.x || idx.y >= self.size.y {
+                break;
+            }
+            path.push(idx);
+        }
+
+        path
+    }
+
+    /// Returns [`FlowField::trace_path`] straightened by string pulling: from every corner the path jumps to the
+    /// furthest cell still in a straight line of it, only the corners are kept. The line test is the one of
+    /// [`FlowField::line_of_sight`], so the path never cuts through walls or costlier terrain. The waypoints are cell
+    /// centers in world space, starting with the first corner after `position`.
+    ///
+    /// The cost grows with the square of the path length, meant for a handful of units, see
+    /// [`PathSmoothing`](crate::smoothing::PathSmoothing).
+    pub fn smoothed_path(&self, position: Vec3, grid: &Grid) -> Vec<Vec3> {
+        let cells = self.trace_path(position, grid);
+        let mut waypoints = Vec::new();
+
+        let mut corner = 0;
+        while corner + 1 < cells.len() {
+            let next = (corner + 2..cells.len())
+                .rev()
+                .find(|&i| self.line_clear(cells[corner], cells[i]))
+                .unwrap_or(corner + 1);
+
+            let idx = cells[next];
+            waypoints.push(self.grid[idx.y as usize][idx.x as usize].world_pos);
+            corner = next;
+        }
+
+        waypoints
+    }
+
+    /// Assigns each of `units` (with their current position) a distinct free cell around the destination, filling
+    /// [`FlowField::goal_slots`]. Slots are the reachable cells closest to the goals by steps, handed out to the
+    /// closest units first so paths into the crowd cross as little as possible.
+    pub fn assign_goal_slots(&mut self, units: &[(Entity, Vec3)]) {
+        self.goal_slots.clear();
+        self.slot_radius = 0.0;
+
+        // breadth-first over the walkable cells around the goals
+        let mut slots: Vec<Vec3> = Vec::new();
+        let mut visited: HashSet<IVec2> = HashSet::new();
+        let mut queue: VecDeque<IVec2> = self.destination_cells.iter().map(|c| c.idx).collect();
+        visited.extend(queue.iter().copied());
+        while let Some(idx) = queue.pop_front() {
+            if slots.len() >= units.len() {
+                break;
+            }
+
+            let cell = &self.grid[idx.y as usize][idx.x as usize];
+            slots.push(cell.world_pos);
+
+            for direction in GridDirection::cardinal_directions() {
+                let next = idx + direction.vector();
+                if !self.in_bounds(next) || !visited.insert(next) {
+                    continue;
+                }
+
+                let next_cell = &self.grid[next.y as usize][next.x as usize];
+                if next_cell.cost != u8::MAX && next_cell.best_cost != u16::MAX {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        // greedy matching, closest unit-slot pairs first
+        let mut pairs: Vec<(f32, usize, usize)> = Vec::with_capacity(units.len() * slots.len());
+        for (u, (_, pos)) in units.iter().enumerate() {
+            for (s, slot) in slots.iter().enumerate() {
+                pairs.push((pos.distance_squared(*slot), u, s));
+            }
+        }
+        pairs.sort_unstable_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+        let mut slot_taken = vec![false; slots.len()];
+        for (_, u, s) in pairs {
+            let unit = units[u].0;
+            if slot_taken[s] || self.goal_slots.contains_key(&unit) {
+                continue;
+            }
+
+            slot_taken[s] = true;
+            self.goal_slots.insert(unit, slots[s]);
+            let from_goal = slots[s].distance(self.nearest_destination(slots[s]));
+            self.slot_radius = self.slot_radius.max(from_goal);
+        }
     }
 
     /// Gets the Cell at the given world position.
     pub fn get_cell_from_world_position(&self, position: Vec3, grid: &Grid) -> Cell {
-        let pos = position;
-        let cell_diameter = grid.cell_diameter;
+        let pos = grid.world_to_local(position);
+        let cell_size = grid.cell_size;
         let size = self.size;
 
         // Calculate the offset for the grid's top-left corner
-        let adjusted_x = pos.x - (-size.x as f32 * cell_diameter / 2.0);
-        let adjusted_y = pos.z - (-size.y as f32 * cell_diameter / 2.0);
+        let adjusted_x = pos.x - (-size.x as f32 * cell_size.x / 2.0);
+        let adjusted_y = pos.z - (-size.y as f32 * cell_size.y / 2.0);
 
         // Calculate percentages within the grid
-        let percent_x = adjusted_x / (size.x as f32 * cell_diameter);
-        let percent_y = adjusted_y / (size.y as f32 * cell_diameter);
+        let percent_x = adjusted_x / (size.x as f32 * cell_size.x);
+        let percent_y = adjusted_y / (size.y as f32 * cell_size.y);
 
         let offset = Some(Vec2::new(percent_x, percent_y));
 
-        utils::get_cell_from_world_position_helper(pos, size, cell_diameter, &self.grid, offset)
+        utils::get_cell_from_world_position_helper(pos, size, cell_size, &self.grid, offset)
     }
 
-    /// Smoothly sample the best_direction at an arbitrary world-space point
-    /// by bilinearly interpolating between the four enclosing cells.
+    /// Samples the best_direction at an arbitrary world-space point according to [`FlowField::sampling`]. Points in a
+    /// cell with [`Cell::los`] get the straight direction to the goal instead. The direction is on the XZ of plane
+    /// space, see [`Grid::direction_to_world`].
+    ///
+    /// In [`DirectionSampling::Bilinear`] mode the four cells around the point are blended by distance to their
+    /// centers. Walls and unreachable cells get no weight, so agents next to a wall are not pulled into it. Points
+    /// off the grid are sampled at its closest edge, see [`FvoConfig::out_of_bounds`] for how agents there are
+    /// steered.
+    ///
+    /// [`FvoConfig::out_of_bounds`]: crate::fvo::FvoConfig::out_of_bounds
     pub fn sample_direction(&self, world_pos: Vec3, grid: &Grid) -> Vec2 {
-        // 1) Map world -> [0..1] uv over the grid
-        let (u, v) = self.world_to_uv(world_pos, grid);
+        let (corners, sx, sy) = self.sample_corners(world_pos, grid);
 
-        // 2) Scale uv to your discrete grid indices in float-space
-        let cols = self.size.x as f32;
-        let rows = self.size.y as f32;
-        let fx = u * (cols - 1.0);
-        let fy = v * (rows - 1.0);
+        let x = if sx < 0.5 { corners[0].x } else { corners[3].x };
+        let y = if sy < 0.5 { corners[0].y } else { corners[3].y };
+        let cell = &self.grid[y as usize][x as usize];
+
+        // clear line to the goal, no need to follow the staircase of the grid
+        if cell.los {
+            let to_goal = self.nearest_destination(cell.world_pos) - world_pos;
+            return grid.plane.to_2d(to_goal).normalize_or_zero();
+        }
+
+        // cell directions are laid out on the grid, turn them with it
+        if self.sampling == DirectionSampling::Nearest {
+            let dir = cell.best_direction.vector().as_vec2().normalize_or_zero();
+            return grid.direction_to_world(dir);
+        }
+
+        let weights = [
+            (1.0 - sx) * (1.0 - sy),
+            sx * (1.0 - sy),
+            (1.0 - sx) * sy,
+            sx * sy,
+        ];
+
+        let mut sum = Vec2::ZERO;
+        let mut total = 0.0;
+        let mut nearest = (0.0, Vec2::ZERO);
+        for (idx, weight) in corners.into_iter().zip(weights) {
+            let cell = &self.grid[idx.y as usize][idx.x as usize];
+            let dir = cell.best_direction.vector().as_vec2().normalize_or_zero();
+            if weight > nearest.0 {
+                nearest = (weight, dir);
+            }
+
+            if cell.cost == u8::MAX || cell.best_cost == u16::MAX {
+                continue;
+            }
 
-        // 3) Corners
-        let x0 = fx.floor() as usize;
-        let y0 = fy.floor() as usize;
-        let x1 = (x0 + 1).min(self.size.x as usize - 1);
-        let y1 = (y0 + 1).min(self.size.y as usize - 1);
+            sum += dir * weight;
+            total += weight;
+        }
+
+        // surrounded by walls, fall back to the closest cell
+        if total <= f32::EPSILON {
+            return grid.direction_to_world(nearest.1);
+        }
+
+        grid.direction_to_world(sum.normalize_or_zero())
+    }
 
-        let sx = fx - x0 as f32;
-        let sy = fy - y0 as f32;
+    /// Bilinearly samples the cell speed multiplier at `world_pos`, mirroring [`FlowField::sample_direction`].
+    pub fn sample_cost(&self, world_pos: Vec3, grid: &Grid) -> f32 {
+        let ([c00, c10, c01, c11], sx, sy) = self.sample_corners(world_pos, grid);
+        let multiplier = |idx: IVec2| self.grid[idx.y as usize][idx.x as usize].speed_multiplier;
 
-        // 4) Pull the four best_direction vectors (Vec2)
-        let d00 = self.grid[y0][x0].best_direction.vector().as_vec2();
-        let d10 = self.grid[y0][x1].best_direction.vector().as_vec2();
-        let d01 = self.grid[y1][x0].best_direction.vector().as_vec2();
-        let d11 = self.grid[y1][x1].best_direction.vector().as_vec2();
+        let m0 = multiplier(c00).lerp(multiplier(c10), sx);
+        let m1 = multiplier(c01).lerp(multiplier(c11), sx);
+        m0.lerp(m1, sy)
+    }
 
-        // 5) Bilinear lerp
-        let lerp = |a: Vec2, b: Vec2, t: f32| a * (1.0 - t) + b * t;
-        let d0 = lerp(d00, d10, sx);
-        let d1 = lerp(d01, d11, sx);
-        let smooth = lerp(d0, d1, sy).normalize_or_zero();
+    // the four cells whose centers surround `world_pos` (top-left, top-right, bottom-left, bottom-right) and the
+    // position between them
+    fn sample_corners(&self, world_pos: Vec3, grid: &Grid) -> ([IVec2; 4], f32, f32) {
+        let (u, v) = self.world_to_uv(world_pos, grid);
 
-        smooth.normalize_or_zero()
+        // cell centers sit half a cell in from the edges
+        let cols = self.size.x as f32;
+        let rows = self.size.y as f32;
+        let fx = (u * cols - 0.5).clamp(0.0, cols - 1.0);
+        let fy = (v * rows - 0.5).clamp(0.0, rows - 1.0);
+
+        let x0 = fx.floor() as i32;
+        let y0 = fy.floor() as i32;
+        let x1 = (x0 + 1).min(self.size.x - 1);
+        let y1 = (y0 + 1).min(self.size.y - 1);
+
+        let corners = [
+            IVec2::new(x0, y0),
+            IVec2::new(x1, y0),
+            IVec2::new(x0, y1),
+            IVec2::new(x1, y1),
+        ];
+        (corners, fx - x0 as f32, fy - y0 as f32)
     }
 
     /// Convert a world-space position into UV [0..1] over the grid.
     fn world_to_uv(&self, world_pos: Vec3, grid: &Grid) -> (f32, f32) {
         // Offset so (0,0) is top-left of your grid
-        let local = world_pos - self.offset;
-        let cell_d = grid.cell_diameter;
+        let local = grid.world_to_local(world_pos) - self.offset;
+        let cell_size = grid.cell_size;
         let cols = self.size.x as f32;
         let rows = self.size.y as f32;
 
-        let u = (local.x + (cols * cell_d * 0.5)) / (cols * cell_d);
-        let v = (local.z + (rows * cell_d * 0.5)) / (rows * cell_d);
+        let u = (local.x + (cols * cell_size.x * 0.5)) / (cols * cell_size.x);
+        let v = (local.z + (rows * cell_size.y * 0.5)) / (rows * cell_size.y);
 
         (u.clamp(0.0, 1.0), v.clamp(0.0, 1.0))
     }
 
-    fn create_integration_field(&mut self, grid: Vec<Vec<Cell>>, destination_idx: IVec2) {
+    fn create_integration_field(&mut self, grid: Vec<Vec<Cell>>, destination_idxs: &[IVec2]) {
         // println!("Start Integration Field Create");
 
         self.grid = grid;
+        self.destination_cells.clear();
 
-        // Initialize the destination cell in the grid
-        let dest_cell = &mut self.grid[destination_idx.y as usize][destination_idx.x as usize];
-        dest_cell.cost = 0;
-        dest_cell.best_cost = 0;
-        self.destination_cell = dest_cell.clone();
-
+        // Seed every destination cell, each cell ends up flowing to its cheapest goal
         let mut cells_to_check: VecDeque<IVec2> = VecDeque::new();
-        cells_to_check.push_back(destination_idx);
+        for &destination_idx in destination_idxs {
+            let dest_cell = &mut self.grid[destination_idx.y as usize][destination_idx.x as usize];
+            dest_cell.cost = 0;
+            dest_cell.best_cost = 0;
+            self.destination_cells.push(*dest_cell);
+            cells_to_check.push_back(destination_idx);
+        }
+
+        if let Some(first) = self.destination_cells.first() {
+            self.destination_cell = *first;
+        }
+
+        match self.integration {
+            IntegrationMethod::Dijkstra => self.integrate(cells_to_check, |_| {}),
+            IntegrationMethod::FastMarching => self.fast_march(),
+        }
+
+        // println!("End Integration Field Create");
+    }
 
+    // spreads best costs outward from the queued cells, calling `on_improve` for every cell that got cheaper
+    fn integrate(
+        &mut self,
+        mut cells_to_check: VecDeque<IVec2>,
+        mut on_improve: impl FnMut(IVec2),
+    ) {
         while let Some(cur_idx) = cells_to_check.pop_front() {
             let cur_x = cur_idx.x as usize;
             let cur_y = cur_idx.y as usize;
@@ -188,195 +986,1242 @@ impl FlowField {
                     && neighbor_idx.y >= 0
                     && neighbor_idx.y < self.size.y
                 {
+                    if !self.in_corridor(neighbor_idx) {
+                        continue;
+                    }
+
                     let neighbor_x = neighbor_idx.x as usize;
                     let neighbor_y = neighbor_idx.y as usize;
 
                     let neighbor_cell = &mut self.grid[neighbor_y][neighbor_x];
 
-                    if neighbor_cell.cost == u8::MAX {
+                    // units walk from the neighbor into the current cell
+                    let step = neighbor_cell.exit_cost(direction.opposite());
+                    if neighbor_cell.cost == u8::MAX || step == u8::MAX {
                         continue;
                     }
 
-                    let tentative_best_cost = neighbor_cell.cost as u16 + cur_cell_best_cost;
+                    let tentative_best_cost =
+                        cur_cell_best_cost.saturating_add(neighbor_cell.cost as u16 + step as u16);
                     if tentative_best_cost < neighbor_cell.best_cost {
                         neighbor_cell.best_cost = tentative_best_cost;
                         cells_to_check.push_back(neighbor_idx);
+                        on_improve(neighbor_idx);
                     }
                 }
             }
         }
-
-        // println!("End Integration Field Create");
     }
 }
 
-pub fn flowfield_group_stop_system(
-    mut cmds: Commands,
-    mut q_ff: Query<(Entity, &mut FlowField)>,
-    q_tf: Query<(&Transform, &FvoAgent)>,
-    q_dest: Query<&Destination>,
-    grid: Res<Grid>, // ← you already have this in your FVO system
-) {
-    // ——— reuse the world-to-bucket math from the FVO solver ———
-    let world_w = grid.size.x as f32 * grid.cell_diameter;
-    let world_d = grid.size.y as f32 * grid.cell_diameter;
-    let bucket_w = world_w / grid.buckets as f32;
-    let bucket_d = world_d / grid.buckets as f32;
-    let cols = grid.grid.len();
-    let rows = grid.grid[0].len();
-    let origin = grid.grid[cols / 2][rows / 2].world_pos;
+impl FlowField {
+    // Fast Marching from the goal cells: cells are frozen in order of travel time, each solving the eikonal update
+    // from its frozen neighbors. Fills `distances` and mirrors them into `best_cost`.
+    fn fast_march(&mut self) {
+        let cell_count = (self.size.x * self.size.y) as usize;
+        self.distances.clear();
+        self.distances.resize(cell_count, f32::INFINITY);
+        let mut frozen = vec![false; cell_count];
+
+        // non-negative floats order like their bit patterns
+        let mut heap: BinaryHeap<Reverse<(u32, i32, i32)>> = BinaryHeap::new();
+        for cell in &self.destination_cells {
+            let i = self.distance_index(cell.idx);
+            self.distances[i] = 0.0;
+            heap.push(Reverse((0.0f32.to_bits(), cell.idx.x, cell.idx.y)));
+        }
 
-    for (ff_ent, mut ff) in q_ff.iter_mut() {
-        // 1) Have we already marked an arrival?
-        let mut any_arrived = ff.arrived;
+        while let Some(Reverse((_, x, y))) = heap.pop() {
+            let cur_idx = IVec2::new(x, y);
+            let cur = self.distance_index(cur_idx);
+            if frozen[cur] {
+                continue;
+            }
+            frozen[cur] = true;
 
-        // 2) Build a list of “arrived” agents (no Destination)
-        let mut arrived_list: Vec<Entity> = ff
-            .units
-            .iter()
-            .copied()
-            .filter(|&u| q_dest.get(u).is_err())
-            .collect();
+            for direction in GridDirection::cardinal_directions() {
+                let neighbor_idx = cur_idx + direction.vector();
+                if !self.in_bounds(neighbor_idx) || !self.in_corridor(neighbor_idx) {
+                    continue;
+                }
 
-        // 3) If none yet, pick the first within threshold → remove Destination
-        if !any_arrived {
-            let threshold2 = 25.0;
-            if let Some(&winner) = ff.units.iter().find(|&&u| {
-                q_tf.get(u)
-                    .map(|(tf, _)| {
-                        tf.translation
-                            .distance_squared(ff.destination_cell.world_pos)
-                            < threshold2
-                    })
-                    .unwrap_or(false)
-            }) {
-                cmds.entity(winner).remove::<Destination>();
-                arrived_list.push(winner);
-                any_arrived = true;
-            }
-        }
+                let neighbor = self.distance_index(neighbor_idx);
+                let neighbor_cell = &self.grid[neighbor_idx.y as usize][neighbor_idx.x as usize];
+                let cost = neighbor_cell.cost;
+                if frozen[neighbor]
+                    || cost == u8::MAX
+                    || !neighbor_cell.can_exit(direction.opposite())
+                {
+                    continue;
+                }
 
-        // ─── NEW: build your buckets from arrived_list ───
-        let mut buckets: HashMap<(i32, i32), Vec<Vec3>> =
-            HashMap::with_capacity(arrived_list.len());
-        for &a in &arrived_list {
-            if let Ok((tf_a, _)) = q_tf.get(a) {
-                let bx = ((tf_a.translation.x - origin.x) / bucket_w).floor() as i32;
-                let by = ((tf_a.translation.z - origin.y) / bucket_d).floor() as i32;
-                buckets.entry((bx, by)).or_default().push(tf_a.translation);
+                let time = self.eikonal_update(neighbor_idx, cost as f32, &frozen);
+                if time < self.distances[neighbor] {
+                    self.distances[neighbor] = time;
+                    heap.push(Reverse((time.to_bits(), neighbor_idx.x, neighbor_idx.y)));
+                }
             }
         }
 
-        // 4) replace nested‐loops: for each unit still moving, only probe 3×3 buckets
-        for &u in &ff.units {
-            if q_dest.get(u).is_err() {
-                continue;
-            } // skip already arrived
-            if let Ok((tf_u, agent)) = q_tf.get(u) {
-                let bx = ((tf_u.translation.x - origin.x) / bucket_w).floor() as i32;
-                let by = ((tf_u.translation.z - origin.y) / bucket_d).floor() as i32;
-                let stop_r2 = (agent.settings.sensor_range.max(agent.settings.radius * 2.0)).powi(2);
-
-                'probe: for dx in -1..=1 {
-                    for dy in -1..=1 {
-                        if let Some(cell) = buckets.get(&(bx + dx, by + dy)) {
-                            if cell
-                                .iter()
-                                .any(|&pos| tf_u.translation.distance_squared(pos) <= stop_r2)
-                            {
-                                cmds.entity(u).remove::<Destination>();
-                                break 'probe;
-                            }
-                        }
-                    }
-                }
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                let time = self.distances[self.distance_index(IVec2::new(x, y))];
+                self.grid[y as usize][x as usize].best_cost = if time.is_finite() {
+                    (time.round() as u16).min(u16::MAX - 1)
+                } else {
+                    u16::MAX
+                };
             }
         }
+    }
 
-        // 5) record and potentially despawn
-        ff.arrived = any_arrived;
-        let any_left = ff.units.iter().any(|&u| q_dest.get(u).is_ok());
-        if !any_left {
-            cmds.entity(ff_ent).despawn();
+    // arrival time at `idx` from the cheapest frozen neighbor along each axis, `slowness` being the cell cost. The
+    // exit cost of stepping from `idx` towards a neighbor is added to the neighbor's time.
+    fn eikonal_update(&self, idx: IVec2, slowness: f32, frozen: &[bool]) -> f32 {
+        let cell = &self.grid[idx.y as usize][idx.x as usize];
+        let axis_min = |a: GridDirection, b: GridDirection| {
+            [a, b]
+                .into_iter()
+                .filter(|&direction| cell.can_exit(direction))
+                .map(|direction| (idx + direction.vector(), cell.exit_cost(direction)))
+                .filter(|&(n, _)| self.in_bounds(n) && frozen[self.distance_index(n)])
+                .map(|(n, step)| self.distances[self.distance_index(n)] + step as f32)
+                .fold(f32::INFINITY, f32::min)
+        };
+        let a = axis_min(GridDirection::West, GridDirection::East);
+        let b = axis_min(GridDirection::North, GridDirection::South);
+
+        // only one axis known, or the other too far behind to contribute: plain 1D step
+        if (a - b).abs() >= slowness || !a.is_finite() || !b.is_finite() {
+            return a.min(b) + slowness;
         }
+
+        (a + b + (2.0 * slowness * slowness - (a - b) * (a - b)).sqrt()) * 0.5
     }
 }
 
-fn initialize_flowfield(
-    trigger: On<InitializeFlowFieldEv>,
-    mut cmds: Commands,
-    grid: ResMut<Grid>,
-    mut q_ff: Query<(Entity, &mut FlowField)>,
-    mut _meshes: ResMut<Assets<Mesh>>, // TODO: Remove
-    mut _materials: ResMut<Assets<StandardMaterial>>, // TODO: Remove
-    q_destination_radius: Query<(Entity, &DestinationRadius)>, // TODO: Remove
-) {
-    let destination_pos = trigger.event().destination_pos;
-    let units = trigger.event().entities.clone();
-    if units.is_empty() {
-        return;
-    }
+/// Builds a ready-to-use [`FlowField`] in one go instead of filling its fields and calling the integration by hand.
+/// The whole grid is integrated right away on the calling thread, trigger an [`InitializeFlowFieldEv`] instead for
+/// background builds, hierarchical corridors and the [`FlowFieldCache`].
+///
+/// # Example
+///
+/// ```
+/// fn rally(mut cmds: Commands, grid: Res<Grid>, q_units: Query<Entity, With<Unit>>) {
+///     let units: Vec<Entity> = q_units.iter().collect();
+///     FlowFieldBuilder::new(Vec3::new(120.0, 0.0, -40.0))
+///         .with_units(&units)
+///         .with_arrival_radius(8.0)
+///         .spawn(&mut cmds, &grid);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FlowFieldBuilder {
+    destination: Vec3,
+    extra_destinations: Vec<Vec3>,
+    units: Vec<Entity>,
+    arrival_radius: Option<f32>,
+    arrival: Option<ArrivalSettings>,
+    integration: IntegrationMethod,
+    sampling: DirectionSampling,
+    layer: NavLayer,
+    line_of_sight: bool,
+    min_clearance: u16,
+    profile: Option<TraversalProfile>,
+    spread_goals: bool,
+    remove_arrived: bool,
+}
 
+impl FlowFieldBuilder {
+    pub fn new(destination: Vec3) -> Self {
+        let config = FlowFieldConfig::default();
+        Self {
+            destination,
+            extra_destinations: Vec::new(),
+            units: Vec::new(),
+            arrival_radius: None,
+            arrival: None,
+            integration: config.integration,
+            sampling: DirectionSampling::default(),
+            layer: NavLayer::default(),
+            line_of_sight: config.line_of_sight,
+            min_clearance: 0,
+            profile: None,
+            spread_goals: config.spread_goals,
+            remove_arrived: false,
+        }
+    }
+
+    /// Takes the integration method, line of sight and goal spreading of `config`, like flowfields built from an
+    /// [`InitializeFlowFieldEv`].
+    pub fn with_config(mut self, config: &FlowFieldConfig) -> Self {
+        self.integration = config.integration;
+        self.line_of_sight = config.line_of_sight;
+        self.spread_goals = config.spread_goals;
+        self
+    }
+
+    pub fn with_units(mut self, units: &[Entity]) -> Self {
+        self.units.extend_from_slice(units);
+        self
+    }
+
+    /// Further goals, see [`InitializeFlowFieldEv::extra_destinations`].
+    pub fn with_extra_destinations(mut self, destinations: &[Vec3]) -> Self {
+        self.extra_destinations.extend_from_slice(destinations);
+        self
+    }
+
+    /// See [`FlowField::arrival_radius`].
+    pub fn with_arrival_radius(mut self, radius: f32) -> Self {
+        self.arrival_radius = Some(radius);
+        self
+    }
+
+    /// See [`FlowField::arrival`].
+    pub fn with_arrival(mut self, arrival: ArrivalSettings) -> Self {
+        self.arrival = Some(arrival);
+        self
+    }
+
+    pub fn with_integration(mut self, integration: IntegrationMethod) -> Self {
+        self.integration = integration;
+        self
+    }
+
+    pub fn with_sampling(mut self, sampling: DirectionSampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Cost layer the field is integrated over, see [`FlowField::layer`].
+    pub fn with_layer(mut self, layer: NavLayer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_line_of_sight(mut self, line_of_sight: bool) -> Self {
+        self.line_of_sight = line_of_sight;
+        self
+    }
+
+    /// See [`FlowField::min_clearance`], e.g. [`Grid::clearance_for_radius`] of the widest unit.
+    pub fn with_min_clearance(mut self, min_clearance: u16) -> Self {
+        self.min_clearance = min_clearance;
+        self
+    }
+
+    /// See [`FlowField::profile`].
+    pub fn with_profile(mut self, profile: TraversalProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    pub fn with_spread_goals(mut self, spread_goals: bool) -> Self {
+        self.spread_goals = spread_goals;
+        self
+    }
+
+    /// See [`FlowField::remove_arrived`].
+    pub fn with_remove_arrived(mut self, remove_arrived: bool) -> Self {
+        self.remove_arrived = remove_arrived;
+        self
+    }
+
+    /// Integrates the field over `grid`. Insert the result on an entity of your own, the units only follow it once
+    /// they have a [`Destination`].
+    pub fn build(self, grid: &Grid) -> FlowField {
+        let destination_idxs: Vec<IVec2> = std::iter::once(self.destination)
+            .chain(self.extra_destinations)
+            .map(|pos| grid.get_cell_from_world_position(pos).idx)
+            .collect();
+
+        let unit_count = self.units.len() as f32;
+        let mut ff = FlowField::new(grid.size, self.units, unit_count, Vec3::ZERO);
+        if let Some(radius) = self.arrival_radius {
+            ff.arrival_radius = radius;
+        }
+        ff.arrival = self.arrival;
+        ff.integration = self.integration;
+        ff.sampling = self.sampling;
+        ff.layer = self.layer;
+        ff.line_of_sight = self.line_of_sight;
+        ff.min_clearance = self.min_clearance;
+        ff.profile = self.profile.clone();
+        ff.spread_goals = self.spread_goals;
+        ff.remove_arrived = self.remove_arrived;
+        ff.create_integration_field(
+            grid.integration_cells_for(self.layer, self.min_clearance, self.profile.as_ref()),
+            &destination_idxs,
+        );
+        ff.create_flowfield();
+        ff
+    }
+
+    /// Builds the field and spawns it the way an [`InitializeFlowFieldEv`] would: the units get their
+    /// [`Destination`] and leave their previous flowfields, and a [`FlowFieldReady`] is triggered.
+    pub fn spawn(self, cmds: &mut Commands, grid: &Grid) -> Entity {
+        let flowfield = cmds.spawn_empty().id();
+        cmds.trigger(SpawnBuiltFlowFieldEv {
+            flowfield,
+            field: self.build(grid),
+        });
+        flowfield
+    }
+}
+
+// hands a `FlowFieldBuilder::spawn` over to the same install as the flowfields built from orders
+#[derive(Event)]
+struct SpawnBuiltFlowFieldEv {
+    flowfield: Entity,
+    field: FlowField,
+}
+
+/// Settled units a moving unit has to touch before it settles as well.
+const SETTLE_CONTACTS: usize = 2;
+/// Gap, as a share of the unit radius, still counted as touching a settled unit.
+const SETTLE_CONTACT_MARGIN: f32 = 0.25;
+
+pub fn flowfield_group_stop_system(
+    mut cmds: Commands,
+    mut q_ff: Query<(Entity, &mut FlowField, Has<FlowFieldTarget>)>,
+    mut q_tf: Query<(&Transform, &mut FvoAgent)>,
+    q_dest: Query<&Destination>,
+    spatial: Res<SpatialHash>,
+    plane: Res<Plane>,
+) {
+    for (ff_ent, mut ff, chasing) in q_ff.iter_mut() {
+        // 1) Have we already marked an arrival?
+        let mut any_arrived = ff.arrived;
+
+        // fire an arrival event once for every unit that got within the arrival radius
+        let arrival_r2 = ff.arrival_radius * ff.arrival_radius;
+        let newly_arrived: Vec<Entity> = ff
+            .units
+            .iter()
+            .copied()
+            .filter(|u| !ff.arrived_units.contains(u))
+            .filter(|&u| {
+                q_tf.get(u).is_ok_and(|(tf, _)| {
+                    tf.translation
+                        .distance_squared(ff.goal_of(u, tf.translation))
+                        <= arrival_r2
+                })
+            })
+            .collect();
+
+        for agent in newly_arrived {
+            cmds.trigger(AgentArrivedEvent {
+                agent,
+                flowfield: ff_ent,
+            });
+
+            if ff.remove_arrived {
+                ff.remove_unit(agent);
+                cmds.entity(agent).remove::<Destination>();
+            } else {
+                ff.arrived_units.insert(agent);
+            }
+        }
+
+        // 2) Build a set of “arrived” agents (no Destination)
+        let mut arrived: HashSet<Entity> = ff
+            .units
+            .iter()
+            .copied()
+            .filter(|&u| q_dest.get(u).is_err())
+            .collect();
+
+        // 3) If none yet, pick the first within threshold → settle it
+        if !any_arrived {
+            let threshold2 = 25.0;
+            if let Some(&winner) = ff.units.iter().find(|&&u| {
+                q_tf.get(u)
+                    .map(|(tf, _)| {
+                        tf.translation
+                            .distance_squared(ff.goal_of(u, tf.translation))
+                            < threshold2
+                    })
+                    .unwrap_or(false)
+            }) {
+                cmds.entity(winner).remove::<Destination>().insert(Arrived);
+                arrived.insert(winner);
+                any_arrived = true;
+            }
+        }
+
+        // 4) settle every unit close to the goal that presses against enough settled ones, so the crowd packs into
+        //    a blob around the first arrival instead of orbiting it
+        if !arrived.is_empty() {
+            let contacts_needed = arrived.len().min(SETTLE_CONTACTS);
+            let mut settled = Vec::new();
+            for &u in &ff.units {
+                if arrived.contains(&u) || q_dest.get(u).is_err() {
+                    continue;
+                }
+
+                let Ok((tf_u, agent)) = q_tf.get(u) else {
+                    continue;
+                };
+                let pos = tf_u.translation;
+                let radius = agent.settings.radius;
+
+                // units with a slot settle on it, not on whoever they brush past
+                if let Some(&slot) = ff.goal_slots.get(&u) {
+                    if pos.distance(slot) <= radius {
+                        settled.push(u);
+                    }
+                    continue;
+                }
+
+                let settle_range = ff.destination_radius.max(ff.arrival_radius);
+                if pos.distance(ff.nearest_destination(pos)) > settle_range {
+                    continue;
+                }
+
+                let reach = agent.settings.sensor_range.max(radius * 4.0);
+                let contacts = spatial
+                    .neighbors_within(plane.to_plane(pos), reach)
+                    .filter(|other| arrived.contains(other))
+                    .filter(|&other| {
+                        q_tf.get(other).is_ok_and(|(tf_o, other_agent)| {
+                            let touch = radius + other_agent.settings.radius;
+                            pos.distance(tf_o.translation) <= touch + radius * SETTLE_CONTACT_MARGIN
+                        })
+                    })
+                    .count();
+                if contacts >= contacts_needed {
+                    settled.push(u);
+                }
+            }
+
+            for u in settled {
+                cmds.entity(u).remove::<Destination>().insert(Arrived);
+            }
+        }
+
+        // 5) record and potentially despawn
+        ff.arrived = any_arrived;
+        // a chased target may move on again, keep the field while it has units
+        let any_left = ff.units.iter().any(|&u| q_dest.get(u).is_ok());
+        if !any_left && (ff.units.is_empty() || !chasing) {
+            // nothing solves the settled units any more, stop them instead of letting them coast on
+            for &u in &ff.units {
+                if let Ok((_, mut agent)) = q_tf.get_mut(u) {
+                    agent.velocity = Vec3::ZERO;
+                    agent.steering = Vec3::ZERO;
+                }
+            }
+            cmds.entity(ff_ent).despawn();
+        }
+    }
+}
+
+/// The flowfield provider of the [`PreferredVelocity`](crate::preferred::PreferredVelocity): every membership of an
+/// agent adds the flow direction at its position, scaled by the preferred speed, the arrival slowdown and the terrain
+/// cost, weighted by [`FlowField::unit_weight`].
+pub fn flowfield_preferred_velocity(
+    grid: Res<Grid>,
+    config: Res<FvoConfig>,
+    q_ff: Query<(Entity, &FlowField)>,
+    mut q_agents: Query<(&Transform, &FvoAgent, &mut PreferredVelocity)>,
+    q_paths: Query<&PathSmoothing>,
+) {
+    let mut fields: Vec<(Entity, &FlowField)> = q_ff.iter().collect();
+    if config.deterministic {
+        // summation order of the blended preferred velocity
+        fields.sort_unstable_by_key(|&(ent, _)| ent);
+    }
+
+    for (ff_ent, ff) in fields {
+        for &unit in &ff.units {
+            let Ok((tf, agent, mut preferred)) = q_agents.get_mut(unit) else {
+                continue;
+            };
+            let pos = tf.translation;
+            let settings = &agent.settings;
+
+            // preferred velocity = flow direction * target speed
+            let dir2d = ff.sample_direction(pos, &grid);
+            let mut flow_dir = grid.plane.from_2d(dir2d).normalize_or_zero();
+
+            // smoothed path: head for the next waypoint instead of following the staircase of the grid
+            let waypoint = q_paths
+                .get(unit)
+                .ok()
+                .filter(|path| path.flowfield() == Some(ff_ent))
+                .and_then(PathSmoothing::waypoint);
+            if let Some(waypoint) = waypoint {
+                flow_dir = grid.plane.flatten(waypoint - pos).normalize_or_zero();
+            }
+
+            // spread goals: close to the destination, head for the unit's own slot
+            let goal = ff.goal_of(unit, pos);
+            let near_goal = pos.distance(ff.nearest_destination(pos))
+                <= ff.slot_radius + grid.cell_size.max_element();
+            if ff.goal_slots.contains_key(&unit) && near_goal {
+                flow_dir = grid.plane.flatten(goal - pos).normalize_or_zero();
+            }
+
+            if config.out_of_bounds == OutOfBoundsPolicy::SteerBackToGrid && !grid.contains(pos) {
+                flow_dir = grid
+                    .plane
+                    .flatten(grid.clamp_position(pos) - pos)
+                    .normalize_or_zero();
+            }
+
+            // slow down as we approach the goal to reduce overshoot
+            let goal_dist = pos.distance(goal);
+            let speed_scale = ff
+                .arrival
+                .unwrap_or(settings.arrival)
+                .speed_scale(goal_dist, settings.sensor_range);
+
+            // terrain: roads speed agents up, mud slows them down, max_speed still bounds the result
+            let terrain = ff.sample_cost(pos, &grid);
+
+            let speed = settings.preferred_speed * speed_scale * terrain;
+            preferred.add(flow_dir * speed, ff.unit_weight(unit));
+        }
+    }
+}
+
+/// Copies the solved velocity of every unit into the `steering_map` of its flowfields.
+pub fn update_steering_maps(mut q_ff: Query<&mut FlowField>, q_agents: Query<&FvoAgent>) {
+    for mut ff in q_ff.iter_mut() {
+        let ff = &mut *ff;
+        for &unit in &ff.units {
+            if let Ok(agent) = q_agents.get(unit) {
+                ff.steering_map.insert(unit, agent.velocity);
+            }
+        }
+    }
+}
+
+// hands out goal slots whenever a unit of a spreading flowfield has none, e.g. after it joined or the goal moved
+fn spread_flowfield_goals(
+    mut q_ff: Query<&mut FlowField, Without<FlowFieldTask>>,
+    q_tf: Query<&Transform>,
+) {
+    for mut ff in q_ff.iter_mut() {
+        if !ff.spread_goals || ff.units.iter().all(|unit| ff.goal_slots.contains_key(unit)) {
+            continue;
+        }
+
+        let units: Vec<(Entity, Vec3)> = ff
+            .units
+            .iter()
+            .filter_map(|&unit| q_tf.get(unit).ok().map(|tf| (unit, tf.translation)))
+            .collect();
+        ff.assign_goal_slots(&units);
+    }
+}
+
+// moves flowfields after their target once it got far enough from the destination
+fn follow_flowfield_targets(
+    mut cmds: Commands,
+    grid: Res<Grid>,
+    q_ff: Query<(Entity, &FlowField, &FlowFieldTarget), Without<FlowFieldTask>>,
+    q_tf: Query<&Transform>,
+) {
+    for (ff_ent, ff, target) in q_ff.iter() {
+        let Ok(target_tf) = q_tf.get(target.entity) else {
+            cmds.entity(ff_ent).remove::<FlowFieldTarget>();
+            continue;
+        };
+
+        let cell = grid.get_cell_from_world_position(target_tf.translation).idx;
+        let drift = (cell - ff.destination_cell.idx).abs().max_element();
+        if drift > target.retarget_cells {
+            cmds.trigger(RetargetFlowFieldEv {
+                flowfield: ff_ent,
+                destination_pos: target_tf.translation,
+                extra_destinations: Vec::new(),
+            });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn initialize_flowfield(
+    trigger: On<InitializeFlowFieldEv>,
+    mut cmds: Commands,
+    grid: ResMut<Grid>,
+    config: Res<FlowFieldConfig>,
+    sector_graph: Res<SectorGraph>,
+    mut cache: ResMut<FlowFieldCache>,
+    q_tf: Query<&Transform>,
+    q_layers: Query<&NavLayer>,
+    q_profiles: Query<&TraversalProfile>,
+    q_agents: Query<&FvoAgent>,
+    q_entities: Query<Entity>,
+    mut q_ff: Query<(Entity, &mut FlowField)>,
+    mut q_tasks: Query<(Entity, &mut FlowFieldTask)>,
+    q_destination_radius: Query<(Entity, &DestinationRadius)>, // TODO: Remove
+) {
+    let destination_pos = trigger.event().destination_pos;
+    let extra_destinations = trigger.event().extra_destinations.clone();
+    let units = trigger.event().entities.clone();
+    if units.is_empty() {
+        return;
+    }
+
+    let radius_of = |unit: Entity| {
+        q_agents
+            .get(unit)
+            .map_or(0.0, |agent| agent.settings.radius)
+    };
+
+    // every size class ordered gets a flowfield of its own, smallest first
+    let class = config.size_class(radius_of(units[0]));
+    if units
+        .iter()
+        .any(|&unit| config.size_class(radius_of(unit)) != class)
+    {
+        let mut classes: Vec<(usize, Vec<Entity>)> = Vec::new();
+        for &unit in &units {
+            let class = config.size_class(radius_of(unit));
+            match classes.iter_mut().find(|(c, _)| *c == class) {
+                Some((_, class_units)) => class_units.push(unit),
+                None => classes.push((class, vec![unit])),
+            }
+        }
+        classes.sort_by_key(|(class, _)| *class);
+
+        for (_, entities) in classes {
+            cmds.trigger(InitializeFlowFieldEv {
+                entities,
+                destination_pos,
+                extra_destinations: extra_destinations.clone(),
+            });
+        }
+        return;
+    }
+
+    // let world_mouse_pos = utils::get_world_pos(map_base, cam.1, cam.0, cursor_pos);
+    let destination_idxs: Vec<IVec2> = std::iter::once(destination_pos)
+        .chain(extra_destinations)
+        .map(|pos| grid.get_cell_from_world_position(pos).idx)
+        .collect();
+
+    // boats and fliers ordered along with ground units follow the first unit's layer
+    let layer = units
+        .iter()
+        .find_map(|&unit| q_layers.get(unit).ok())
+        .copied()
+        .unwrap_or_default();
+    let profile = q_profiles.get(units[0]).ok().cloned();
+
+    // the widest agent the size class may hold, or else the widest unit, decides which gaps the flowfield may lead
+    // through
+    let widest = || {
+        units
+            .iter()
+            .map(|&unit| radius_of(unit))
+            .fold(0.0, f32::max)
+    };
+    let min_clearance = match config.size_classes.get(class) {
+        Some(&max_radius) => grid.clearance_for_radius(max_radius),
+        None if config.clearance || !config.size_classes.is_empty() => {
+            grid.clearance_for_radius(widest())
+        }
+        None => 0,
+    };
+
+    // a single goal shares the flowfield already serving its cell on the same layer, clearance and profile, or the one
+    // reserved by `FlowFieldCache::request_flowfield`
+    let key = (destination_idxs.len() == 1).then_some(destination_idxs[0]);
+    let target = key
+        .and_then(|idx| {
+            cache
+                .live(idx, layer, min_clearance, profile.as_ref())
+                .or_else(|| cache.take_reserved(idx, layer))
+        })
+        .filter(|ent| q_entities.contains(*ent));
+
+    // the latest order wins over flowfields still being built for the same units
+    for (task_ent, mut task) in q_tasks.iter_mut() {
+        if Some(task_ent) == target {
+            continue;
+        }
+
+        if let Some(task_units) = task.units.as_mut() {
+            task_units.retain(|ent| !units.contains(ent));
+            if task_units.is_empty() {
+                cmds.entity(task_ent).despawn();
+            }
+        }
+    }
+
+    if let Some(ff_ent) = target {
+        // still being built, the units are handed over once it is swapped in
+        if let Ok((_, mut task)) = q_tasks.get_mut(ff_ent) {
+            if let Some(task_units) = task.units.as_mut() {
+                for &unit in &units {
+                    if !task_units.contains(&unit) {
+                        task_units.push(unit);
+                    }
+                }
+                return;
+            }
+        }
+
+        if q_ff.contains(ff_ent) {
+            claim_units(&mut cmds, &mut q_ff, &q_destination_radius, &units, ff_ent);
+            if let Ok((_, mut ff)) = q_ff.get_mut(ff_ent) {
+                for &unit in &units {
+                    if !ff.units.contains(&unit) {
+                        ff.add_unit(unit);
+                    }
+                }
+            }
+            return;
+        }
+    }
+
+    // reserved by `FlowFieldCache::request_flowfield`, or a new one
+    let ff_ent = target.unwrap_or_else(|| cmds.spawn_empty().id());
+    if let Some(idx) = key {
+        cache.set_live(idx, layer, min_clearance, profile.as_ref(), ff_ent);
+    }
+
+    // integrated for the same cell before, only the units are new
+    let cached = key
+        .and_then(|idx| cache.get(idx, layer, min_clearance, profile.as_ref()))
+        .filter(|ff| {
+            ff.integration == config.integration && ff.line_of_sight == config.line_of_sight
+        });
+    if let Some(mut ff) = cached {
+        ff.spread_goals = config.spread_goals;
+        // same as `FlowField::new`
+        ff.destination_radius = (units.len() as f32 * units.len() as f32).sqrt() * 3.0;
+        for &unit in &units {
+            ff.add_unit(unit);
+        }
+        install_flowfield(
+            &mut cmds,
+            &mut cache,
+            &mut q_ff,
+            &q_destination_radius,
+            ff_ent,
+            ff,
+        );
+        return;
+    }
+
+    // large maps: only integrate the sectors on the way from the units to the goals, the sector graph is built over
+    // the ground costs
+    let sectors = (config.hierarchical && uses_sector_graph(layer, profile.as_ref()))
+        .then(|| corridor(&grid, &sector_graph, &q_tf, &units, &destination_idxs))
+        .flatten();
+
+    let cells = grid.integration_cells_for(layer, min_clearance, profile.as_ref());
+    let size = grid.size;
+    let sector_size = sector_graph.sector_size();
+    let integration = config.integration;
+    let line_of_sight = config.line_of_sight;
+    let spread_goals = config.spread_goals;
+    let task_units = units.clone();
+    let build = move || {
+        let unit_count = task_units.len() as f32;
+        let mut ff = FlowField::new(size, task_units, unit_count, Vec3::ZERO);
+        ff.sectors = sectors;
+        ff.sector_size = sector_size;
+        ff.integration = integration;
+        ff.line_of_sight = line_of_sight;
+        ff.min_clearance = min_clearance;
+        ff.layer = layer;
+        ff.profile = profile;
+        ff.spread_goals = spread_goals;
+        ff.create_integration_field(cells, &destination_idxs);
+        ff.create_flowfield();
+        ff
+    };
+
+    if config.async_compute {
+        let task = AsyncComputeTaskPool::get().spawn(async move { build() });
+        cmds.entity(ff_ent).insert(FlowFieldTask {
+            task,
+            units: Some(units),
+        });
+    } else {
+        install_flowfield(
+            &mut cmds,
+            &mut cache,
+            &mut q_ff,
+            &q_destination_radius,
+            ff_ent,
+            build(),
+        );
+    }
+}
+
+fn spawn_built_flowfield(
+    trigger: On<SpawnBuiltFlowFieldEv>,
+    mut cmds: Commands,
+    mut cache: ResMut<FlowFieldCache>,
+    mut q_ff: Query<(Entity, &mut FlowField)>,
+    q_destination_radius: Query<(Entity, &DestinationRadius)>, // TODO: Remove
+) {
+    let ev = trigger.event();
+    install_flowfield(
+        &mut cmds,
+        &mut cache,
+        &mut q_ff,
+        &q_destination_radius,
+        ev.flowfield,
+        ev.field.clone(),
+    );
+}
+
+type Passage<'a> = (NavLayer, Option<&'a TraversalProfile>);
+
+// splits an order by nav layer and traversal profile, each needs a flowfield integrated over its own costs
+fn move_order(
+    trigger: On<MoveOrderEvent>,
+    mut cmds: Commands,
+    q_agents: Query<(Option<&NavLayer>, Option<&TraversalProfile>), With<FvoAgent>>,
+) {
+    let ev = trigger.event();
+    let mut seen = HashSet::new();
+    let mut orders: Vec<(Passage, Vec<Entity>)> = Vec::new();
+    for &unit in &ev.units {
+        let Ok((layer, profile)) = q_agents.get(unit) else {
+            continue;
+        };
+        if !seen.insert(unit) {
+            continue;
+        }
+
+        let layer = (layer.copied().unwrap_or_default(), profile);
+        match orders
+            .iter_mut()
+            .find(|(order_layer, _)| *order_layer == layer)
+        {
+            Some((_, units)) => units.push(unit),
+            None => orders.push((layer, vec![unit])),
+        }
+    }
+
+    for (_, units) in orders {
+        cmds.trigger(InitializeFlowFieldEv {
+            entities: units,
+            destination_pos: ev.destination,
+            extra_destinations: Vec::new(),
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn retarget_flowfield(
+    trigger: On<RetargetFlowFieldEv>,
+    mut cmds: Commands,
+    grid: Res<Grid>,
+    config: Res<FlowFieldConfig>,
+    sector_graph: Res<SectorGraph>,
+    mut cache: ResMut<FlowFieldCache>,
+    q_tf: Query<&Transform>,
+    mut q_ff: Query<&mut FlowField>,
+) {
+    let ev = trigger.event();
+    let ff_ent = ev.flowfield;
+    let Ok(mut ff) = q_ff.get_mut(ff_ent) else {
+        return;
+    };
+
+    let destination_idxs: Vec<IVec2> = std::iter::once(ev.destination_pos)
+        .chain(ev.extra_destinations.iter().copied())
+        .map(|pos| grid.get_cell_from_world_position(pos).idx)
+        .collect();
+
+    // units that arrived at the old goal had their Destination removed
+    for &unit in &ff.units {
+        cmds.entity(unit).insert(Destination);
+    }
+
+    cache.remove_live(ff_ent);
+    if let [idx] = destination_idxs.as_slice() {
+        cache.set_live(
+            *idx,
+            ff.layer,
+            ff.min_clearance,
+            ff.profile.as_ref(),
+            ff_ent,
+        );
+    }
+
+    let sectors = (config.hierarchical && uses_sector_graph(ff.layer, ff.profile.as_ref()))
+        .then(|| corridor(&grid, &sector_graph, &q_tf, &ff.units, &destination_idxs))
+        .flatten();
+
+    // units keep following the old goal until the new field is swapped in
+    if config.async_compute {
+        let cells = grid.integration_cells_for(ff.layer, ff.min_clearance, ff.profile.as_ref());
+        let mut rebuilt = FlowField {
+            integration: ff.integration,
+            line_of_sight: ff.line_of_sight,
+            min_clearance: ff.min_clearance,
+            profile: ff.profile.clone(),
+            layer: ff.layer,
+            sectors,
+            sector_size: sector_graph.sector_size(),
+            size: ff.size,
+            ..default()
+        };
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            rebuilt.create_integration_field(cells, &destination_idxs);
+            rebuilt.create_flowfield();
+            rebuilt
+        });
+        cmds.entity(ff_ent)
+            .insert(FlowFieldTask { task, units: None });
+        return;
+    }
+
+    ff.sectors = sectors;
+    ff.sector_size = sector_graph.sector_size();
+    let cells = grid.integration_cells_for(ff.layer, ff.min_clearance, ff.profile.as_ref());
+    ff.retarget(cells, &destination_idxs);
+
+    cmds.trigger(FlowFieldReady { flowfield: ff_ent });
+    cmds.trigger(SetActiveFlowfieldEv(Some(ff.clone())));
+}
+
+// slab test of the segment `from`-`to` against `rect`, edges included
+fn segment_hits_rect(from: Vec2, to: Vec2, rect: Rect) -> bool {
+    let delta = to - from;
+    let (mut t_min, mut t_max) = (0.0f32, 1.0f32);
+    for axis in 0..2 {
+        if delta[axis].abs() <= f32::EPSILON {
+            if from[axis] < rect.min[axis] || from[axis] > rect.max[axis] {
+                return false;
+            }
+            continue;
+        }
+
+        let t0 = (rect.min[axis] - from[axis]) / delta[axis];
+        let t1 = (rect.max[axis] - from[axis]) / delta[axis];
+        t_min = t_min.max(t0.min(t1));
+        t_max = t_max.min(t0.max(t1));
+        if t_min > t_max {
+            return false;
+        }
+    }
+
+    true
+}
+
+// the sector graph is built over the plain ground costs, units that weigh them differently may not fit its routes
+fn uses_sector_graph(layer: NavLayer, profile: Option<&TraversalProfile>) -> bool {
+    layer == NavLayer::Ground && profile.is_none_or(|profile| profile.impassable.is_empty())
+}
+
+// union of the sector routes from every unit to the goals, `None` if the graph is stale or no goal is reachable
+fn corridor(
+    grid: &Grid,
+    sector_graph: &SectorGraph,
+    q_tf: &Query<&Transform>,
+    units: &[Entity],
+    goals: &[IVec2],
+) -> Option<HashSet<IVec2>> {
+    if sector_graph.grid_size() != grid.size {
+        return None;
+    }
+
+    let mut sectors = HashSet::new();
+    let mut routed = HashSet::new();
+    for tf in units.iter().filter_map(|&unit| q_tf.get(unit).ok()) {
+        let cell = grid.get_cell_from_world_position(tf.translation).idx;
+        if routed.insert(sector_graph.sector_of(cell)) {
+            sectors.extend(sector_graph.route(grid, cell, goals)?);
+        }
+    }
+
+    (!sectors.is_empty()).then_some(sectors)
+}
+
+// keeps the sector graph in sync with the grid while hierarchical flowfields are enabled
+fn update_sector_graph(
+    config: Res<FlowFieldConfig>,
+    grid: Res<Grid>,
+    mut sector_graph: ResMut<SectorGraph>,
+) {
+    if !config.hierarchical {
+        return;
+    }
+
+    let stale = sector_graph.sector_size() != config.sector_size.max(1)
+        || sector_graph.grid_size() != grid.size;
+    if grid.is_changed() || stale {
+        *sector_graph = SectorGraph::build(&grid, config.sector_size);
+    }
+}
+
+// generates the fine field of the sectors on demand when a unit strays out of its flowfield's corridor
+fn extend_flowfield_corridors(
+    grid: Res<Grid>,
+    sector_graph: Res<SectorGraph>,
+    mut q_ff: Query<&mut FlowField, Without<FlowFieldTask>>,
+    q_tf: Query<&Transform>,
+) {
+    for mut ff in q_ff.iter_mut() {
+        if ff.sectors.is_none() || ff.sector_size != sector_graph.sector_size() {
+            continue;
+        }
+
+        let goals: Vec<IVec2> = ff.destination_cells.iter().map(|c| c.idx).collect();
+        let mut added: HashSet<IVec2> = HashSet::new();
+        for tf in ff.units.iter().filter_map(|&unit| q_tf.get(unit).ok()) {
+            let cell = grid.get_cell_from_world_position(tf.translation).idx;
+            if ff.in_corridor(cell) || added.contains(&sector_graph.sector_of(cell)) {
+                continue;
+            }
+
+            if let Some(route) = sector_graph.route(&grid, cell, &goals) {
+                added.extend(route);
+            }
+        }
+
+        let ff = &mut *ff;
+        let Some(sectors) = ff.sectors.as_mut() else {
+            continue;
+        };
+        added.retain(|sector| !sectors.contains(sector));
+        if added.is_empty() {
+            continue;
+        }
+        sectors.extend(added.iter().copied());
+
+        // the new sectors are integrated from the corridor around them by the dirty region refresh
+        let size = IVec2::splat(ff.sector_size);
+        for sector in added {
+            let region = IRect::from_corners(sector * size, sector * size + size - IVec2::ONE);
+            ff.dirty_region = Some(match ff.dirty_region {
+                Some(dirty) => dirty.union(region),
+                None => region,
+            });
+        }
+    }
+}
+
+// moves the units of a freshly built flowfield off their old ones and spawns it on `ff_ent`
+fn install_flowfield(
+    cmds: &mut Commands,
+    cache: &mut FlowFieldCache,
+    q_ff: &mut Query<(Entity, &mut FlowField)>,
+    q_destination_radius: &Query<(Entity, &DestinationRadius)>, // TODO: Remove
+    ff_ent: Entity,
+    ff: FlowField,
+) {
+    claim_units(cmds, q_ff, q_destination_radius, &ff.units, ff_ent);
+
+    // keep the integrated field around for later orders to the same cell
+    if let [destination] = ff.destination_cells.as_slice() {
+        cache.insert(destination.idx, &ff);
+        cache.set_live(
+            destination.idx,
+            ff.layer,
+            ff.min_clearance,
+            ff.profile.as_ref(),
+            ff_ent,
+        );
+    }
+
+    // Spawn the new flowfield
+    cmds.entity(ff_ent).insert((
+        ff.clone(),
+        Name::new("ParentFlowField"),
+        Transform::default(),
+        GlobalTransform::default(),
+    ));
+
+    cmds.trigger(FlowFieldReady { flowfield: ff_ent });
+    cmds.trigger(SetActiveFlowfieldEv(Some(ff)));
+}
+
+// gives `units` a Destination and removes them from every flowfield but `keep`, despawning the ones left empty
+fn claim_units(
+    cmds: &mut Commands,
+    q_ff: &mut Query<(Entity, &mut FlowField)>,
+    q_destination_radius: &Query<(Entity, &DestinationRadius)>, // TODO: Remove
+    units: &[Entity],
+    keep: Entity,
+) {
     // insert Destination component to all units
     for unit in units.iter() {
         cmds.entity(*unit).insert(Destination);
     }
 
     // Remove existing flowfields that contain any of the units
-    for (ff_ent, mut ff) in q_ff.iter_mut() {
+    for (old_ent, mut old) in q_ff.iter_mut() {
+        if old_ent == keep {
+            continue;
+        }
+
         // 1) Filter out any units from `flowfield.units` that are in `units`
         //    i.e. the ones that are about to be added to the new flowfield.
-        ff.units.retain(|ent| !units.contains(ent));
+        old.units.retain(|ent| !units.contains(ent));
 
-        ff.steering_map.retain(|ent, _| !units.contains(ent));
+        old.steering_map.retain(|ent, _| !units.contains(ent));
+        old.weights.retain(|ent, _| !units.contains(ent));
+        old.arrived_units.retain(|ent| !units.contains(ent));
 
         // 2) If after removal, the flowfield is now empty, *then* despawn it.
-        if ff.units.is_empty() {
-            cmds.entity(ff_ent).despawn();
+        if old.units.is_empty() {
+            cmds.entity(old_ent).despawn();
 
             // Also remove any "destination radius" entity that references this flowfield
             // TODO: Remove
             for (ent, d) in q_destination_radius.iter() {
-                if d.0 == ff_ent.index() {
+                if d.0 == old_ent.index() {
                     cmds.entity(ent).despawn();
                 }
             }
         }
     }
+}
 
-    // let world_mouse_pos = utils::get_world_pos(map_base, cam.1, cam.0, cursor_pos);
-    let destination_cell = grid.get_cell_from_world_position(destination_pos);
+// swaps in every flowfield whose background build finished
+fn poll_flowfield_tasks(
+    mut cmds: Commands,
+    mut q_tasks: Query<(Entity, &mut FlowFieldTask)>,
+    mut q_ff: Query<(Entity, &mut FlowField)>,
+    q_destination_radius: Query<(Entity, &DestinationRadius)>, // TODO: Remove
+    mut cache: ResMut<FlowFieldCache>,
+) {
+    for (task_ent, mut task) in q_tasks.iter_mut() {
+        let Some(mut built) = block_on(future::poll_once(&mut task.task)) else {
+            continue;
+        };
+        cmds.entity(task_ent).remove::<FlowFieldTask>();
+
+        match task.units.take() {
+            Some(units) => {
+                // some units may have been ordered elsewhere while this was being built
+                let dropped: Vec<Entity> = built
+                    .units
+                    .iter()
+                    .copied()
+                    .filter(|unit| !units.contains(unit))
+                    .collect();
+                for unit in dropped {
+                    built.remove_unit(unit);
+                }
 
-    let mut ff = FlowField::new(grid.size, units.clone(), units.len() as f32, Vec3::ZERO);
+                // and others joined it through the cache
+                for unit in units {
+                    if !built.units.contains(&unit) {
+                        built.add_unit(unit);
+                    }
+                }
 
-    ff.create_integration_field(grid.grid.clone(), destination_cell.idx);
-    ff.create_flowfield();
-    // Spawn the new flowfield
-    // cmds.spawn(flowfield.clone()); // TODO: Uncomment
-    let _ff_ent = cmds
-        .spawn((
-            ff.clone(),
-            Name::new("ParentFlowField"),
-            Transform::default(),
-            GlobalTransform::default(),
-        ))
-        .id();
-
-    // TODO: Remove (debugging purposes)
-    {
-        // let mesh = Mesh3d(_meshes.add(Cylinder::new(ff.destination_radius, 2.0)));
-        // let material = MeshMaterial3d(_materials.add(Color::srgba(1.0, 1.0, 0.33, 0.85)));
-        // cmds.entity(_ff_ent).with_children(|parent| {
-        //     parent.spawn((
-        //         DestinationRadius(_ff_ent.index()),
-        //         mesh,
-        //         material,
-        //         Transform::from_translation(ff.destination_cell.world_pos),
-        //     ));
-        // });
+                install_flowfield(
+                    &mut cmds,
+                    &mut cache,
+                    &mut q_ff,
+                    &q_destination_radius,
+                    task_ent,
+                    built,
+                );
+            }
+            None => {
+                let Ok((_, mut ff)) = q_ff.get_mut(task_ent) else {
+                    continue;
+                };
+
+                // retargeted: arrivals at the old goals no longer count
+                let mut old = std::mem::take(&mut *ff);
+                if old.destination_cells != built.destination_cells {
+                    old.arrived = false;
+                    old.arrived_units.clear();
+                    old.goal_slots.clear();
+                }
+
+                // the rebuild replaces the field, only the state of the entity and its units carries over
+                *ff = FlowField {
+                    arrived: old.arrived,
+                    arrival: old.arrival,
+                    arrival_radius: old.arrival_radius,
+                    arrived_units: old.arrived_units,
+                    destination_radius: old.destination_radius,
+                    dirty_region: old.dirty_region,
+                    goal_slots: old.goal_slots,
+                    offset: old.offset,
+                    remove_arrived: old.remove_arrived,
+                    sampling: old.sampling,
+                    slot_radius: old.slot_radius,
+                    spread_goals: old.spread_goals,
+                    steering_map: old.steering_map,
+                    units: old.units,
+                    weights: old.weights,
+                    ..built
+                };
+
+                cmds.trigger(FlowFieldReady {
+                    flowfield: task_ent,
+                });
+                cmds.trigger(SetActiveFlowfieldEv(Some(ff.clone())));
+            }
+        }
     }
+}
 
-    cmds.trigger(SetActiveFlowfieldEv(Some(ff)));
+// a new order gets settled units moving again
+fn unsettle_on_order(trigger: On<Insert, Destination>, mut cmds: Commands) {
+    cmds.entity(trigger.entity).remove::<Arrived>();
+}
+
+// a despawned flowfield no longer serves its destination, later orders start from the cached field
+fn forget_flowfield(trigger: On<Remove, FlowField>, mut cache: ResMut<FlowFieldCache>) {
+    cache.remove_live(trigger.entity);
+}
+
+// every flowfield spans the whole grid, so any cost edit affects all of them
+fn mark_flowfields_dirty(
+    trigger: On<CostFieldChangedEv>,
+    mut cmds: Commands,
+    mut q_ff: Query<&mut FlowField>,
+    mut cache: ResMut<FlowFieldCache>,
+) {
+    let region = trigger.event().region;
+    cache.mark_dirty(region);
+
+    // debug feature only
+    if q_ff.is_empty() {
+        cmds.trigger(DrawCostFieldEv);
+        return;
+    }
+
+    for mut ff in q_ff.iter_mut() {
+        ff.dirty_region = Some(match ff.dirty_region {
+            Some(dirty) => dirty.union(region),
+            None => region,
+        });
+    }
+}
+
+// re-integrates the regions marked by cost edits, once per frame however many edits came in
+fn refresh_dirty_flowfields(
+    mut cmds: Commands,
+    mut q_ff: Query<&mut FlowField, Without<FlowFieldTask>>,
+    grid: Res<Grid>,
+) {
+    // a field being rebuilt keeps its region dirty, the rebuild may have started from older costs
+    let mut active_ff = None;
+    for mut ff in q_ff.iter_mut() {
+        let Some(region) = ff.dirty_region.take() else {
+            continue;
+        };
+
+        ff.update_region(&grid, region);
+
+        active_ff = Some(ff.clone());
+    }
+
+    // debug feature only
+    if active_ff.is_some() {
+        cmds.trigger(SetActiveFlowfieldEv(active_ff));
+    }
 }
 
 // TODO: Causes huge performance dip
@@ -384,9 +2229,14 @@ fn initialize_flowfield(
 fn update_fields(
     _trigger: On<UpdateCostEv>,
     mut cmds: Commands,
-    mut q_ff: Query<&mut FlowField>,
+    mut q_ff: Query<(Entity, &mut FlowField)>,
     grid: Res<Grid>,
+    config: Res<FlowFieldConfig>,
+    mut cache: ResMut<FlowFieldCache>,
 ) {
+    // cached fields would need a full rebuild as well, cheaper to integrate them again when reused
+    cache.clear();
+
     // if there is not FF, then we still want to draw the cost field
     // debug feature only
     if q_ff.is_empty() {
@@ -395,15 +2245,77 @@ fn update_fields(
     }
 
     let mut active_ff = None;
-    for mut ff in q_ff.iter_mut() {
-        let dest_idx = ff.destination_cell.idx;
-        ff.create_integration_field(grid.grid.clone(), dest_idx);
+    for (ff_ent, mut ff) in q_ff.iter_mut() {
+        // the grid may have been rebuilt with another layout, find the goals again by their position
+        let old_idxs: Vec<IVec2> = ff.destination_cells.iter().map(|c| c.idx).collect();
+        let mut dest_idxs: Vec<IVec2> = Vec::new();
+        for cell in ff.destination_cells.iter() {
+            let idx = grid.get_cell_from_world_position(cell.world_pos).idx;
+            if !dest_idxs.contains(&idx) {
+                dest_idxs.push(idx);
+            }
+        }
+
+        if let ([old], [new]) = (old_idxs.as_slice(), dest_idxs.as_slice()) {
+            if old != new
+                && cache.live(*old, ff.layer, ff.min_clearance, ff.profile.as_ref()) == Some(ff_ent)
+            {
+                cache.remove_live(ff_ent);
+                cache.set_live(
+                    *new,
+                    ff.layer,
+                    ff.min_clearance,
+                    ff.profile.as_ref(),
+                    ff_ent,
+                );
+            }
+        }
+
+        // the sector route was planned on the old grid, integrate the whole new one instead
+        let resized = ff.size != grid.size;
+        let sectors = if resized { None } else { ff.sectors.clone() };
+
+        // units keep following the stale field until the rebuild is swapped in, replacing a pending task cancels it
+        if config.async_compute {
+            let cells = grid.integration_cells_for(ff.layer, ff.min_clearance, ff.profile.as_ref());
+            let mut rebuilt = FlowField {
+                integration: ff.integration,
+                line_of_sight: ff.line_of_sight,
+                min_clearance: ff.min_clearance,
+                profile: ff.profile.clone(),
+                layer: ff.layer,
+                sectors,
+                sector_size: ff.sector_size,
+                size: grid.size,
+                ..default()
+            };
+            let task = AsyncComputeTaskPool::get().spawn(async move {
+                rebuilt.create_integration_field(cells, &dest_idxs);
+                rebuilt.create_flowfield();
+                rebuilt
+            });
+            cmds.entity(ff_ent)
+                .insert(FlowFieldTask { task, units: None });
+            continue;
+        }
+
+        if resized {
+            ff.size = grid.size;
+            ff.sectors = sectors;
+            ff.dirty_region = None;
+        }
+
+        let cells = grid.integration_cells_for(ff.layer, ff.min_clearance, ff.profile.as_ref());
+        ff.create_integration_field(cells, &dest_idxs);
         ff.create_flowfield();
+        cmds.trigger(FlowFieldReady { flowfield: ff_ent });
 
         active_ff = Some(ff.clone());
     }
 
     // TODO: This does not work perfectly. It will set the last flowfield as the active one.
     // debug feature only
-    cmds.trigger(SetActiveFlowfieldEv(active_ff));
+    if active_ff.is_some() {
+        cmds.trigger(SetActiveFlowfieldEv(active_ff));
+    }
 }
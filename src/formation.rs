@@ -0,0 +1,272 @@
+//! Keeps squads in shape while they follow a flowfield.
+//!
+//! A [`Formation`] is an entity of its own, the anchor. It travels along the flowfield of its members at the pace of
+//! the slowest one and hands every member a [`FormationSlot`] around it. The member's preferred velocity is blended
+//! with the velocity toward its slot, so the squad keeps its shape but still avoids everyone else.
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{Arrived, FvoAgent, FvoSettings},
+    flowfield::FlowField,
+    fvo::{solver_dt, FvoConfig},
+    grid::Grid,
+    plane::Plane,
+    preferred::PreferredVelocity,
+};
+
+/// Arrangement of the slots around the anchor. Members fill the slots in the order of [`Formation::members`], the
+/// first one at the front.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub enum FormationShape {
+    /// A single rank across the direction of travel.
+    Line,
+    /// Ranks of `columns` members, the first rank at the anchor and the following ones behind it.
+    Box { columns: usize },
+    /// A V with the first member at its tip and the others alternating left and right behind it.
+    Wedge,
+    /// Slot offsets in world units, `x` to the right and `y` forward. Members without an offset take the anchor.
+    Custom(Vec<Vec2>),
+}
+
+impl FormationShape {
+    /// Offset of every slot, `x` to the right and `y` forward of the anchor.
+    pub fn offsets(&self, count: usize, spacing: f32) -> Vec<Vec2> {
+        match self {
+            FormationShape::Line => {
+                let center = (count.max(1) - 1) as f32 / 2.0;
+                (0..count)
+                    .map(|i| Vec2::new((i as f32 - center) * spacing, 0.0))
+                    .collect()
+            }
+            FormationShape::Box { columns } => {
+                let columns = (*columns).max(1);
+                let center = (columns.min(count).max(1) - 1) as f32 / 2.0;
+                (0..count)
+                    .map(|i| {
+                        let (row, column) = (i / columns, i % columns);
+                        Vec2::new((column as f32 - center) * spacing, -(row as f32) * spacing)
+                    })
+                    .collect()
+            }
+            FormationShape::Wedge => (0..count)
+                .map(|i| {
+                    let rank = i.div_ceil(2);
+                    let side = if i % 2 == 1 { -1.0 } else { 1.0 };
+                    Vec2::new(side * rank as f32, -(rank as f32)) * spacing
+                })
+                .collect(),
+            FormationShape::Custom(offsets) => (0..count)
+                .map(|i| offsets.get(i).copied().unwrap_or_default())
+                .collect(),
+        }
+    }
+}
+
+/// Anchor of a squad that keeps its members in shape. Spawn it with a `Transform` at the squad's position, facing
+/// the way it should set out, and order the members onto a flowfield as usual. The anchor then follows the flowfield
+/// of its first member that is on one, and stays put while none is.
+///
+/// # Example
+///
+/// ```
+/// let squad: Vec<Entity> = q_selected.iter().collect();
+/// cmds.spawn((
+///     Formation::new(FormationShape::Box { columns: 4 }, squad.clone()).with_spacing(6.0),
+///     Transform::from_translation(centroid),
+/// ));
+/// cmds.trigger(InitializeFlowFieldEv {
+///     entities: squad,
+///     destination_pos,
+///     extra_destinations: Vec::new(),
+/// });
+/// ```
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+#[require(Transform)]
+pub struct Formation {
+    pub shape: FormationShape,
+    /// Distance between neighboring slots, unused by [`FormationShape::Custom`].
+    pub spacing: f32,
+    pub members: Vec<Entity>,
+    /// Blend between the flow velocity (`0.0`) and the velocity toward the slot (`1.0`) of every member.
+    pub slot_weight: f32,
+    /// How quickly members close the gap to their slot, the share of the distance covered per second.
+    pub slot_gain: f32,
+    /// Travel speed of the anchor, `None` for the lowest [`FvoSettings::preferred_speed`] of the members.
+    ///
+    /// [`FvoSettings::preferred_speed`]: crate::components::FvoSettings::preferred_speed
+    pub speed: Option<f32>,
+}
+
+impl Formation {
+    pub fn new(shape: FormationShape, members: Vec<Entity>) -> Self {
+        Self {
+            shape,
+            spacing: 5.0,
+            members,
+            slot_weight: 0.7,
+            slot_gain: 2.0,
+            speed: None,
+        }
+    }
+
+    pub fn with_spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    pub fn with_slot_weight(mut self, slot_weight: f32) -> Self {
+        self.slot_weight = slot_weight;
+        self
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+}
+
+/// Where a [`Formation`] member should be, written by the formation every tick. Inserted and removed with the
+/// membership.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct FormationSlot {
+    pub formation: Entity,
+    pub position: Vec3,
+    /// Velocity of the anchor, kept up by the member on top of closing the gap.
+    pub velocity: Vec3,
+    /// See [`Formation::slot_weight`].
+    pub weight: f32,
+    /// See [`Formation::slot_gain`].
+    pub gain: f32,
+}
+
+impl FormationSlot {
+    /// Blends `preferred_vel` with the velocity that keeps an agent at `position` on the slot, up to `max_speed`.
+    /// Only the offset along `plane` is closed.
+    pub fn steer(&self, position: Vec3, preferred_vel: Vec3, max_speed: f32, plane: Plane) -> Vec3 {
+        let to_slot = plane.flatten(self.position - position);
+        let slot_vel = (self.velocity + to_slot * self.gain).clamp_length_max(max_speed);
+        preferred_vel.lerp(slot_vel, self.weight.clamp(0.0, 1.0))
+    }
+}
+
+// moves every anchor along the flowfield of its members and hands out the slots
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_formations(
+    mut cmds: Commands,
+    time: Res<Time>,
+    fixed_time: Res<Time<Fixed>>,
+    config: Res<FvoConfig>,
+    grid: Res<Grid>,
+    plane: Res<Plane>,
+    mut q_formations: Query<(Entity, &mut Transform, &Formation), Without<FvoAgent>>,
+    q_members: Query<(&Transform, &FvoAgent)>,
+    mut q_slots: Query<(Entity, &mut FormationSlot)>,
+    q_ff: Query<&FlowField>,
+) {
+    let dt = solver_dt(&config, &time, &fixed_time);
+
+    for (formation_ent, mut tf, formation) in q_formations.iter_mut() {
+        let offsets = formation
+            .shape
+            .offsets(formation.members.len(), formation.spacing);
+        let slot_position = |tf: &Transform, offset: Vec2| {
+            let forward = plane.forward(tf.rotation);
+            let right = forward.cross(plane.up());
+            tf.translation + right * offset.x + forward * offset.y
+        };
+
+        // how far the members trail their slots, the anchor waits for stragglers
+        let mut lag = 0.0;
+        let mut count = 0.0;
+        let mut slowest: Option<&FvoSettings> = None;
+        for (&member, &offset) in formation.members.iter().zip(&offsets) {
+            if let Ok((member_tf, agent)) = q_members.get(member) {
+                lag += member_tf.translation.distance(slot_position(&tf, offset));
+                count += 1.0;
+                if slowest.is_none_or(|s| agent.settings.preferred_speed < s.preferred_speed) {
+                    slowest = Some(&agent.settings);
+                }
+            }
+        }
+
+        let ff = formation
+            .members
+            .iter()
+            .find_map(|member| q_ff.iter().find(|ff| ff.units.contains(member)));
+
+        let mut velocity = Vec3::ZERO;
+        if let (Some(ff), Some(slowest)) = (ff, slowest) {
+            let pos = tf.translation;
+            let dir = ff.sample_direction(pos, &grid);
+            let goal_dist = pos.distance(ff.nearest_destination(pos));
+            // like the members, the flowfield's arrival comes first
+            let speed_scale = ff
+                .arrival
+                .unwrap_or(slowest.arrival)
+                .speed_scale(goal_dist, slowest.sensor_range);
+            let catch_up =
+                (formation.spacing / (lag / count).max(formation.spacing)).clamp(0.25, 1.0);
+            let speed = formation.speed.unwrap_or(slowest.preferred_speed);
+
+            velocity = plane.from_2d(dir).normalize_or_zero() * speed * speed_scale * catch_up;
+        }
+
+        tf.translation += velocity * dt;
+        if velocity.length_squared() > f32::EPSILON {
+            tf.rotation = plane.facing(velocity);
+        }
+
+        for (&member, &offset) in formation.members.iter().zip(&offsets) {
+            let slot = FormationSlot {
+                formation: formation_ent,
+                position: slot_position(&tf, offset),
+                velocity,
+                weight: formation.slot_weight,
+                gain: formation.slot_gain,
+            };
+
+            match q_slots.get_mut(member) {
+                Ok((_, mut current)) => *current = slot,
+                Err(_) => {
+                    if q_members.contains(member) {
+                        cmds.entity(member).insert(slot);
+                    }
+                }
+            }
+        }
+    }
+
+    // members that left their formation, or whose formation is gone, steer freely again
+    for (member, slot) in q_slots.iter() {
+        let kept = q_formations
+            .get(slot.formation)
+            .is_ok_and(|(_, _, formation)| formation.members.contains(&member));
+        if !kept {
+            cmds.entity(member).remove::<FormationSlot>();
+        }
+    }
+}
+
+// pulls formation members toward their slot on top of what every other provider wants, settled members only give way
+pub(crate) fn formation_preferred_velocity(
+    plane: Res<Plane>,
+    mut q_members: Query<
+        (
+            &Transform,
+            &FvoAgent,
+            &FormationSlot,
+            &mut PreferredVelocity,
+        ),
+        Without<Arrived>,
+    >,
+) {
+    for (tf, agent, slot, mut preferred) in q_members.iter_mut() {
+        if let Some(velocity) = preferred.get() {
+            let max_speed = agent.settings.max_speed;
+            preferred.set(slot.steer(tf.translation, velocity, max_speed, *plane));
+        }
+    }
+}
@@ -1,272 +1,2716 @@
-use std::{collections::HashMap, f32::consts::PI};
+use std::{f32::consts::PI, time::Duration};
 
+#[cfg(feature = "debug")]
+use bevy::color::palettes::css::{DEEP_SKY_BLUE, LIME, ORANGE, RED, YELLOW};
 use bevy::{
-    color::palettes::css::{RED, YELLOW},
+    ecs::schedule::{InternedScheduleLabel, ScheduleLabel},
+    platform::{
+        collections::{HashMap, HashSet},
+        time::Instant,
+    },
     prelude::*,
+    utils::Parallel,
 };
+use wide::{f32x4, CmpGt, CmpLe, CmpLt};
 
-use crate::{components::*, debug::resources::DbgOptions, flowfield::FlowField, grid::Grid};
+#[cfg(feature = "debug")]
+use crate::debug::resources::DbgOptions;
+use crate::{
+    components::*,
+    diagnostics::FvoDiagnostics,
+    events::{AgentCollisionEvent, AgentStateChangedEvent},
+    flowfield::{flowfield_preferred_velocity, update_steering_maps},
+    formation::{
+        formation_preferred_velocity, update_formations, Formation, FormationShape, FormationSlot,
+    },
+    grid::Grid,
+    kd_tree::KdTree,
+    lod::{update_lod, FvoLod, FvoLodSettings, LodLevel},
+    plane::Plane,
+    preferred::{
+        clear_preferred_velocities, provide_preferred_velocity, PreferredVelocity,
+        PreferredVelocityFn,
+    },
+    smoothing::{update_path_smoothing, PathSmoothing},
+    spatial_hash::SpatialHash,
+    steering::{update_steering, Steering, SteeringBehavior, SteeringTarget},
+    stuck::{stuck_preferred_velocity, update_stuck_detection, StuckDetection, StuckRecovery},
+    trajectory::{record_trajectories, replay_trajectories, ReplayMode, TrajectoryRecorder},
+};
+
+/// Runs the FVO solver. [`BevyPathfindingPlugin`](crate::BevyPathfindingPlugin) adds a default instance,
+/// add your own configured instance *before* it to change the defaults.
+///
+/// # Example
+///
+/// ```
+/// app.add_plugins((FvoPlugin::new().with_apply_translation(), BevyPathfindingPlugin));
+/// ```
+pub struct FvoPlugin {
+    /// Adds the [`apply_steering`] system so agents are moved by their solved velocity.
+    pub apply_translation: bool,
+    /// Data structure used to find the neighbors of each agent.
+    pub neighbor_search: NeighborSearch,
+    /// Plane agents move on, XZ by default.
+    pub plane: Plane,
+    /// Schedule the [`FvoSystemSet`]s run in, `Update` by default.
+    pub schedule: InternedScheduleLabel,
+}
+
+impl Default for FvoPlugin {
+    fn default() -> Self {
+        Self {
+            apply_translation: false,
+            neighbor_search: NeighborSearch::default(),
+            plane: Plane::default(),
+            schedule: Update.intern(),
+        }
+    }
+}
+
+impl FvoPlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the solver in `schedule` instead of `Update`, e.g. `FixedUpdate` for a deterministic tick.
+    pub fn in_schedule(mut self, schedule: impl ScheduleLabel) -> Self {
+        self.schedule = schedule.intern();
+        self
+    }
+
+    /// Let the plugin move agents instead of writing your own movement system.
+    pub fn with_apply_translation(mut self) -> Self {
+        self.apply_translation = true;
+        self
+    }
+
+    pub fn with_neighbor_search(mut self, neighbor_search: NeighborSearch) -> Self {
+        self.neighbor_search = neighbor_search;
+        self
+    }
+
+    /// Moves agents on `plane`, e.g. [`Plane::XY`] for a 2D game with sprites.
+    pub fn with_plane(mut self, plane: Plane) -> Self {
+        self.plane = plane;
+        self
+    }
+}
+
+/// Neighbor search backend of the solver. Inserted as a resource by the [`FvoPlugin`] and can be swapped at runtime.
+#[derive(Resource, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[reflect(Resource)]
+pub enum NeighborSearch {
+    /// Uniform buckets from the persistent [`SpatialHash`]. Cheap to maintain and best when agents are spread evenly.
+    #[default]
+    SpatialHash,
+    /// A [`KdTree`] rebuilt every frame. Adapts to very uneven densities, e.g. one dense blob on a huge, mostly empty
+    /// map, where most buckets are empty and the occupied ones are crowded.
+    KdTree,
+}
+
+/// How agents split the avoidance between each other. Every model builds the same linearized velocity obstacles and
+/// only differs in how far each agent moves out of them, so they can be switched at runtime.
+#[derive(Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AvoidanceModel {
+    /// Optimal reciprocal collision avoidance, the share is split by [`FvoSettings::priority`] and
+    /// [`FvoSettings::mass`].
+    #[default]
+    Orca,
+    /// Hybrid reciprocal velocity obstacles: agents passing on the right of a neighbor share the avoidance, agents
+    /// cutting across to the left take all of it. Breaks the symmetric dance of two agents meeting head-on.
+    Hrvo,
+    /// Reciprocal velocity obstacles: both agents take half of the avoidance.
+    Rvo,
+    /// Plain velocity obstacles: neighbors are treated as moving obstacles that never yield. Cheapest, as the
+    /// solver skips the fallback for infeasible constraints and keeps the partial solution, good enough for
+    /// background crowds.
+    Vo,
+}
+
+/// How two planar agents meeting (almost) head-on pick the side they pass each other on. Without a preference the side
+/// flips with every tiny change of their positions and they dance in front of each other. Both agents of a pair always
+/// agree on a side, so opposing streams pass smoothly.
+#[derive(Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SymmetryBreaking {
+    /// Pick the side by the exact geometry, like agents that don't meet head-on.
+    None,
+    /// Always pass on the right of each other, which sorts opposing streams into lanes.
+    #[default]
+    RightHand,
+    /// Pass on the right or left by a hash of the pair, for crowds that shouldn't look too orderly.
+    Hashed,
+}
+
+impl SymmetryBreaking {
+    /// Whether `unit` passes on the right of `other` when they meet head-on, `None` for no preference.
+    fn pass_right(self, unit: Entity, other: Entity) -> Option<bool> {
+        match self {
+            SymmetryBreaking::None => None,
+            SymmetryBreaking::RightHand => Some(true),
+            SymmetryBreaking::Hashed => {
+                // both agents of the pair hash the same ids in the same order
+                let (a, b) = (unit.min(other), unit.max(other));
+                let mut x = a.to_bits() ^ b.to_bits().rotate_left(32);
+                x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                Some((x ^ (x >> 31)) & 1 == 0)
+            }
+        }
+    }
+}
+
+/// What the solver does with agents that left the [`Grid`].
+#[derive(Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfBoundsPolicy {
+    /// Follow the flowfield at the closest edge of the grid and avoid everyone as usual.
+    #[default]
+    Clamp,
+    /// Head straight back to the closest point on the grid, ignoring the flowfield until back on it.
+    SteerBackToGrid,
+    /// Leave the agent alone: it is not solved, its velocity is zeroed and others don't avoid it.
+    Ignore,
+    /// Despawn the agent.
+    Despawn,
+}
+
+/// Runtime configuration of the FVO solver.
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct FvoConfig {
+    /// Update the [`SpatialHash`] only every `rebuild_interval` frames (`1` = every frame).
+    ///
+    /// Neighbor positions and velocities are always read fresh by the solver, only the bucket an agent is filed
+    /// under can be stale. To compensate, the neighbor search covers one extra ring of buckets when this is greater
+    /// than `1`, so agents that crossed more than one bucket between updates may be missed. Agents spawned since the
+    /// last update are not seen as neighbors until the next one.
+    pub rebuild_interval: u32,
+    /// Makes the steering output a pure function of the agent and flowfield state, for lockstep multiplayer.
+    ///
+    /// Neighbors and flowfields are processed in [`Entity`] order and the solver steps by the `Time<Fixed>` timestep
+    /// instead of the frame delta. Run the plugin in `FixedUpdate` (see [`FvoPlugin::in_schedule`]) so agents also
+    /// move by that timestep. Bit-identical results across machines additionally need the same float behavior, i.e.
+    /// the same target and build.
+    pub deterministic: bool,
+    /// Pushes overlapping agents apart after they moved, instead of the velocity based separation in the solver.
+    ///
+    /// Each overlapping pair is moved apart along the line between their centers, the lighter agent (see
+    /// [`FvoSettings::mass`]) moving further, so packed crowds at chokepoints push through each other instead of
+    /// vibrating.
+    pub soft_contacts: bool,
+    /// Share of the overlap resolved per frame by [`FvoConfig::soft_contacts`], in `0.0..=1.0`. Lower values give
+    /// softer, more squishy crowds.
+    pub contact_stiffness: f32,
+    /// How agents split the avoidance, [`AvoidanceModel::Orca`] by default.
+    pub avoidance_model: AvoidanceModel,
+    /// Number of candidate velocities scored when the linear program finds no velocity satisfying every constraint.
+    ///
+    /// Each candidate is rated by its distance to the preferred velocity plus a penalty growing as the time to the
+    /// first collision shrinks, and the best one replaces the least-violation result of the linear program. `0`
+    /// disables sampling. Only used by planar agents.
+    pub fallback_samples: usize,
+    /// What happens to agents outside of the grid, applied to the flowfield sampling, the solve and the
+    /// [`SpatialHash`] alike. [`OutOfBoundsPolicy::Clamp`] by default.
+    pub out_of_bounds: OutOfBoundsPolicy,
+    /// Side preference of agents meeting head-on, [`SymmetryBreaking::RightHand`] by default.
+    pub symmetry_breaking: SymmetryBreaking,
+    /// Time the solve may take per tick, e.g. `Duration::from_millis(4)`. Agents not solved when it runs out keep
+    /// last tick's velocity and are solved first thing next tick regardless of the budget, so every agent waits at most
+    /// one tick. `None` solves every agent, which is also the case in [`FvoConfig::deterministic`] mode.
+    pub solve_budget: Option<Duration>,
+}
+
+impl Default for FvoConfig {
+    fn default() -> Self {
+        Self {
+            rebuild_interval: 1,
+            deterministic: false,
+            soft_contacts: false,
+            contact_stiffness: 0.5,
+            avoidance_model: AvoidanceModel::Orca,
+            fallback_samples: 0,
+            out_of_bounds: OutOfBoundsPolicy::Clamp,
+            symmetry_breaking: SymmetryBreaking::RightHand,
+            solve_budget: None,
+        }
+    }
+}
 
-pub struct FvoPlugin;
+/// System sets used by the FVO solver, chained in this order in the schedule chosen with
+/// [`FvoPlugin::in_schedule`]. Order your own movement systems after [`FvoSystemSet::Solve`].
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FvoSystemSet {
+    /// Files moved agents into the [`SpatialHash`].
+    BuildSpatialHash,
+    /// Providers add to the [`PreferredVelocity`] of their agents, cleared right before. Formation members are pulled
+    /// toward their slot after it, and agents recovering from being stuck sidestep.
+    PreferredVelocity,
+    /// Computes `FvoAgent::velocity` and fills every `FlowField::steering_map`.
+    Solve,
+    /// Applies the solved velocities to transforms when [`FvoPlugin::apply_translation`] is set, then resolves
+    /// overlaps when [`FvoConfig::soft_contacts`] is set. Agents with [`FaceVelocity`] are turned and agents with
+    /// [`TerrainFollow`] put on the ground here as well.
+    ApplySteering,
+}
 
 impl Plugin for FvoPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, calculate_fvo_steering);
+        app.init_resource::<FvoConfig>()
+            .init_resource::<SpatialHash>()
+            .insert_resource(self.neighbor_search)
+            .insert_resource(self.plane)
+            .register_type::<FvoConfig>()
+            .register_type::<FvoAgent>()
+            .register_type::<FvoSettings>()
+            .register_type::<ArrivalSettings>()
+            .register_type::<ArrivalCurve>()
+            .register_type::<NavigationSpace>()
+            .register_type::<Arrived>()
+            .register_type::<GroupId>()
+            .register_type::<DynamicObstacle>()
+            .register_type::<AvoidanceLayers>()
+            .register_type::<TeamAvoidance>()
+            .register_type::<FvoUpdaterSync>()
+            .register_type::<FvoDebugInfo>()
+            .register_type::<GameCamera>()
+            .register_type::<TrajectoryRecorder>()
+            .register_type::<ReplayMode>()
+            .register_type::<NeighborSearch>()
+            .register_type::<Plane>()
+            .register_type::<AvoidanceModel>()
+            .register_type::<OutOfBoundsPolicy>()
+            .register_type::<SymmetryBreaking>()
+            .register_type::<FvoLodSettings>()
+            .register_type::<LodLevel>()
+            .register_type::<FvoLod>()
+            .register_type::<Team>()
+            .register_type::<TeamRules>()
+            .register_type::<Formation>()
+            .register_type::<FormationShape>()
+            .register_type::<FormationSlot>()
+            .register_type::<PathSmoothing>()
+            .register_type::<Steering>()
+            .register_type::<SteeringBehavior>()
+            .register_type::<SteeringTarget>()
+            .register_type::<PreferredVelocity>()
+            .register_type::<FaceVelocity>()
+            .register_type::<TerrainFollow>()
+            .register_type::<LocomotionState>()
+            .register_type::<AgentState>()
+            .register_type::<StuckDetection>()
+            .register_type::<StuckRecovery>()
+            .configure_sets(
+                self.schedule,
+                (
+                    FvoSystemSet::BuildSpatialHash,
+                    FvoSystemSet::PreferredVelocity,
+                    FvoSystemSet::Solve,
+                    FvoSystemSet::ApplySteering,
+                )
+                    .chain(),
+            )
+            .add_observer(prune_spatial_hash)
+            .add_systems(
+                self.schedule,
+                (
+                    despawn_out_of_bounds
+                        .run_if(|config: Res<FvoConfig>| {
+                            config.out_of_bounds == OutOfBoundsPolicy::Despawn
+                        })
+                        .in_set(FvoSystemSet::BuildSpatialHash)
+                        .before(update_spatial_hash),
+                    update_spatial_hash
+                        .run_if(spatial_hash_due)
+                        .in_set(FvoSystemSet::BuildSpatialHash),
+                    (
+                        clear_preferred_velocities,
+                        update_lod,
+                        update_formations,
+                        update_path_smoothing,
+                        update_steering,
+                    )
+                        .after(FvoSystemSet::BuildSpatialHash)
+                        .before(FvoSystemSet::PreferredVelocity),
+                    (
+                        flowfield_preferred_velocity,
+                        provide_preferred_velocity::<Steering>,
+                        provide_preferred_velocity::<PreferredVelocityFn>,
+                    )
+                        .in_set(FvoSystemSet::PreferredVelocity),
+                    (formation_preferred_velocity, stuck_preferred_velocity)
+                        .chain()
+                        .after(FvoSystemSet::PreferredVelocity)
+                        .before(FvoSystemSet::Solve),
+                    (
+                        calculate_fvo_steering,
+                        update_steering_maps,
+                        update_agent_states,
+                    )
+                        .chain()
+                        .in_set(FvoSystemSet::Solve),
+                    resolve_contacts
+                        .run_if(|config: Res<FvoConfig>| config.soft_contacts)
+                        .in_set(FvoSystemSet::ApplySteering),
+                    face_velocity
+                        .in_set(FvoSystemSet::ApplySteering)
+                        .after(apply_steering),
+                    follow_terrain
+                        .in_set(FvoSystemSet::ApplySteering)
+                        .after(apply_steering)
+                        .after(resolve_contacts),
+                    update_stuck_detection.after(FvoSystemSet::ApplySteering),
+                    (record_trajectories, replay_trajectories)
+                        .chain()
+                        .run_if(resource_exists::<TrajectoryRecorder>)
+                        .after(FvoSystemSet::ApplySteering),
+                ),
+            );
+
+        if self.apply_translation {
+            app.add_systems(
+                self.schedule,
+                apply_steering
+                    .in_set(FvoSystemSet::ApplySteering)
+                    .before(resolve_contacts),
+            );
+        }
+
+        #[cfg(feature = "rapier")]
+        app.add_systems(
+            self.schedule,
+            (
+                crate::rapier::read_rapier_velocity.before(FvoSystemSet::Solve),
+                crate::rapier::write_rapier_velocity.in_set(FvoSystemSet::ApplySteering),
+            ),
+        );
+
+        #[cfg(feature = "avian")]
+        app.add_systems(
+            self.schedule,
+            (
+                crate::avian::derive_avian_obstacles,
+                crate::avian::read_avian_velocity.before(FvoSystemSet::Solve),
+                crate::avian::write_avian_velocity.in_set(FvoSystemSet::ApplySteering),
+            ),
+        );
     }
 }
 
-pub fn calculate_fvo_steering(
+/// Moves every agent some provider gave a [`PreferredVelocity`] this tick by `velocity * dt` and turns it to face its
+/// heading, unless [`FaceVelocity`] turns it. Agents without one are left where they are.
+pub fn apply_steering(
     time: Res<Time>,
-    mut q_agents: Query<(Entity, &Transform, &mut FvoAgent)>,
-    mut q_ff: Query<&mut FlowField>,
-    grid: Res<Grid>,
-    mut gizmos: Gizmos,
-    dbg_options: Option<Res<DbgOptions>>,
+    fixed_time: Res<Time<Fixed>>,
+    config: Res<FvoConfig>,
+    plane: Res<Plane>,
+    mut q_agents: Query<(
+        &mut Transform,
+        &FvoAgent,
+        &PreferredVelocity,
+        Has<FaceVelocity>,
+    )>,
+) {
+    let dt = solver_dt(&config, &time, &fixed_time);
+
+    for (mut tf, agent, preferred, faces_velocity) in q_agents.iter_mut() {
+        if preferred.get().is_none() {
+            continue;
+        }
+
+        tf.translation += agent.velocity * dt;
+
+        let heading = plane.flatten(agent.heading);
+        if heading.length_squared() > f32::EPSILON && !faces_velocity {
+            tf.rotation = plane.facing(heading);
+        }
+    }
+}
+
+/// Slerps every agent with [`FaceVelocity`] toward its velocity, see [`FaceVelocity::turn_speed`].
+pub fn face_velocity(
+    time: Res<Time>,
+    fixed_time: Res<Time<Fixed>>,
+    config: Res<FvoConfig>,
+    plane: Res<Plane>,
+    mut q_agents: Query<(&mut Transform, &FvoAgent, &FaceVelocity)>,
+) {
+    let dt = solver_dt(&config, &time, &fixed_time);
+
+    for (mut tf, agent, face) in q_agents.iter_mut() {
+        let direction = match agent.space {
+            NavigationSpace::Planar => plane.flatten(agent.velocity),
+            NavigationSpace::Volumetric => agent.velocity,
+        };
+        if direction.length_squared() <= f32::EPSILON {
+            continue;
+        }
+
+        let target = plane.facing(direction);
+        let angle = tf.rotation.angle_between(target);
+        let max_turn = face.turn_speed * dt;
+        tf.rotation = if angle <= max_turn {
+            target
+        } else {
+            tf.rotation.slerp(target, max_turn / angle)
+        };
+    }
+}
+
+/// Puts every entity with [`TerrainFollow`] on the ground under it, see [`TerrainFollow`].
+pub fn follow_terrain(
+    grid: Option<Res<Grid>>,
+    plane: Res<Plane>,
+    mut has_heights: Local<bool>,
+    q_map: Query<&GlobalTransform, With<MapBase>>,
+    mut q_followers: Query<(&mut Transform, &TerrainFollow, Option<&FvoAgent>)>,
+) {
+    // scanning the cells is only worth it when the grid changed
+    if let Some(grid) = grid.as_ref().filter(|grid| grid.is_changed()) {
+        *has_heights = grid.has_heights();
+    }
+
+    let heights = grid.as_ref().filter(|_| *has_heights);
+    let map_tf = q_map.single().ok();
+    for (mut tf, follow, agent) in q_followers.iter_mut() {
+        if agent.is_some_and(|agent| agent.space == NavigationSpace::Volumetric) {
+            continue;
+        }
+
+        // heights are measured along the up axis of the plane
+        let pos = plane.to_plane(tf.translation);
+        let ground = match (heights, map_tf) {
+            (Some(grid), _) => Some(grid.height_at(tf.translation)),
+            // straight down (or up) onto the plane of the map
+            (None, Some(map_tf)) => {
+                let normal = plane.to_plane(*map_tf.up());
+                (normal.y.abs() > f32::EPSILON).then(|| {
+                    let to_plane = plane.to_plane(map_tf.translation() - tf.translation);
+                    pos.y + normal.dot(to_plane) / normal.y
+                })
+            }
+            (None, None) => None,
+        };
+
+        if let Some(ground) = ground {
+            tf.translation = plane.from_plane(pos.with_y(ground + follow.offset));
+        }
+    }
+}
+
+/// Moves overlapping agents apart by a share of their overlap, see [`FvoConfig::soft_contacts`].
+pub fn resolve_contacts(
+    config: Res<FvoConfig>,
+    time: Res<Time>,
+    fixed_time: Res<Time<Fixed>>,
+    spatial: Res<SpatialHash>,
+    plane: Res<Plane>,
+    mut q_agents: Query<(Entity, &mut Transform, &FvoAgent)>,
 ) {
-    let dt = time.delta_secs();
-
-    // ——— bucket sizing, shared with flowfield stop logic ———
-    let world_width = grid.size.x as f32 * grid.cell_diameter;
-    let world_depth = grid.size.y as f32 * grid.cell_diameter;
-    let bucket_size_x = world_width / grid.buckets;
-    let bucket_size_y = world_depth / grid.buckets;
-    let cols = grid.grid.len();
-    let rows = grid.grid[0].len();
-    let origin = grid.grid[cols / 2][rows / 2].world_pos;
-
-    // snapshot positions & velocities to build buckets
-    let snapshot: Vec<(Entity, Vec3, Vec3, f32)> = q_agents
+    let dt = solver_dt(&config, &time, &fixed_time);
+    let stiffness = config.contact_stiffness.clamp(0.0, 1.0);
+
+    let mut agents: Vec<(Entity, Vec3, f32, f32, f32, NavigationSpace)> = q_agents
+        .iter()
+        .map(|(ent, tf, agent)| {
+            let s = &agent.settings;
+            (
+                ent,
+                plane.to_plane(tf.translation),
+                s.radius,
+                s.mass.max(0.0),
+                s.max_speed,
+                agent.space,
+            )
+        })
+        .collect();
+    if config.deterministic {
+        agents.sort_unstable_by_key(|a| a.0);
+    }
+
+    let lookup: HashMap<Entity, (Vec3, f32, f32)> = agents
         .iter()
-        .map(|(e, tf, agent)| (e, tf.translation, agent.velocity, agent.settings.radius))
+        .map(|&(ent, pos, radius, mass, ..)| (ent, (pos, radius, mass)))
         .collect();
+    let max_radius = agents.iter().map(|a| a.2).fold(0.0, f32::max);
+    // the hash holds the positions before this frame's movement (or older when throttled)
+    let max_speed = agents.iter().map(|a| a.4).fold(0.0, f32::max);
+    let stale = 2.0 * max_speed * dt * config.rebuild_interval.max(1) as f32;
+
+    let mut corrections: HashMap<Entity, Vec3> = HashMap::new();
+    let mut others: Vec<Entity> = Vec::new();
+    for &(ent, pos, radius, mass, _, space) in &agents {
+        others.clear();
+        others.extend(spatial.neighbors_within(pos, radius + max_radius + stale));
+        if config.deterministic {
+            others.sort_unstable();
+        }
+
+        for &other in &others {
+            // every pair once, by the lower id
+            if other <= ent {
+                continue;
+            }
+            let Some(&(other_pos, other_radius, other_mass)) = lookup.get(&other) else {
+                continue;
+            };
+
+            let offset = match space {
+                NavigationSpace::Planar => (pos - other_pos).with_y(0.0),
+                NavigationSpace::Volumetric => pos - other_pos,
+            };
+            let dist = offset.length();
+            let overlap = radius + other_radius - dist;
+            if overlap <= 0.0 || dist <= 1e-4 {
+                continue;
+            }
+
+            // heavier agents move less
+            let total = (mass + other_mass).max(f32::EPSILON);
+            let push = offset / dist * overlap * stiffness;
+            *corrections.entry(ent).or_default() += push * (other_mass / total);
+            *corrections.entry(other).or_default() -= push * (mass / total);
+        }
+    }
+
+    for (ent, correction) in corrections {
+        if let Ok((_, mut tf, _)) = q_agents.get_mut(ent) {
+            tf.translation += plane.from_plane(correction);
+        }
+    }
+}
+
+/// Signed angle on `plane` from the world direction `from` to `to`, positive to the left.
+fn turn_angle(plane: Plane, from: Vec3, to: Vec3) -> f32 {
+    let (from, to) = (plane.to_2d(from), plane.to_2d(to));
+    if from.length_squared() <= f32::EPSILON || to.length_squared() <= f32::EPSILON {
+        return 0.0;
+    }
+
+    // x and z map to the 2D x and y, which mirrors the handedness
+    -from.perp_dot(to).atan2(from.dot(to))
+}
+
+/// Exponential low-pass from `previous` toward `raw` with the time constant `smoothing_time`, see
+/// [`FvoSettings::velocity_smoothing_time`].
+fn low_pass(previous: Vec3, raw: Vec3, smoothing_time: f32, dt: f32) -> Vec3 {
+    if smoothing_time <= 0.0 {
+        return raw;
+    }
+
+    previous.lerp(raw, 1.0 - (-dt / smoothing_time).exp())
+}
+
+/// The fixed timestep in deterministic mode, so frame time jitter never reaches the solver.
+pub(crate) fn solver_dt(config: &FvoConfig, time: &Time, fixed_time: &Time<Fixed>) -> f32 {
+    if config.deterministic {
+        fixed_time.timestep().as_secs_f32()
+    } else {
+        time.delta_secs()
+    }
+}
+
+fn spatial_hash_due(config: Res<FvoConfig>, mut frame: Local<u32>) -> bool {
+    let due = frame.is_multiple_of(config.rebuild_interval.max(1));
+    *frame = frame.wrapping_add(1);
+    due
+}
+
+type MovedAgent = (With<FvoAgent>, Or<(Changed<Transform>, Added<FvoAgent>)>);
+
+/// Files moved and newly added agents into the [`SpatialHash`], refilling it from scratch when the grid changes.
+/// Agents off the grid are left out unless [`FvoConfig::out_of_bounds`] keeps them steered.
+pub fn update_spatial_hash(
+    mut spatial: ResMut<SpatialHash>,
+    grid: Res<Grid>,
+    config: Res<FvoConfig>,
+    q_moved: Query<(Entity, &Transform), MovedAgent>,
+    q_all: Query<(Entity, &Transform), With<FvoAgent>>,
+    diagnostics: Option<ResMut<FvoDiagnostics>>,
+) {
+    let start = Instant::now();
+    let file = |spatial: &mut SpatialHash, ent: Entity, pos: Vec3| {
+        if is_ignored(&config, &grid, pos) {
+            spatial.remove(ent);
+        } else {
+            spatial.insert(ent, grid.plane.to_plane(pos));
+        }
+    };
+
+    if grid.is_changed() {
+        *spatial = SpatialHash::from_grid(&grid);
+        for (ent, tf) in q_all.iter() {
+            file(&mut spatial, ent, tf.translation);
+        }
+    } else {
+        for (ent, tf) in q_moved.iter() {
+            file(&mut spatial, ent, tf.translation);
+        }
+    }
+
+    if let Some(mut diagnostics) = diagnostics {
+        diagnostics.bucket_build = start.elapsed();
+    }
+}
+
+/// Despawns agents that left the grid, see [`OutOfBoundsPolicy::Despawn`].
+pub fn despawn_out_of_bounds(
+    mut cmds: Commands,
+    grid: Res<Grid>,
+    q_agents: Query<(Entity, &Transform), With<FvoAgent>>,
+) {
+    for (ent, tf) in q_agents.iter() {
+        if !grid.contains(tf.translation) {
+            cmds.entity(ent).despawn();
+        }
+    }
+}
+
+// agents off the grid that the solver leaves alone
+fn is_ignored(config: &FvoConfig, grid: &Grid, pos: Vec3) -> bool {
+    matches!(
+        config.out_of_bounds,
+        OutOfBoundsPolicy::Ignore | OutOfBoundsPolicy::Despawn
+    ) && !grid.contains(pos)
+}
+
+// reacts to every removal immediately, so none are missed while the update is throttled
+fn prune_spatial_hash(trigger: On<Remove, FvoAgent>, mut spatial: ResMut<SpatialHash>) {
+    spatial.remove(trigger.entity);
+}
+
+type SteeredAgent = (
+    Entity,
+    &'static Transform,
+    &'static mut FvoAgent,
+    Option<&'static GroupId>,
+    Option<&'static AvoidanceLayers>,
+    Option<&'static mut FvoDebugInfo>,
+    Option<&'static mut LocomotionState>,
+    &'static FvoLod,
+    &'static PreferredVelocity,
+    Has<Arrived>,
+);
+
+#[allow(clippy::too_many_arguments)]
+pub fn calculate_fvo_steering(
+    time: Res<Time>,
+    mut q_agents: Query<SteeredAgent>,
+    q_nav_layers: Query<&NavLayer>,
+    q_teams: Query<(Option<&Team>, Option<&TeamRules>)>,
+    q_obstacles: Query<(Entity, &Transform, &DynamicObstacle), Without<FvoAgent>>,
+    grid: Res<Grid>,
+    config: Res<FvoConfig>,
+    spatial: Res<SpatialHash>,
+    neighbor_search: Res<NeighborSearch>,
+    fixed_time: Res<Time<Fixed>>,
+    par_cmds: ParallelCommands,
+    #[cfg(feature = "debug")] mut gizmos: Gizmos,
+    #[cfg(feature = "debug")] dbg_options: Option<Res<DbgOptions>>,
+    diagnostics: Option<ResMut<FvoDiagnostics>>,
+    mut overdue: Local<HashSet<Entity>>,
+    mut scratch: Local<FvoScratch>,
+) {
+    let start = Instant::now();
+    let dt = solver_dt(&config, &time, &fixed_time);
+    let budget = config.solve_budget.filter(|_| !config.deterministic);
+    // the solver works in plane space, agents on XY are turned onto XZ and back
+    let plane = grid.plane;
+
+    let FvoScratch {
+        snapshots,
+        solved,
+        obstacles,
+        kd_points,
+        kd_tree,
+        solve: solve_scratch,
+    } = &mut *scratch;
+
+    // read-only copy of every agent so the solve can run in parallel while agents are written, ignored agents are
+    // nobody's neighbor
+    snapshots.clear();
+    snapshots.extend(
+        q_agents
+            .iter()
+            .filter(|(_, tf, ..)| !is_ignored(&config, &grid, tf.translation))
+            .map(|(ent, tf, agent, group, layers, ..)| {
+                let (team, team_rules) = q_teams.get(ent).unwrap_or_default();
+                let snapshot = AgentSnapshot {
+                    position: plane.to_plane(tf.translation),
+                    velocity: plane.to_plane(agent.velocity),
+                    heading: plane.to_plane(agent.heading),
+                    settings: agent.settings,
+                    space: agent.space,
+                    group: group.map(|g| g.0),
+                    layers: layers.copied().unwrap_or_default(),
+                    nav_layer: q_nav_layers.get(ent).copied().unwrap_or_default(),
+                    team: team.copied(),
+                    team_rules: team_rules.copied().unwrap_or_default(),
+                };
+                (ent, snapshot)
+            }),
+    );
+    let snapshots = &*snapshots;
 
     // optional debug: draw partition grid + sensing radius
-    if let Some(dbg) = dbg_options {
+    #[cfg(feature = "debug")]
+    if let Some(dbg) = &dbg_options {
         if dbg.draw_spatial_grid {
             gizmos.grid(
-                Isometry3d::from_rotation(Quat::from_rotation_x(PI / 2.0)),
-                UVec2::new(grid.buckets as u32, grid.buckets as u32),
-                Vec2::new(bucket_size_x, bucket_size_y),
+                Isometry3d::from_rotation(plane.rotation() * Quat::from_rotation_x(PI / 2.0)),
+                (grid.world_size() / spatial.bucket_size())
+                    .ceil()
+                    .as_uvec2(),
+                spatial.bucket_size(),
                 YELLOW,
             );
         }
 
         if dbg.draw_radius {
-            for (_, tf, agent) in q_agents.iter() {
-                let pos = tf.translation;
-                let rot = Quat::from_rotation_x(PI / 2.0);
-                let iso = Isometry3d::new(pos, rot);
-                gizmos.circle(iso, agent.settings.sensor_range, RED);
+            for snapshot in snapshots.values() {
+                let rot = plane.rotation() * Quat::from_rotation_x(PI / 2.0);
+                let iso = Isometry3d::new(plane.from_plane(snapshot.position), rot);
+                gizmos.circle(iso, snapshot.settings.sensor_range, RED);
             }
         }
     }
 
-    // bucket map: (bx, by) -> list of agents in that cell
-    let mut buckets: HashMap<(i32, i32), Vec<(Entity, Vec3, Vec3, f32)>> =
-        HashMap::with_capacity(snapshot.len());
-
-    for &(ent, pos, vel, radius) in &snapshot {
-        let bx = ((pos.x - origin.x) / bucket_size_x).floor() as i32;
-        let by = ((pos.z - origin.y) / bucket_size_y).floor() as i32;
-        buckets
-            .entry((bx, by))
-            .or_default()
-            .push((ent, pos, vel, radius));
-    }
-
-    // main FVO solve per flow field
-    for mut ff in q_ff.iter_mut() {
-        let mut pending: Vec<(Entity, Vec3)> = Vec::with_capacity(ff.units.len());
-
-        for &unit in &ff.units {
-            if let Ok((_, tf, mut agent)) = q_agents.get_mut(unit) {
-                let bx = ((tf.translation.x - origin.x) / bucket_size_x).floor() as i32;
-                let by = ((tf.translation.z - origin.y) / bucket_size_y).floor() as i32;
-
-                // expand bucket search to cover the sensor range
-                let bucket_radius_x = (agent.settings.sensor_range / bucket_size_x).ceil() as i32;
-                let bucket_radius_y = (agent.settings.sensor_range / bucket_size_y).ceil() as i32;
-
-                let mut neighbors: Vec<(Vec3, Vec3, f32)> = Vec::new();
-                for dx in -bucket_radius_x..=bucket_radius_x {
-                    for dy in -bucket_radius_y..=bucket_radius_y {
-                        if let Some(bucket) = buckets.get(&(bx + dx, by + dy)) {
-                            for &(other, pos, vel, radius) in bucket {
-                                if other == unit {
-                                    continue;
-                                }
-                                let range = agent.settings.sensor_range + radius;
-                                if tf.translation.distance_squared(pos) <= range * range {
-                                    neighbors.push((pos, vel, radius));
-                                }
-                            }
-                        }
-                    }
+    // agents something was provided for and that are due this tick, the others keep their velocity
+    solved.clear();
+    solved.extend(
+        q_agents
+            .iter()
+            .filter(|(ent, .., lod, preferred, _)| {
+                lod.solve && preferred.get().is_some() && snapshots.contains_key(ent)
+            })
+            .map(|(ent, ..)| ent),
+    );
+    let solved = &*solved;
+
+    let kd_tree = if *neighbor_search == NeighborSearch::KdTree {
+        kd_points.clear();
+        kd_points.extend(snapshots.iter().map(|(&ent, a)| (a.position, ent)));
+        // the tree shape, and with it tie-breaking between equally close agents, depends on the input order
+        if config.deterministic {
+            kd_points.sort_unstable_by_key(|&(_, ent)| ent);
+        }
+        kd_tree.rebuild(kd_points.iter().copied());
+        Some(&*kd_tree)
+    } else {
+        None
+    };
+
+    obstacles.clear();
+    obstacles.extend(q_obstacles.iter().map(|(ent, tf, obstacle)| {
+        let position = plane.to_plane(tf.translation);
+        Neighbor::obstacle(
+            ent,
+            position,
+            plane.to_plane(obstacle.velocity),
+            obstacle.radius,
+        )
+    }));
+    if config.deterministic {
+        obstacles.sort_unstable_by_key(|n| n.entity);
+    }
+    let obstacles = &*obstacles;
+
+    let max_radius = snapshots
+        .values()
+        .map(|a| a.settings.radius)
+        .fold(0.0, f32::max);
+
+    #[cfg(feature = "debug")]
+    let record_neighbors = dbg_options
+        .as_ref()
+        .is_some_and(|dbg| dbg.draw_neighbor_links);
+    #[cfg(not(feature = "debug"))]
+    let record_neighbors = false;
+
+    let ctx = SolveContext {
+        snapshots,
+        spatial: &spatial,
+        kd_tree,
+        max_radius,
+        obstacles,
+        grid: &grid,
+        dt,
+        // agents may have left their bucket since the last update, search one extra ring to find them
+        stale_margin: if config.rebuild_interval > 1 { 1 } else { 0 },
+        deterministic: config.deterministic,
+        soft_contacts: config.soft_contacts,
+        model: config.avoidance_model,
+        fallback_samples: config.fallback_samples,
+        symmetry_breaking: config.symmetry_breaking,
+        solved,
+        record_neighbors,
+        timed: diagnostics.is_some(),
+    };
+    // only drained by the debug gizmos
+    #[cfg_attr(not(feature = "debug"), allow(unused_mut))]
+    let mut neighbor_links: Parallel<Vec<(Vec3, Vec3)>> = Parallel::default();
+    let mut stats: Parallel<SolveStats> = Parallel::default();
+    let mut deferred: Parallel<Vec<Entity>> = Parallel::default();
+
+    // main FVO solve, every agent only reads the snapshot so they can be solved in parallel
+    q_agents.par_iter_mut().for_each(
+        |(unit, _, mut agent, _, _, debug_info, locomotion, lod, preferred, arrived)| {
+            if !snapshots.contains_key(&unit) {
+                agent.steering = Vec3::ZERO;
+                agent.velocity = Vec3::ZERO;
+                if let Some(mut locomotion) = locomotion {
+                    *locomotion = LocomotionState::default();
                 }
+                return;
+            }
 
-                // preferred velocity = flow direction * target speed
-                let dir2d = ff.sample_direction(tf.translation, &grid);
-                let flow_dir = Vec3::new(dir2d.x, 0.0, dir2d.y).normalize_or_zero();
+            let Some(preferred_vel) = preferred.get().filter(|_| lod.solve) else {
+                return;
+            };
 
-                // slow down as we approach the goal to reduce overshoot
-                let goal_dist =
-                    tf.translation.distance(ff.destination_cell.world_pos).max(f32::EPSILON);
-                let slow_radius = (agent.settings.sensor_range * 2.0).max(0.1);
-                let speed_scale = if goal_dist < slow_radius {
-                    (goal_dist / slow_radius).clamp(0.0, 1.0)
-                } else {
-                    1.0
+            // out of time: keep going and be first in line next tick
+            if budget.is_some_and(|budget| start.elapsed() > budget) && !overdue.contains(&unit) {
+                deferred.scope(|deferred| deferred.push(unit));
+                return;
+            }
+
+            let snapshot = &snapshots[&unit];
+
+            // settled agents only give way
+            let preferred_vel = if arrived {
+                Vec3::ZERO
+            } else {
+                plane.to_plane(preferred_vel)
+            };
+
+            // agents solved at a lower rate catch up on the ticks they skipped
+            let dt = dt * lod.interval as f32;
+            let ctx = SolveContext { dt, ..ctx };
+            let solved = solve_scratch
+                .scope(|scratch| solve_agent(&ctx, unit, snapshot, preferred_vel, scratch));
+            let smoothing_time = snapshot.settings.velocity_smoothing_time;
+            let velocity = plane.from_plane(solved.velocity);
+            agent.steering = velocity;
+            agent.velocity = low_pass(agent.velocity, velocity, smoothing_time, dt);
+            let heading = plane.from_plane(solved.heading);
+
+            if let Some(mut locomotion) = locomotion {
+                let preferred_speed = snapshot.settings.preferred_speed;
+                *locomotion = LocomotionState {
+                    speed_ratio: if preferred_speed > f32::EPSILON {
+                        agent.velocity.length() / preferred_speed
+                    } else {
+                        0.0
+                    },
+                    turning_rate: turn_angle(plane, agent.heading, heading) / dt.max(f32::EPSILON),
                 };
-                let preferred_vel = flow_dir * (agent.settings.preferred_speed * speed_scale);
-
-                // build ORCA-style half-plane constraints against neighbors
-                let constraints = build_orca_constraints(
-                    tf.translation,
-                    agent.velocity,
-                    &agent.settings,
-                    &neighbors,
-                    dt,
-                );
+            }
+            agent.heading = heading;
+
+            if let Some(mut debug_info) = debug_info {
+                *debug_info = FvoDebugInfo {
+                    preferred_velocity: plane.from_plane(solved.debug.preferred_velocity),
+                    solved_velocity: plane.from_plane(solved.debug.solved_velocity),
+                    ..solved.debug
+                };
+            }
 
-                // choose the velocity closest to preferred that satisfies constraints
-                let solved =
-                    solve_orca(preferred_vel, agent.velocity, &constraints, agent.settings.max_speed);
-
-                // strong local separation if still intersecting
-                let mut separation = Vec3::ZERO;
-                for (n_pos, _n_vel, n_radius) in &neighbors {
-                    let offset = tf.translation - *n_pos;
-                    let dist = offset.length();
-                    let combined = agent.settings.radius + *n_radius;
-                    if dist < combined * 1.05 && dist > 1e-3 {
-                        let push = (combined * 1.05 - dist) * dt.recip();
-                        separation += offset.normalize() * push;
+            if ctx.timed {
+                stats.scope(|stats| stats.add(&solved));
+            }
+
+            if !solved.neighbor_positions.is_empty() {
+                neighbor_links.scope(|links| {
+                    let from = snapshot.position;
+                    links.extend(solved.neighbor_positions.iter().map(|&to| (from, to)));
+                });
+            }
+
+            if !solved.collisions.is_empty() {
+                par_cmds.command_scope(|mut cmds| {
+                    for (other, penetration) in solved.collisions {
+                        cmds.trigger(AgentCollisionEvent {
+                            a: unit,
+                            b: other,
+                            penetration,
+                        });
                     }
-                }
+                });
+            }
+        },
+    );
+
+    overdue.clear();
+    overdue.extend(deferred.drain());
+
+    if let Some(mut diagnostics) = diagnostics {
+        let stats = stats
+            .iter_mut()
+            .map(std::mem::take)
+            .fold(SolveStats::default(), SolveStats::merge);
+        diagnostics.neighbor_gather = stats.gather;
+        diagnostics.constraint_build = stats.constraint_build;
+        diagnostics.solve = stats.solve;
+        diagnostics.agents = stats.agents;
+        diagnostics.neighbors_avg = stats.neighbors as f32 / stats.agents.max(1) as f32;
+        diagnostics.neighbors_max = stats.neighbors_max;
+        diagnostics.constraints = stats.constraints;
+        diagnostics.deferred = overdue.len();
+    }
 
-                let desired_vel = (solved + separation).clamp_length_max(agent.settings.max_speed);
+    // optional debug: links to the neighbors each agent built constraints for
+    #[cfg(feature = "debug")]
+    for (from, to) in neighbor_links.drain() {
+        gizmos.line(plane.from_plane(from), plane.from_plane(to), ORANGE);
+    }
 
-                // drive toward chosen velocity while respecting acceleration limits
-                let desired_accel =
-                    (desired_vel - agent.velocity).clamp_length_max(agent.settings.max_accel);
-                let new_velocity = (agent.velocity + desired_accel * dt)
-                    .clamp_length_max(agent.settings.max_speed + f32::EPSILON);
+    // optional debug: solved and preferred velocity of every agent
+    #[cfg(feature = "debug")]
+    if dbg_options.is_some_and(|dbg| dbg.draw_velocities) {
+        for (_, tf, agent, .., preferred, arrived) in q_agents.iter() {
+            let pos = tf.translation;
+            gizmos.arrow(
+                pos,
+                pos + agent.velocity * VELOCITY_GIZMO_SCALE,
+                DEEP_SKY_BLUE,
+            );
 
-                agent.steering = new_velocity;
-                agent.velocity = new_velocity;
-                pending.push((unit, new_velocity));
+            if let Some(preferred_vel) = preferred.get().filter(|_| !arrived) {
+                gizmos.arrow(pos, pos + preferred_vel * VELOCITY_GIZMO_SCALE, LIME);
             }
         }
+    }
+}
+
+/// Share of the wanted speed below which a moving agent counts as [`AgentState::Blocked`].
+const BLOCKED_SPEED_SHARE: f32 = 0.1;
+/// Seconds an agent has to crawl before it counts as [`AgentState::Blocked`], so setting off doesn't.
+const BLOCKED_TIME: f32 = 0.5;
+/// Share of [`FvoSettings::preferred_speed`] below which a wanted speed counts as [`AgentState::Slowing`].
+const SLOWING_SPEED_SHARE: f32 = 0.9;
+
+/// Derives the [`AgentState`] of every agent from its preferred and solved velocity, triggering an
+/// [`AgentStateChangedEvent`] for every change.
+pub fn update_agent_states(
+    mut cmds: Commands,
+    time: Res<Time>,
+    fixed_time: Res<Time<Fixed>>,
+    config: Res<FvoConfig>,
+    mut crawling: Local<HashMap<Entity, f32>>,
+    mut q_agents: Query<(
+        Entity,
+        &FvoAgent,
+        &PreferredVelocity,
+        &mut AgentState,
+        Has<Arrived>,
+    )>,
+) {
+    let dt = solver_dt(&config, &time, &fixed_time);
+    crawling.retain(|&ent, _| q_agents.contains(ent));
+
+    for (ent, agent, preferred, mut state, arrived) in q_agents.iter_mut() {
+        let wanted = preferred.get().map_or(0.0, Vec3::length);
+        let speed = agent.velocity.length();
+
+        let crawl_time =
+            if !arrived && wanted > f32::EPSILON && speed < wanted * BLOCKED_SPEED_SHARE {
+                let crawl_time = crawling.entry(ent).or_default();
+                *crawl_time += dt;
+                *crawl_time
+            } else {
+                crawling.remove(&ent);
+                0.0
+            };
 
-        for (unit, steer) in pending {
-            ff.steering_map.insert(unit, steer);
+        let new_state = if arrived {
+            AgentState::Arrived
+        } else if wanted <= f32::EPSILON {
+            AgentState::Idle
+        } else if crawl_time >= BLOCKED_TIME {
+            AgentState::Blocked
+        } else if wanted < agent.settings.preferred_speed * SLOWING_SPEED_SHARE {
+            AgentState::Slowing
+        } else {
+            AgentState::Moving
+        };
+
+        if *state != new_state {
+            cmds.trigger(AgentStateChangedEvent {
+                agent: ent,
+                from: *state,
+                to: new_state,
+            });
+            *state = new_state;
         }
     }
 }
 
+// velocity arrows show the distance covered in this many seconds
+#[cfg(feature = "debug")]
+const VELOCITY_GIZMO_SCALE: f32 = 0.1;
+
+/// Read-only copy of an agent, taken before the solve so neighbors can be read while agents are written.
 #[derive(Clone, Copy)]
-struct OrcaConstraint {
-    point: Vec2,
-    normal: Vec2,
+struct AgentSnapshot {
+    position: Vec3,
+    velocity: Vec3,
+    heading: Vec3,
+    settings: FvoSettings,
+    space: NavigationSpace,
+    group: Option<u32>,
+    layers: AvoidanceLayers,
+    nav_layer: NavLayer,
+    team: Option<Team>,
+    team_rules: TeamRules,
 }
 
-fn build_orca_constraints(
-    current_pos: Vec3,
-    current_vel: Vec3,
-    settings: &FvoSettings,
-    neighbors: &[(Vec3, Vec3, f32)],
+impl AgentSnapshot {
+    /// How this agent avoids `other`, `None` if it doesn't avoid it at all.
+    fn avoidance(&self, other: &AgentSnapshot) -> Option<TeamAvoidance> {
+        if !self.layers.avoids(&other.layers) {
+            return None;
+        }
+
+        match self.team_rules.avoidance(self.team, other.team) {
+            TeamAvoidance::Ignore => None,
+            avoidance => Some(avoidance),
+        }
+    }
+}
+
+/// Buffers of [`calculate_fvo_steering`], kept from tick to tick so a running solve doesn't allocate.
+#[derive(Default)]
+pub struct FvoScratch {
+    snapshots: HashMap<Entity, AgentSnapshot>,
+    solved: HashSet<Entity>,
+    obstacles: Vec<Neighbor>,
+    kd_points: Vec<(Vec3, Entity)>,
+    kd_tree: KdTree,
+    /// One per thread of the parallel solve.
+    solve: Parallel<SolveScratch>,
+}
+
+/// Buffers of a single [`solve_agent`], cleared by every solve.
+#[derive(Default)]
+struct SolveScratch {
+    candidates: Vec<Entity>,
+    neighbors: Vec<Neighbor>,
+    far: Vec<(f32, Neighbor)>,
+    segments: Vec<(Vec2, Vec2)>,
+    constraints: Vec<OrcaConstraint>,
+}
+
+/// Per-frame data shared by every agent solve.
+struct SolveContext<'a> {
+    snapshots: &'a HashMap<Entity, AgentSnapshot>,
+    spatial: &'a SpatialHash,
+    /// Set when [`NeighborSearch::KdTree`] is selected, takes precedence over `spatial`.
+    kd_tree: Option<&'a KdTree>,
+    /// Largest agent radius, widens tree queries that only see centers.
+    max_radius: f32,
+    /// Every [`DynamicObstacle`], few enough to be scanned by each agent.
+    obstacles: &'a [Neighbor],
+    grid: &'a Grid,
     dt: f32,
-) -> Vec<OrcaConstraint> {
-    let mut constraints = Vec::with_capacity(neighbors.len());
-    let inv_tau = 1.0 / settings.horizon.max(0.001);
-    let inv_dt = 1.0 / dt.max(0.001);
+    stale_margin: i32,
+    deterministic: bool,
+    /// Overlaps are resolved by [`resolve_contacts`], skip the velocity separation.
+    soft_contacts: bool,
+    model: AvoidanceModel,
+    fallback_samples: usize,
+    symmetry_breaking: SymmetryBreaking,
+    /// Agents solved this frame.
+    solved: &'a HashSet<Entity>,
+    /// Fill [`SolvedAgent::neighbor_positions`], only needed for [`DbgOptions::draw_neighbor_links`].
+    record_neighbors: bool,
+    /// Time the stages of every solve, only needed for [`FvoDiagnostics`].
+    timed: bool,
+}
 
-    let self_vel = Vec2::new(current_vel.x, current_vel.z);
+struct SolvedAgent {
+    velocity: Vec3,
+    heading: Vec3,
+    debug: FvoDebugInfo,
+    /// Overlapping neighbors and their penetration depth.
+    collisions: Vec<(Entity, f32)>,
+    /// Position of every neighbor a constraint was built for, empty unless [`SolveContext::record_neighbors`] is set.
+    neighbor_positions: Vec<Vec3>,
+    /// Time spent on the neighbor gather, the constraint build and the solve, zero unless [`SolveContext::timed`] is
+    /// set.
+    timings: [Duration; 3],
+}
 
-    for (neighbor_pos, neighbor_vel, neighbor_radius) in neighbors {
-        let rel_pos = Vec2::new(neighbor_pos.x - current_pos.x, neighbor_pos.z - current_pos.z);
-        let rel_vel = Vec2::new(current_vel.x - neighbor_vel.x, current_vel.z - neighbor_vel.z);
-        let combined_radius = settings.radius + *neighbor_radius;
-        let combined_radius_sq = combined_radius * combined_radius;
-        let dist_sq = rel_pos.length_squared();
-
-        let (shift, normal) = if dist_sq > combined_radius_sq {
-            // Not colliding: use time horizon to build half-plane
-            let w = rel_vel - rel_pos * inv_tau;
-            let w_len_sq = w.length_squared();
-            let dot = w.dot(rel_pos);
-
-            // Project on truncated VO cone (from RVO2)
-            if dot < 0.0 && dot * dot > combined_radius_sq * w_len_sq {
-                // project on cutoff circle at horizon
-                let w_len = w_len_sq.sqrt();
-                let unit_w = w / w_len;
-                let u = unit_w * (combined_radius * inv_tau - w_len);
-                let n = unit_w;
-                (u, n)
-            } else {
-                // legs of the VO
-                let dist = dist_sq.sqrt();
-                let leg = (dist_sq - combined_radius_sq).sqrt();
-                let rel_pos_unit = rel_pos / dist;
-                let left = Vec2::new(
-                    rel_pos_unit.x * leg - rel_pos_unit.y * combined_radius,
-                    rel_pos_unit.x * combined_radius + rel_pos_unit.y * leg,
-                ) / dist;
-                let right = Vec2::new(
-                    rel_pos_unit.x * leg + rel_pos_unit.y * combined_radius,
-                    -rel_pos_unit.x * combined_radius + rel_pos_unit.y * leg,
-                ) / dist;
-
-                let cross = rel_vel.x * rel_pos.y - rel_vel.y * rel_pos.x;
-                let dir = if cross > 0.0 { left } else { right };
-                let n = Vec2::new(-dir.y, dir.x).normalize_or_zero(); // outward normal
-                let u = n * (rel_vel.dot(n));
-                (u, n)
-            }
-        } else {
-            // Already colliding: push away aggressively using timestep
-            let dist = dist_sq.sqrt().max(1e-3);
-            let n = rel_pos / dist;
-            let u = n * ((combined_radius - dist) * inv_dt);
-            (u, n)
-        };
+/// Solver counts and timings summed over the agents solved by one thread, see [`FvoDiagnostics`].
+#[derive(Default)]
+struct SolveStats {
+    agents: usize,
+    neighbors: usize,
+    neighbors_max: usize,
+    constraints: usize,
+    gather: Duration,
+    constraint_build: Duration,
+    solve: Duration,
+}
 
-        // use full shift so a single agent still reacts if the partner lags
-        let point = self_vel + shift;
-        constraints.push(OrcaConstraint { point, normal });
+impl SolveStats {
+    fn add(&mut self, solved: &SolvedAgent) {
+        let [gather, constraint_build, solve] = solved.timings;
+        self.agents += 1;
+        self.neighbors += solved.debug.neighbor_count;
+        self.neighbors_max = self.neighbors_max.max(solved.debug.neighbor_count);
+        self.constraints += solved.debug.constraint_count;
+        self.gather += gather;
+        self.constraint_build += constraint_build;
+        self.solve += solve;
     }
 
-    constraints
+    fn merge(self, other: Self) -> Self {
+        Self {
+            agents: self.agents + other.agents,
+            neighbors: self.neighbors + other.neighbors,
+            neighbors_max: self.neighbors_max.max(other.neighbors_max),
+            constraints: self.constraints + other.constraints,
+            gather: self.gather + other.gather,
+            constraint_build: self.constraint_build + other.constraint_build,
+            solve: self.solve + other.solve,
+        }
+    }
 }
 
-fn solve_orca(
-    preferred_vel: Vec3,
-    _current_vel: Vec3,
-    constraints: &[OrcaConstraint],
-    max_speed: f32,
-) -> Vec3 {
-    let mut result = Vec2::new(preferred_vel.x, preferred_vel.z);
+// time since the last lap, zero when not timing
+fn lap(clock: &mut Option<Instant>) -> Duration {
+    let Some(last) = clock else {
+        return Duration::ZERO;
+    };
+
+    let now = Instant::now();
+    let elapsed = now - *last;
+    *last = now;
+    elapsed
+}
+
+fn solve_agent(
+    ctx: &SolveContext,
+    unit: Entity,
+    agent: &AgentSnapshot,
+    mut preferred_vel: Vec3,
+    scratch: &mut SolveScratch,
+) -> SolvedAgent {
+    let SolveScratch {
+        candidates,
+        neighbors,
+        far,
+        segments,
+        constraints,
+    } = scratch;
+    let dt = ctx.dt;
+    let pos = agent.position;
+    let settings = &agent.settings;
+    let mut clock = ctx.timed.then(Instant::now);
+
+    // cohesion reuses the neighbor gather, so widen it to cover the cohesion distance
+    let group = agent.group.filter(|_| settings.cohesion_weight > 0.0);
+    let search_range = match group {
+        Some(_) => settings.sensor_range.max(settings.cohesion_max_distance),
+        None => settings.sensor_range,
+    };
+    let cohesion_range_sq = settings.cohesion_max_distance.powi(2);
+    let avoids = |other: Entity| {
+        other != unit
+            && ctx
+                .snapshots
+                .get(&other)
+                .is_some_and(|o| agent.avoidance(o).is_some())
+    };
+
+    // everything close enough to matter, in whatever order the backend finds it
+    candidates.clear();
+    let bucket_search = match ctx.kd_tree {
+        Some(tree) => {
+            // the tree stores centers, widen by the largest radius so touching agents are not missed
+            tree.within(pos, search_range + ctx.max_radius, |other, _| {
+                candidates.push(other)
+            });
+            None
+        }
+        None => {
+            let (bx, by) = ctx.spatial.key(pos);
+            let bucket_size = ctx.spatial.bucket_size();
 
-    // clamp preferred to max speed
-    if result.length() > max_speed {
-        result = result.normalize_or_zero() * max_speed;
+            // expand bucket search to cover the search range
+            let bucket_radius_x = (search_range / bucket_size.x).ceil() as i32 + ctx.stale_margin;
+            let bucket_radius_y = (search_range / bucket_size.y).ceil() as i32 + ctx.stale_margin;
+
+            for dx in -bucket_radius_x..=bucket_radius_x {
+                for dy in -bucket_radius_y..=bucket_radius_y {
+                    candidates.extend_from_slice(ctx.spatial.bucket((bx + dx, by + dy)));
+                }
+            }
+            Some(((bx, by), bucket_radius_x, bucket_radius_y))
+        }
+    };
+
+    // the constraint order changes the solution, so lockstep clients have to agree on it
+    if ctx.deterministic {
+        candidates.sort_unstable();
     }
 
-    for c in constraints {
-        if (result - c.point).dot(c.normal) <= 0.0 {
+    neighbors.clear();
+    // agents found in the searched area but outside the sensor range, used to reach `min_neighbors`
+    far.clear();
+    let mut group_sum = pos;
+    let mut group_count = 1.0;
+    for &other in candidates.iter() {
+        if other == unit {
+            continue;
+        }
+
+        // may have been despawned since the spatial hash was updated
+        let Some(other_agent) = ctx.snapshots.get(&other) else {
             continue;
+        };
+
+        let dist_sq = pos.distance_squared(other_agent.position);
+        if group.is_some() && other_agent.group == group && dist_sq <= cohesion_range_sq {
+            group_sum += other_agent.position;
+            group_count += 1.0;
         }
 
-        // project onto constraint line
-        result = result - (result - c.point).dot(c.normal) * c.normal;
+        let Some(avoidance) = agent.avoidance(other_agent) else {
+            continue;
+        };
 
-        // clamp after projection
-        let len = result.length();
-        if len > max_speed {
-            result = result / len * max_speed;
+        let neighbor = Neighbor::new(other, other_agent, avoidance);
+        let range = settings.sensor_range + neighbor.radius;
+        if dist_sq <= range * range {
+            neighbors.push(neighbor);
+        } else {
+            far.push((dist_sq, neighbor));
         }
     }
 
-    Vec3::new(result.x, 0.0, result.y)
+    // sparse area: look further out until enough neighbors are found
+    let min_neighbors = settings.min_neighbors;
+    if let Some(tree) = ctx.kd_tree.filter(|_| neighbors.len() < min_neighbors) {
+        // take the closest agents regardless of distance
+        far.clear();
+        far.extend(
+            tree.nearest(pos, min_neighbors, avoids)
+                .into_iter()
+                .filter_map(|(_, other)| {
+                    let o = ctx.snapshots.get(&other)?;
+                    let neighbor = Neighbor::new(other, o, agent.avoidance(o)?);
+                    Some((pos.distance_squared(o.position), neighbor))
+                })
+                .filter(|(dist_sq, n)| *dist_sq > (settings.sensor_range + n.radius).powi(2)),
+        );
+    } else if let Some(((bx, by), bucket_radius_x, bucket_radius_y)) = bucket_search {
+        // widen the search ring by ring until the count is met or the cap is hit
+        let mut ring = 1;
+        while neighbors.len() + far.len() < min_neighbors && ring <= MAX_NEIGHBOR_SEARCH_RINGS {
+            let rx = bucket_radius_x + ring;
+            let ry = bucket_radius_y + ring;
+            for dx in -rx..=rx {
+                for dy in -ry..=ry {
+                    if dx.abs() != rx && dy.abs() != ry {
+                        continue;
+                    }
+
+                    for &other in ctx.spatial.bucket((bx + dx, by + dy)) {
+                        if other == unit {
+                            continue;
+                        }
+
+                        let Some(other_agent) = ctx.snapshots.get(&other) else {
+                            continue;
+                        };
+
+                        let Some(avoidance) = agent.avoidance(other_agent) else {
+                            continue;
+                        };
+
+                        let neighbor = Neighbor::new(other, other_agent, avoidance);
+                        far.push((pos.distance_squared(neighbor.position), neighbor));
+                    }
+                }
+            }
+            ring += 1;
+        }
+    }
+
+    // use the closest of the out-of-range agents, their constraints weaken with distance
+    if neighbors.len() < min_neighbors && !far.is_empty() {
+        far.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.entity.cmp(&b.1.entity)));
+        let missing = min_neighbors - neighbors.len();
+        for (dist_sq, mut neighbor) in far.drain(..).take(missing) {
+            let range = settings.sensor_range + neighbor.radius;
+            neighbor.weight = (range / dist_sq.sqrt().max(f32::EPSILON)).clamp(0.0, 1.0);
+            neighbors.push(neighbor);
+        }
+    }
+
+    // dense crowd: only the closest neighbors matter, the rest just add constraints
+    let max_neighbors = settings.max_neighbors;
+    if max_neighbors > 0 && neighbors.len() > max_neighbors {
+        let key = |n: &Neighbor| (pos.distance_squared(n.position), n.entity);
+        neighbors.select_nth_unstable_by(max_neighbors, |a, b| {
+            let (da, ea) = key(a);
+            let (db, eb) = key(b);
+            da.total_cmp(&db).then(ea.cmp(&eb))
+        });
+        neighbors.truncate(max_neighbors);
+    }
+
+    // moving obstacles never yield, so they are always considered regardless of the cap
+    neighbors.extend(ctx.obstacles.iter().copied().filter(|n| {
+        let range = settings.sensor_range + n.radius;
+        pos.distance_squared(n.position) <= range * range
+    }));
+
+    for neighbor in neighbors.iter_mut() {
+        neighbor.pass_right = ctx.symmetry_breaking.pass_right(unit, neighbor.entity);
+    }
+
+    let gather_time = lap(&mut clock);
+
+    // pull toward the group centroid, the result is still fed through the avoidance constraints
+    if group_count > 1.0 {
+        let to_centroid = group_sum / group_count - pos;
+        let to_centroid = Vec3::new(to_centroid.x, 0.0, to_centroid.z);
+        let max_dist = settings.cohesion_max_distance.max(0.1);
+        let pull = to_centroid.clamp_length_max(max_dist) / max_dist
+            * settings.cohesion_weight
+            * settings.preferred_speed;
+        let cap = preferred_vel.length().max(settings.preferred_speed);
+        preferred_vel = (preferred_vel + pull).clamp_length_max(cap);
+    }
+
+    // build ORCA-style half-plane constraints against neighbors
+    let (solved, infeasible, constraint_count, constraint_time) = match agent.space {
+        NavigationSpace::Planar => {
+            // walls first, the linear program never relaxes them when the agent constraints are infeasible
+            let range = settings.sensor_range + settings.radius;
+            obstacle_segments(ctx.grid, agent.nav_layer, pos, range, segments);
+            constraints.clear();
+            build_obstacle_constraints(pos, settings, segments, dt, constraints);
+            let obstacle_count = constraints.len();
+            build_orca_constraints(
+                pos,
+                agent.velocity,
+                settings,
+                neighbors,
+                dt,
+                ctx.model,
+                constraints,
+            );
+            let constraint_time = lap(&mut clock);
+
+            // choose the velocity closest to preferred that satisfies constraints
+            let (solved, infeasible) = solve_orca(
+                preferred_vel,
+                constraints,
+                obstacle_count,
+                settings.max_speed,
+                ctx.model != AvoidanceModel::Vo,
+            );
+
+            // too many conflicting constraints, a scored sample may beat the least-violation compromise
+            let solved = if infeasible && ctx.fallback_samples > 0 {
+                sample_velocity(
+                    pos,
+                    preferred_vel,
+                    solved,
+                    settings,
+                    neighbors,
+                    &constraints[..obstacle_count],
+                    ctx.fallback_samples,
+                )
+            } else {
+                solved
+            };
+            (solved, infeasible, constraints.len(), constraint_time)
+        }
+        NavigationSpace::Volumetric => {
+            // flying agents avoid each other as spheres and ignore the ground walls
+            let planes = build_orca_planes(pos, agent.velocity, settings, neighbors, dt, ctx.model);
+            let constraint_time = lap(&mut clock);
+            let (solved, infeasible) = solve_orca_3d(preferred_vel, &planes, settings.max_speed);
+            (solved, infeasible, planes.len(), constraint_time)
+        }
+    };
+
+    // overlaps take the "already colliding" branch of the constraint builders. Each pair is reported once, by the
+    // agent with the lower id, unless the other agent is not being solved this frame
+    let collisions: Vec<(Entity, f32)> = neighbors
+        .iter()
+        .filter(|n| unit < n.entity || !ctx.solved.contains(&n.entity))
+        .filter_map(|n| {
+            let offset = match agent.space {
+                NavigationSpace::Planar => (n.position - pos).with_y(0.0),
+                NavigationSpace::Volumetric => n.position - pos,
+            };
+            let penetration = settings.radius + n.radius - offset.length();
+            (penetration > 0.0).then_some((n.entity, penetration))
+        })
+        .collect();
+
+    // strong local separation if still intersecting, soft contacts instead push agents apart after they moved
+    let mut separation = Vec3::ZERO;
+    for neighbor in neighbors.iter().filter(|_| !ctx.soft_contacts) {
+        let offset = pos - neighbor.position;
+        let dist = offset.length();
+        let combined = settings.radius + neighbor.radius;
+        if dist < combined * 1.05 && dist > 1e-3 {
+            let push = (combined * 1.05 - dist) * dt.recip() * separation_share(settings, neighbor);
+            separation += offset.normalize() * push;
+        }
+    }
+
+    let desired_vel = (solved + separation).clamp_length_max(settings.max_speed);
+
+    // drive toward chosen velocity while respecting acceleration limits
+    let desired_accel = (desired_vel - agent.velocity).clamp_length_max(settings.max_accel);
+    let new_velocity =
+        (agent.velocity + desired_accel * dt).clamp_length_max(settings.max_speed + f32::EPSILON);
+
+    // low-pass the reaction to neighbors, a blend of two clamped velocities stays within the limits
+    let smoothing = settings.steering_smoothing.clamp(0.0, 0.99);
+    let new_velocity = new_velocity.lerp(agent.velocity, smoothing);
+
+    // vehicles can only turn so fast, the speed along the new heading is kept. The snapshot is already in plane space.
+    let max_turn = settings.max_angular_speed * dt;
+    let (new_velocity, heading) = limit_turn(Plane::XZ, agent.heading, new_velocity, max_turn);
+    let solve_time = lap(&mut clock);
+
+    SolvedAgent {
+        velocity: new_velocity,
+        heading,
+        debug: FvoDebugInfo {
+            neighbor_count: neighbors.len(),
+            constraint_count,
+            infeasible,
+            preferred_velocity: preferred_vel,
+            solved_velocity: new_velocity,
+        },
+        collisions,
+        neighbor_positions: if ctx.record_neighbors {
+            neighbors.iter().map(|n| n.position).collect()
+        } else {
+            Vec::new()
+        },
+        timings: [gather_time, constraint_time, solve_time],
+    }
+}
+
+/// Rotates `heading` toward `velocity` by at most `max_angle` radians on `plane`. Returns the velocity along the new
+/// heading, keeping only the part of the speed that points that way, and the new heading. Speed off the plane is kept.
+fn limit_turn(plane: Plane, heading: Vec3, velocity: Vec3, max_angle: f32) -> (Vec3, Vec3) {
+    let flat = plane.to_2d(velocity);
+    let Some(desired) = flat.try_normalize() else {
+        return (velocity, heading);
+    };
+
+    let from = plane.to_2d(heading);
+    let Some(from) = from.try_normalize().filter(|_| max_angle.is_finite()) else {
+        return (velocity, plane.from_2d(desired));
+    };
+
+    let angle = from.angle_to(desired);
+    if angle.abs() <= max_angle {
+        return (velocity, plane.from_2d(desired));
+    }
+
+    let turned = Vec2::from_angle(angle.signum() * max_angle.max(0.0)).rotate(from);
+    let speed = flat.dot(turned).max(0.0);
+    let heading = plane.from_2d(turned);
+    let up = plane.up();
+
+    (heading * speed + up * velocity.dot(up), heading)
+}
+
+/// Upper bound on the extra bucket rings searched to satisfy [`FvoSettings::min_neighbors`].
+const MAX_NEIGHBOR_SEARCH_RINGS: i32 = 4;
+
+#[derive(Clone, Copy)]
+struct Neighbor {
+    entity: Entity,
+    position: Vec3,
+    velocity: Vec3,
+    radius: f32,
+    priority: f32,
+    mass: f32,
+    /// Scales how strongly the constraint is enforced, `1.0` inside the sensor range.
+    weight: f32,
+    /// `false` for a [`DynamicObstacle`], which does not take its share of the avoidance, and for agents treated as
+    /// [`TeamAvoidance::Obstacle`].
+    reciprocal: bool,
+    /// Side the agent passes this neighbor on when they meet head-on, see [`SymmetryBreaking`].
+    pass_right: Option<bool>,
+}
+
+impl Neighbor {
+    fn new(entity: Entity, agent: &AgentSnapshot, avoidance: TeamAvoidance) -> Self {
+        Self {
+            entity,
+            position: agent.position,
+            velocity: agent.velocity,
+            radius: agent.settings.radius,
+            priority: agent.settings.priority,
+            mass: agent.settings.mass,
+            weight: 1.0,
+            reciprocal: avoidance == TeamAvoidance::Reciprocal,
+            pass_right: None,
+        }
+    }
+
+    fn obstacle(entity: Entity, position: Vec3, velocity: Vec3, radius: f32) -> Self {
+        Self {
+            entity,
+            position,
+            velocity,
+            radius,
+            priority: 0.0,
+            mass: 0.0,
+            weight: 1.0,
+            reciprocal: false,
+            pass_right: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct OrcaConstraint {
+    point: Vec2,
+    normal: Vec2,
+    weight: f32,
+}
+
+/// Share of the shift an agent takes against `neighbor`. Equal priorities and masses take the full shift so a single
+/// agent still reacts if the partner lags, otherwise the lighter or lower-priority side takes more of it (up to twice)
+/// and the other side less. Non-reciprocal obstacles always leave the full shift to the agent.
+fn responsibility(settings: &FvoSettings, neighbor: &Neighbor) -> f32 {
+    if !neighbor.reciprocal {
+        return 1.0;
+    }
+
+    share(
+        settings.priority.max(0.0) * settings.mass.max(0.0),
+        neighbor.priority.max(0.0) * neighbor.mass.max(0.0),
+    )
+}
+
+/// [`responsibility`] adjusted for the avoidance `model`. `passing_right` tells on which side of the neighbor the
+/// agent is passing, `None` when it is not passing along a leg of the velocity obstacle.
+fn model_responsibility(
+    model: AvoidanceModel,
+    settings: &FvoSettings,
+    neighbor: &Neighbor,
+    passing_right: Option<bool>,
+) -> f32 {
+    match model {
+        AvoidanceModel::Orca => responsibility(settings, neighbor),
+        AvoidanceModel::Rvo => 0.5 * responsibility(settings, neighbor),
+        AvoidanceModel::Hrvo if passing_right == Some(false) => responsibility(settings, neighbor),
+        AvoidanceModel::Hrvo => 0.5 * responsibility(settings, neighbor),
+        AvoidanceModel::Vo => 1.0,
+    }
+}
+
+/// Share of the separation push an agent takes against `neighbor`, split by mass like [`responsibility`].
+fn separation_share(settings: &FvoSettings, neighbor: &Neighbor) -> f32 {
+    if !neighbor.reciprocal {
+        return 1.0;
+    }
+
+    share(settings.mass.max(0.0), neighbor.mass.max(0.0))
+}
+
+/// `1.0` when both weights are equal, up to `2.0` when `other` outweighs `own`.
+fn share(own: f32, other: f32) -> f32 {
+    let total = own + other;
+    if total > f32::EPSILON {
+        2.0 * other / total
+    } else {
+        1.0
+    }
+}
+
+/// Sine of the largest angle between the relative velocity and the line to a neighbor that counts as head-on.
+const HEAD_ON_SIN: f32 = 0.1;
+
+/// Neighbors processed at once by [`build_orca_constraints`].
+const LANES: usize = 4;
+
+/// Up to [`LANES`] neighbors in structure-of-arrays layout, one neighbor per lane. Missing neighbors of the last
+/// chunk are zeroed lanes whose results are discarded.
+struct NeighborLanes {
+    x: f32x4,
+    z: f32x4,
+    vel_x: f32x4,
+    vel_z: f32x4,
+    radius: f32x4,
+    /// Set where the neighbor has a head-on side preference, see [`Neighbor::pass_right`].
+    has_side: f32x4,
+    /// Set where the preferred side is the right.
+    side_right: f32x4,
+}
+
+impl NeighborLanes {
+    fn load(chunk: &[Neighbor]) -> Self {
+        let lanes = |f: &dyn Fn(&Neighbor) -> f32| {
+            let mut lanes = [0.0; LANES];
+            for (lane, neighbor) in lanes.iter_mut().zip(chunk) {
+                *lane = f(neighbor);
+            }
+            f32x4::from(lanes)
+        };
+        let mask = |set: bool| if set { f32::from_bits(u32::MAX) } else { 0.0 };
+
+        Self {
+            x: lanes(&|n| n.position.x),
+            z: lanes(&|n| n.position.z),
+            vel_x: lanes(&|n| n.velocity.x),
+            vel_z: lanes(&|n| n.velocity.z),
+            radius: lanes(&|n| n.radius),
+            has_side: lanes(&|n| mask(n.pass_right.is_some())),
+            side_right: lanes(&|n| mask(n.pass_right == Some(true))),
+        }
+    }
+}
+
+/// Builds the ORCA half-planes against every neighbor, [`LANES`] neighbors at a time. Every lane takes all three
+/// branches (already colliding, cutoff circle and legs of the velocity obstacle) and the masks pick the result, so
+/// there is no branching per neighbor.
+fn build_orca_constraints(
+    current_pos: Vec3,
+    current_vel: Vec3,
+    settings: &FvoSettings,
+    neighbors: &[Neighbor],
+    dt: f32,
+    model: AvoidanceModel,
+    constraints: &mut Vec<OrcaConstraint>,
+) {
+    constraints.reserve(neighbors.len());
+    let inv_tau = f32x4::splat(1.0 / settings.time_horizon_agents.max(0.001));
+    let inv_dt = f32x4::splat(1.0 / dt.max(0.001));
+    let zero = f32x4::ZERO;
+
+    let self_vel = Vec2::new(current_vel.x, current_vel.z);
+    let (pos_x, pos_z) = (f32x4::splat(current_pos.x), f32x4::splat(current_pos.z));
+    let (vel_x, vel_z) = (f32x4::splat(current_vel.x), f32x4::splat(current_vel.z));
+    let radius = f32x4::splat(settings.radius);
+
+    for chunk in neighbors.chunks(LANES) {
+        let n = NeighborLanes::load(chunk);
+
+        let rel_pos_x = n.x - pos_x;
+        let rel_pos_y = n.z - pos_z;
+        let rel_vel_x = vel_x - n.vel_x;
+        let rel_vel_y = vel_z - n.vel_z;
+        let combined_radius = radius + n.radius;
+        let combined_radius_sq = combined_radius * combined_radius;
+        let dist_sq = rel_pos_x * rel_pos_x + rel_pos_y * rel_pos_y;
+        let dist = dist_sq.sqrt();
+        let colliding = dist_sq.cmp_le(combined_radius_sq);
+
+        // already colliding: push away aggressively using timestep
+        let dist_col = dist.max(f32x4::splat(1e-3));
+        let col_n_x = rel_pos_x / dist_col;
+        let col_n_y = rel_pos_y / dist_col;
+        let col_shift = (combined_radius - dist_col) * inv_dt;
+
+        // not colliding: use time horizon to build half-plane
+        let w_x = rel_vel_x - rel_pos_x * inv_tau;
+        let w_y = rel_vel_y - rel_pos_y * inv_tau;
+        let w_len_sq = w_x * w_x + w_y * w_y;
+        let dot = w_x * rel_pos_x + w_y * rel_pos_y;
+        // project on truncated VO cone (from RVO2)
+        let cutoff = dot.cmp_lt(zero) & (dot * dot).cmp_gt(combined_radius_sq * w_len_sq);
+
+        // project on cutoff circle at horizon
+        let w_len = w_len_sq.sqrt().max(f32x4::splat(f32::EPSILON));
+        let cut_n_x = w_x / w_len;
+        let cut_n_y = w_y / w_len;
+        let cut_shift = combined_radius * inv_tau - w_len;
+
+        // legs of the VO
+        let leg = (dist_sq - combined_radius_sq).max(zero).sqrt();
+        let unit_x = rel_pos_x / dist;
+        let unit_y = rel_pos_y / dist;
+        let left_x = (unit_x * leg - unit_y * combined_radius) / dist;
+        let left_y = (unit_x * combined_radius + unit_y * leg) / dist;
+        let right_x = (unit_x * leg + unit_y * combined_radius) / dist;
+        let right_y = (-unit_x * combined_radius + unit_y * leg) / dist;
+
+        let cross = rel_vel_x * rel_pos_y - rel_vel_y * rel_pos_x;
+        // head-on the sign of `cross` flips with the slightest nudge, keep to the agreed side instead
+        let rel_speed = (rel_vel_x * rel_vel_x + rel_vel_y * rel_vel_y).sqrt();
+        let head_on = cross
+            .abs()
+            .cmp_le(f32x4::splat(HEAD_ON_SIN) * rel_speed * dist);
+        let pass_right = (head_on & n.has_side).blend(n.side_right, cross.cmp_le(zero));
+
+        let dir_x = pass_right.blend(right_x, left_x);
+        let dir_y = pass_right.blend(right_y, left_y);
+        // outward normal
+        let dir_len = (dir_x * dir_x + dir_y * dir_y).sqrt();
+        let has_dir = dir_len.cmp_gt(zero);
+        let leg_n_x = has_dir.blend(-dir_y / dir_len, zero);
+        let leg_n_y = has_dir.blend(dir_x / dir_len, zero);
+        let leg_shift = rel_vel_x * leg_n_x + rel_vel_y * leg_n_y;
+
+        let normal_x = colliding.blend(col_n_x, cutoff.blend(cut_n_x, leg_n_x));
+        let normal_y = colliding.blend(col_n_y, cutoff.blend(cut_n_y, leg_n_y));
+        let shift = colliding.blend(col_shift, cutoff.blend(cut_shift, leg_shift));
+        let on_legs = !(colliding | cutoff);
+
+        let (normal_x, normal_y, shift) =
+            (normal_x.to_array(), normal_y.to_array(), shift.to_array());
+        let (on_legs, pass_right) = (on_legs.to_array(), pass_right.to_array());
+        for (lane, neighbor) in chunk.iter().enumerate() {
+            let normal = Vec2::new(normal_x[lane], normal_y[lane]);
+            let passing_right =
+                (on_legs[lane].to_bits() != 0).then_some(pass_right[lane].to_bits() != 0);
+            let responsibility = model_responsibility(model, settings, neighbor, passing_right);
+            constraints.push(OrcaConstraint {
+                point: self_vel + normal * shift[lane] * responsibility,
+                normal,
+                weight: neighbor.weight,
+            });
+        }
+    }
+}
+
+/// Collects the edges of cells blocked on `layer` within `range` of `pos` that face a walkable cell. Edges shared by
+/// two blocked cells are interior to a wall and skipped.
+fn obstacle_segments(
+    grid: &Grid,
+    layer: NavLayer,
+    pos: Vec3,
+    range: f32,
+    segments: &mut Vec<(Vec2, Vec2)>,
+) {
+    segments.clear();
+    if grid.grid.is_empty() {
+        return;
+    }
+
+    // walls are found on the grid's local plane and turned into plane space at the end
+    let local = grid.world_to_local(grid.plane.from_plane(pos));
+    let to_world = |p: Vec2| {
+        grid.plane
+            .to_2d(grid.local_to_world(Vec3::new(p.x, 0.0, p.y)))
+    };
+    let cell_size = grid.cell_size;
+    let offset = -grid.world_size() / 2.0;
+    let cx = ((local.x - offset.x) / cell_size.x).floor() as i32;
+    let cy = ((local.z - offset.y) / cell_size.y).floor() as i32;
+    let scale = grid.plane.to_plane(grid.transform.scale).abs().xz();
+    let reach = (Vec2::splat(range) / (cell_size * scale)).ceil().as_ivec2() + IVec2::ONE;
+
+    let blocked = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && x < grid.size.x
+            && y < grid.size.y
+            && grid.layer_cost(layer, IVec2::new(x, y)) == u8::MAX
+    };
+
+    let pos2d = Vec2::new(pos.x, pos.z);
+    for y in (cy - reach.y).max(0)..=(cy + reach.y).min(grid.size.y - 1) {
+        for x in (cx - reach.x).max(0)..=(cx + reach.x).min(grid.size.x - 1) {
+            if !blocked(x, y) {
+                continue;
+            }
+
+            let min = offset + Vec2::new(x as f32, y as f32) * cell_size;
+            let max = min + cell_size;
+            let edges = [
+                ((x, y - 1), Vec2::new(min.x, min.y), Vec2::new(max.x, min.y)),
+                ((x + 1, y), Vec2::new(max.x, min.y), Vec2::new(max.x, max.y)),
+                ((x, y + 1), Vec2::new(max.x, max.y), Vec2::new(min.x, max.y)),
+                ((x - 1, y), Vec2::new(min.x, max.y), Vec2::new(min.x, min.y)),
+            ];
+
+            for ((nx, ny), a, b) in edges {
+                if blocked(nx, ny) {
+                    continue;
+                }
+
+                let (a, b) = (to_world(a), to_world(b));
+                if closest_point_on_segment(pos2d, a, b).distance(pos2d) <= range {
+                    segments.push((a, b));
+                }
+            }
+        }
+    }
+}
+
+fn closest_point_on_segment(p: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+    a + ab * t
+}
+
+/// Builds one half-plane per wall segment: within `time_horizon_obstacles` the agent may not close more than the gap
+/// between its edge and the closest point of the segment. Overlapping agents are pushed back out within a single
+/// timestep.
+fn build_obstacle_constraints(
+    current_pos: Vec3,
+    settings: &FvoSettings,
+    segments: &[(Vec2, Vec2)],
+    dt: f32,
+    constraints: &mut Vec<OrcaConstraint>,
+) {
+    let inv_tau = 1.0 / settings.time_horizon_obstacles.max(0.001);
+    let inv_dt = 1.0 / dt.max(0.001);
+    let pos = Vec2::new(current_pos.x, current_pos.z);
+
+    constraints.extend(segments.iter().filter_map(|&(a, b)| {
+        let to_wall = closest_point_on_segment(pos, a, b) - pos;
+        let dist = to_wall.length();
+        if dist < 1e-4 {
+            return None;
+        }
+
+        let normal = to_wall / dist;
+        let gap = dist - settings.radius;
+        let max_approach = if gap > 0.0 {
+            gap * inv_tau
+        } else {
+            gap * inv_dt
+        };
+
+        Some(OrcaConstraint {
+            point: normal * max_approach,
+            normal,
+            weight: 1.0,
+        })
+    }));
+}
+
+/// 3D counterpart of [`OrcaConstraint`], violated when `(v - point) · normal > 0`.
+#[derive(Clone, Copy)]
+struct OrcaPlane {
+    point: Vec3,
+    normal: Vec3,
+    weight: f32,
+}
+
+/// Builds half-space constraints against neighbors treated as spheres (RVO2-3D).
+fn build_orca_planes(
+    current_pos: Vec3,
+    current_vel: Vec3,
+    settings: &FvoSettings,
+    neighbors: &[Neighbor],
+    dt: f32,
+    model: AvoidanceModel,
+) -> Vec<OrcaPlane> {
+    let inv_tau = 1.0 / settings.time_horizon_agents.max(0.001);
+    let inv_dt = 1.0 / dt.max(0.001);
+
+    neighbors
+        .iter()
+        .filter_map(|neighbor| {
+            let rel_pos = neighbor.position - current_pos;
+            let rel_vel = current_vel - neighbor.velocity;
+            let combined_radius = settings.radius + neighbor.radius;
+            let combined_radius_sq = combined_radius * combined_radius;
+            let dist_sq = rel_pos.length_squared();
+
+            let (w, scale) = if dist_sq > combined_radius_sq {
+                let w = rel_vel - rel_pos * inv_tau;
+                let dot = w.dot(rel_pos);
+
+                if dot < 0.0 && dot * dot > combined_radius_sq * w.length_squared() {
+                    // project on cutoff sphere at horizon
+                    (w, combined_radius * inv_tau)
+                } else {
+                    // project on the cone
+                    let a = dist_sq;
+                    let b = rel_pos.dot(rel_vel);
+                    let c = rel_vel.length_squared()
+                        - rel_pos.cross(rel_vel).length_squared() / (dist_sq - combined_radius_sq);
+                    let t = (b + (b * b - a * c).max(0.0).sqrt()) / a;
+                    (rel_vel - rel_pos * t, combined_radius * t)
+                }
+            } else {
+                // already colliding: resolve within one timestep
+                (rel_vel - rel_pos * inv_dt, combined_radius * inv_dt)
+            };
+
+            let w_len = w.length();
+            if w_len < 1e-6 {
+                return None;
+            }
+
+            let unit_w = w / w_len;
+            let u = unit_w * (scale - w_len);
+
+            Some(OrcaPlane {
+                // there is no left or right around a sphere, HRVO splits like RVO
+                point: current_vel + u * model_responsibility(model, settings, neighbor, None),
+                normal: -unit_w,
+                weight: neighbor.weight,
+            })
+        })
+        .collect()
+}
+
+/// Iteratively projects `preferred_vel` onto the violated half-spaces and the `max_speed` sphere. Returns the
+/// velocity and whether a constraint was still violated after the last pass.
+fn solve_orca_3d(preferred_vel: Vec3, planes: &[OrcaPlane], max_speed: f32) -> (Vec3, bool) {
+    const PASSES: usize = 8;
+
+    let mut result = preferred_vel.clamp_length_max(max_speed);
+    for _ in 0..PASSES {
+        let mut changed = false;
+        for p in planes {
+            let violation = (result - p.point).dot(p.normal);
+            if violation <= 0.0 {
+                continue;
+            }
+
+            result = (result - violation * p.weight * p.normal).clamp_length_max(max_speed);
+            changed = true;
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let infeasible = planes
+        .iter()
+        .any(|p| p.weight >= 1.0 && (result - p.point).dot(p.normal) > 1e-3);
+
+    (result, infeasible)
+}
+
+/// Picks the velocity closest to `preferred_vel` inside every constraint and the `max_speed` disc (RVO2 linear
+/// programs). If no such velocity exists the maximum violation of the agent constraints is minimized instead, the
+/// first `obstacle_count` constraints stay hard. Returns the velocity and whether that fallback was needed.
+///
+/// Constraints weighted below `1.0` (far neighbors) are soft: they only bend the preferred velocity beforehand.
+/// Without `fallback` the partial solution of the failed program is returned as is.
+fn solve_orca(
+    preferred_vel: Vec3,
+    constraints: &[OrcaConstraint],
+    obstacle_count: usize,
+    max_speed: f32,
+    fallback: bool,
+) -> (Vec3, bool) {
+    let mut preferred = Vec2::new(preferred_vel.x, preferred_vel.z);
+    for c in constraints.iter().filter(|c| c.weight < 1.0) {
+        let violation = (preferred - c.point).dot(c.normal);
+        if violation > 0.0 {
+            preferred -= violation * c.weight * c.normal;
+        }
+    }
+
+    // RVO2 lines keep the valid side on their left
+    let lines: Vec<Line> = constraints
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| *i < obstacle_count || c.weight >= 1.0)
+        .map(|(_, c)| Line {
+            point: c.point,
+            direction: Vec2::new(-c.normal.y, c.normal.x),
+        })
+        .collect();
+
+    let mut result = Vec2::ZERO;
+    let fail = linear_program2(&lines, max_speed, preferred, false, &mut result);
+    let infeasible = fail < lines.len();
+    if infeasible && fallback {
+        linear_program3(&lines, obstacle_count, fail, max_speed, &mut result);
+    }
+
+    (Vec3::new(result.x, 0.0, result.y), infeasible)
+}
+
+/// Weight of the time-to-collision penalty of [`sample_velocity`], in world units (RVO1 safety factor).
+const SAMPLING_SAFETY_FACTOR: f32 = 7.5;
+
+/// Velocity-sampling fallback (ClearPath / RVO1 style). Scores `fallback`, the clamped preferred velocity and
+/// `samples` velocities spread evenly over the `max_speed` disc by their distance to `preferred_vel` plus
+/// [`SAMPLING_SAFETY_FACTOR`] divided by the time to the first collision with a neighbor, and returns the cheapest.
+/// Candidates crossing a wall constraint are rejected. The samples follow a fixed spiral, so the result stays
+/// deterministic.
+fn sample_velocity(
+    pos: Vec3,
+    preferred_vel: Vec3,
+    fallback: Vec3,
+    settings: &FvoSettings,
+    neighbors: &[Neighbor],
+    obstacle_constraints: &[OrcaConstraint],
+    samples: usize,
+) -> Vec3 {
+    let max_speed = settings.max_speed;
+    let preferred = Vec2::new(preferred_vel.x, preferred_vel.z);
+    let golden_angle = PI * (3.0 - 5.0_f32.sqrt());
+
+    let candidates = (0..samples).map(|i| {
+        let radius = max_speed * ((i as f32 + 0.5) / samples as f32).sqrt();
+        Vec2::from_angle(i as f32 * golden_angle) * radius
+    });
+
+    let mut best = Vec2::new(fallback.x, fallback.z);
+    let mut best_penalty = f32::INFINITY;
+    for candidate in [best, preferred.clamp_length_max(max_speed)]
+        .into_iter()
+        .chain(candidates)
+    {
+        let crosses_wall = obstacle_constraints
+            .iter()
+            .any(|c| (candidate - c.point).dot(c.normal) > LP_EPSILON);
+        if crosses_wall {
+            continue;
+        }
+
+        let time_to_collision = neighbors
+            .iter()
+            .map(|n| time_to_collision(pos, candidate, settings.radius, n))
+            .fold(f32::INFINITY, f32::min);
+        let penalty = candidate.distance(preferred)
+            + SAMPLING_SAFETY_FACTOR / time_to_collision.max(f32::EPSILON);
+        if penalty < best_penalty {
+            best_penalty = penalty;
+            best = candidate;
+        }
+    }
+
+    Vec3::new(best.x, 0.0, best.y)
+}
+
+/// Time until an agent at `pos` moving at `velocity` touches `neighbor` on the XZ plane, `f32::INFINITY` if never.
+/// Overlapping agents collide immediately unless they are already moving apart.
+fn time_to_collision(pos: Vec3, velocity: Vec2, radius: f32, neighbor: &Neighbor) -> f32 {
+    let rel_pos = Vec2::new(neighbor.position.x - pos.x, neighbor.position.z - pos.z);
+    let rel_vel = velocity - Vec2::new(neighbor.velocity.x, neighbor.velocity.z);
+    let combined_radius = radius + neighbor.radius;
+
+    let c = rel_pos.length_squared() - combined_radius * combined_radius;
+    let b = rel_pos.dot(rel_vel);
+    if c < 0.0 {
+        return if b > 0.0 { 0.0 } else { f32::INFINITY };
+    }
+
+    let a = rel_vel.length_squared();
+    let discriminant = b * b - a * c;
+    if b <= 0.0 || discriminant <= 0.0 {
+        return f32::INFINITY;
+    }
+
+    (b - discriminant.sqrt()) / a
+}
+
+#[derive(Clone, Copy)]
+struct Line {
+    point: Vec2,
+    direction: Vec2,
+}
+
+const LP_EPSILON: f32 = 1e-5;
+
+fn det(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Optimizes along line `line_no` subject to the previous lines and the speed disc.
+fn linear_program1(
+    lines: &[Line],
+    line_no: usize,
+    radius: f32,
+    opt_velocity: Vec2,
+    direction_opt: bool,
+    result: &mut Vec2,
+) -> bool {
+    let line = lines[line_no];
+    let dot = line.point.dot(line.direction);
+    let discriminant = dot * dot + radius * radius - line.point.length_squared();
+    if discriminant < 0.0 {
+        // the speed disc does not reach this line
+        return false;
+    }
+
+    let sqrt_discriminant = discriminant.sqrt();
+    let mut t_left = -dot - sqrt_discriminant;
+    let mut t_right = -dot + sqrt_discriminant;
+
+    for other in &lines[..line_no] {
+        let denominator = det(line.direction, other.direction);
+        let numerator = det(other.direction, line.point - other.point);
+
+        if denominator.abs() <= LP_EPSILON {
+            // parallel lines
+            if numerator < 0.0 {
+                return false;
+            }
+            continue;
+        }
+
+        let t = numerator / denominator;
+        if denominator >= 0.0 {
+            t_right = t_right.min(t);
+        } else {
+            t_left = t_left.max(t);
+        }
+
+        if t_left > t_right {
+            return false;
+        }
+    }
+
+    let t = if direction_opt {
+        if opt_velocity.dot(line.direction) > 0.0 {
+            t_right
+        } else {
+            t_left
+        }
+    } else {
+        line.direction
+            .dot(opt_velocity - line.point)
+            .clamp(t_left, t_right)
+    };
+    *result = line.point + t * line.direction;
+
+    true
+}
+
+/// Returns the index of the first line that could not be satisfied, `lines.len()` on success.
+fn linear_program2(
+    lines: &[Line],
+    radius: f32,
+    opt_velocity: Vec2,
+    direction_opt: bool,
+    result: &mut Vec2,
+) -> usize {
+    *result = if direction_opt {
+        // opt_velocity is a unit direction here
+        opt_velocity * radius
+    } else {
+        opt_velocity.clamp_length_max(radius)
+    };
+
+    for i in 0..lines.len() {
+        if det(lines[i].direction, lines[i].point - *result) > 0.0 {
+            let previous = *result;
+            if !linear_program1(lines, i, radius, opt_velocity, direction_opt, result) {
+                *result = previous;
+                return i;
+            }
+        }
+    }
+
+    lines.len()
+}
+
+/// Minimizes the largest violation of the lines from `begin_line` on, keeping the first `obstacle_count` hard.
+fn linear_program3(
+    lines: &[Line],
+    obstacle_count: usize,
+    begin_line: usize,
+    radius: f32,
+    result: &mut Vec2,
+) {
+    let mut distance = 0.0;
+
+    for i in begin_line..lines.len() {
+        if det(lines[i].direction, lines[i].point - *result) <= distance {
+            continue;
+        }
+
+        let mut projected: Vec<Line> = lines[..obstacle_count].to_vec();
+        for j in obstacle_count..i {
+            let determinant = det(lines[i].direction, lines[j].direction);
+            let point = if determinant.abs() <= LP_EPSILON {
+                if lines[i].direction.dot(lines[j].direction) > 0.0 {
+                    // same direction, line j is already covered
+                    continue;
+                }
+                0.5 * (lines[i].point + lines[j].point)
+            } else {
+                lines[i].point
+                    + (det(lines[j].direction, lines[i].point - lines[j].point) / determinant)
+                        * lines[i].direction
+            };
+
+            projected.push(Line {
+                point,
+                direction: (lines[j].direction - lines[i].direction).normalize_or_zero(),
+            });
+        }
+
+        let previous = *result;
+        let opt = Vec2::new(-lines[i].direction.y, lines[i].direction.x);
+        if linear_program2(&projected, radius, opt, true, result) < projected.len() {
+            // should not happen in theory, keep the previous result on numerical trouble
+            *result = previous;
+        }
+
+        distance = det(lines[i].direction, lines[i].point - *result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::{
+        events::InitializeFlowFieldEv,
+        flowfield::{FlowField, FlowFieldConfig, FlowfieldPlugin},
+        grid::GridPlugin,
+    };
+
+    const DT: f32 = 1.0 / 60.0;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            FvoPlugin::new().with_apply_translation(),
+            FlowfieldPlugin,
+        ))
+        .insert_resource(Grid::new(10.0, IVec2::new(50, 50), 4.0))
+        // build flowfields right away instead of on the task pool
+        .insert_resource(FlowFieldConfig {
+            async_compute: false,
+            ..default()
+        })
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            DT,
+        )))
+        // flowfields and the debug gizmos need somewhere to go
+        .init_resource::<Assets<Mesh>>()
+        .init_resource::<Assets<StandardMaterial>>()
+        .init_resource::<Assets<bevy::gizmos::GizmoAsset>>()
+        .init_gizmo_group::<DefaultGizmoConfigGroup>();
+        app
+    }
+
+    fn run(app: &mut App, frames: usize) {
+        for _ in 0..frames {
+            app.update();
+        }
+    }
+
+    fn spawn_agent(app: &mut App, position: Vec3, settings: FvoSettings) -> Entity {
+        let tf = Transform::from_translation(position);
+        app.world_mut().spawn((tf, FvoAgent::new(settings))).id()
+    }
+
+    // sends `units` to `goal` on a new flowfield
+    fn order(app: &mut App, units: &[Entity], goal: Vec3) -> Entity {
+        app.world_mut().trigger(InitializeFlowFieldEv {
+            entities: units.to_vec(),
+            destination_pos: goal,
+            extra_destinations: Vec::new(),
+        });
+        app.world_mut().flush();
+        app.world_mut()
+            .query::<(Entity, &FlowField)>()
+            .iter(app.world())
+            .find(|(_, ff)| ff.units.contains(&units[0]))
+            .map(|(ff, _)| ff)
+            .unwrap()
+    }
+
+    fn position(app: &App, unit: Entity) -> Vec3 {
+        app.world().get::<Transform>(unit).unwrap().translation
+    }
+
+    fn mean_pairwise_distance(app: &App, units: &[Entity]) -> f32 {
+        let positions: Vec<Vec3> = units.iter().map(|&unit| position(app, unit)).collect();
+        let mut sum = 0.0;
+        let mut pairs = 0.0;
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                sum += a.distance(*b);
+                pairs += 1.0;
+            }
+        }
+        sum / pairs
+    }
+
+    // spread of a column of agents marching east after two seconds
+    fn column_spread(cohesion_weight: f32) -> f32 {
+        let mut app = app();
+        let settings = FvoSettings {
+            cohesion_weight,
+            cohesion_max_distance: 60.0,
+            ..default()
+        };
+        let units: Vec<Entity> = (0..6)
+            .map(|i| {
+                let unit = spawn_agent(
+                    &mut app,
+                    Vec3::new(-90.0 + i as f32 * 12.0, 0.0, 2.0),
+                    settings,
+                );
+                app.world_mut().entity_mut(unit).insert(GroupId(1));
+                unit
+            })
+            .collect();
+        order(&mut app, &units, Vec3::new(90.0, 0.0, 2.0));
+
+        run(&mut app, 120);
+        mean_pairwise_distance(&app, &units)
+    }
+
+    #[test]
+    fn cohesion_pulls_a_scattered_group_together() {
+        let loose = column_spread(0.0);
+        let tight = column_spread(1.0);
+        assert!(
+            tight < loose * 0.75,
+            "{tight} with cohesion vs {loose} without"
+        );
+    }
+
+    #[test]
+    fn arrival_curves_slow_down_before_the_goal() {
+        let curves = [
+            ArrivalCurve::Linear,
+            ArrivalCurve::Quadratic,
+            ArrivalCurve::SmoothStep,
+            ArrivalCurve::Custom(EaseFunction::CubicOut),
+        ];
+        // without a slow radius agents brake from twice their sensor range
+        assert_eq!(ArrivalSettings::default().speed_scale(24.0, 12.0), 1.0);
+        assert!(ArrivalSettings::default().speed_scale(20.0, 12.0) < 1.0);
+
+        let mut speeds = Vec::new();
+        for curve in curves {
+            let arrival = ArrivalSettings::new(40.0, 0.0, curve);
+            assert_eq!(arrival.speed_scale(0.0, 12.0), 0.0, "{curve:?} at the goal");
+            assert_eq!(
+                arrival.speed_scale(40.0, 12.0),
+                1.0,
+                "{curve:?} at the slow radius"
+            );
+
+            // head north, slow enough and with a wide enough radius for the brakes to follow the curve
+            let mut app = app();
+            let settings = FvoSettings {
+                arrival,
+                ..FvoSettings::new(10.0, 12.0, 20.0, 3.0, 1.0, 8.0)
+            };
+            let goal = Vec3::new(2.0, 0.0, -38.0);
+            let unit = spawn_agent(&mut app, goal + Vec3::Z * 100.0, settings);
+            order(&mut app, &[unit], goal);
+
+            // speed while passing 15 units out, until the agent arrives
+            let mut speed = None;
+            for _ in 0..2400 {
+                app.update();
+                if speed.is_none() && position(&app, unit).distance(goal) < 15.0 {
+                    speed = Some(app.world().get::<FvoAgent>(unit).unwrap().velocity.length());
+                }
+                if !app.world().entity(unit).contains::<Destination>() {
+                    break;
+                }
+            }
+
+            // arrived on the near side of the goal
+            assert!(
+                !app.world().entity(unit).contains::<Destination>(),
+                "{curve:?} never arrived"
+            );
+            let end = position(&app, unit);
+            assert!(
+                end.z > goal.z && end.distance(goal) < 8.0,
+                "{curve:?} arrived at {end}"
+            );
+            speeds.push(speed.unwrap());
+        }
+
+        // quadratic brakes hardest this far out, cubic out keeps going the longest
+        let [linear, quadratic, smooth_step, cubic_out] = speeds[..] else {
+            unreachable!();
+        };
+        assert!(quadratic < linear && quadratic < smooth_step, "{speeds:?}");
+        assert!(linear < cubic_out && smooth_step < cubic_out, "{speeds:?}");
+    }
+
+    #[test]
+    fn settled_agent_stays_put() {
+        let mut app = app();
+        let goal = Vec3::new(2.0, 0.0, -38.0);
+        let unit = spawn_agent(&mut app, goal + Vec3::Z * 40.0, default());
+        let ff = order(&mut app, &[unit], goal);
+
+        for _ in 0..1200 {
+            app.update();
+            if app.world().get_entity(ff).is_err() {
+                break;
+            }
+        }
+        assert!(
+            app.world().get_entity(ff).is_err(),
+            "flowfield never despawned"
+        );
+
+        // the flowfield is gone, nothing steers the agent any more
+        let settled = position(&app, unit);
+        run(&mut app, 120);
+        assert_eq!(
+            app.world().get::<FvoAgent>(unit).unwrap().velocity,
+            Vec3::ZERO
+        );
+        assert_eq!(position(&app, unit), settled);
+    }
+
+    #[test]
+    fn removed_obstacles_restore_the_cost_below() {
+        let mut app = app();
+        app.add_plugins(GridPlugin);
+        app.world_mut()
+            .resource_mut::<Grid>()
+            .set_cost(IVec2::new(25, 25), 7);
+        let cost = |app: &App| app.world().resource::<Grid>().grid[25][25].cost;
+
+        // a crate pushed against a wall, both covering the painted cell
+        let at = Transform::from_xyz(2.0, 0.0, 2.0);
+        let wall = app.world_mut().spawn((at, Obstacle(Vec2::splat(2.0)))).id();
+        let crate_ = app
+            .world_mut()
+            .spawn((at, Obstacle(Vec2::new(6.0, 2.0))))
+            .id();
+        assert_eq!(cost(&app), u8::MAX);
+
+        app.world_mut().despawn(crate_);
+        assert_eq!(cost(&app), u8::MAX, "the wall still stands");
+
+        app.world_mut().despawn(wall);
+        assert_eq!(cost(&app), 7);
+    }
+
+    #[test]
+    fn opened_gates_keep_the_costs_below() {
+        let mut app = app();
+        app.add_plugins(GridPlugin);
+        app.world_mut()
+            .resource_mut::<Grid>()
+            .set_cost(IVec2::new(24, 25), 7);
+        let cost = |app: &App, x: usize| app.world().resource::<Grid>().grid[25][x].cost;
+
+        // a three cell gate over painted mud, its middle cell walled in
+        let at = Transform::from_xyz(2.0, 0.0, 2.0);
+        let gate = app
+            .world_mut()
+            .spawn((at, Obstacle(Vec2::new(6.0, 2.0)), Gate { open: false }))
+            .id();
+        app.world_mut().spawn((at, Obstacle(Vec2::splat(2.0))));
+        run(&mut app, 1);
+
+        app.world_mut().get_mut::<Gate>(gate).unwrap().open = true;
+        run(&mut app, 1);
+        assert_eq!(cost(&app, 24), 7);
+        assert_eq!(cost(&app, 25), u8::MAX, "the wall still stands");
+        assert_eq!(cost(&app, 26), 1);
+    }
+
+    #[test]
+    fn agents_driven_by_a_closure_move() {
+        let mut app = app();
+        let unit = spawn_agent(&mut app, Vec3::new(2.0, 0.0, 2.0), default());
+        app.world_mut()
+            .entity_mut(unit)
+            .insert(PreferredVelocityFn::new(|_, _, agent| {
+                Some(Vec3::X * agent.settings.preferred_speed)
+            }));
+
+        run(&mut app, 30);
+        assert!(position(&app, unit).x > 5.0, "{}", position(&app, unit));
+    }
+
+    // path of an agent passing another one nearly head on, with 8 unit spatial buckets
+    fn passing_path(rebuild_interval: u32) -> Vec<Vec3> {
+        let mut app = app();
+        app.insert_resource(Grid::new(25.0, IVec2::new(50, 50), 4.0))
+            .insert_resource(FvoConfig {
+                rebuild_interval,
+                ..default()
+            });
+        let east = spawn_agent(&mut app, Vec3::new(-40.0, 0.0, 2.0), default());
+        let west = spawn_agent(&mut app, Vec3::new(40.0, 0.0, 3.0), default());
+        order(&mut app, &[east], Vec3::new(98.0, 0.0, 2.0));
+        order(&mut app, &[west], Vec3::new(-98.0, 0.0, 2.0));
+
+        (0..120)
+            .map(|_| {
+                app.update();
+                position(&app, east)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stale_spatial_hash_finds_the_same_neighbors() {
+        let fresh = passing_path(1);
+        let stale = passing_path(10);
+        // a single neighbor to find, seeing it on the same frames keeps the path identical
+        let drift = fresh
+            .iter()
+            .zip(&stale)
+            .map(|(a, b)| a.distance(*b))
+            .fold(0.0, f32::max);
+        assert!(drift < 1e-3, "stale buckets drifted {drift} off the path");
+    }
+
+    // an agent between two flowfields, pulled north and south with the given weights
+    fn spawn_torn(app: &mut App, north: f32, south: f32) -> Entity {
+        let unit = spawn_agent(app, Vec3::new(2.0, 0.0, 2.0), default());
+        for (goal, weight) in [
+            (Vec3::new(2.0, 0.0, -38.0), north),
+            (Vec3::new(2.0, 0.0, 42.0), south),
+        ] {
+            // a new order would take the unit off its other flowfield, join it afterwards
+            let placeholder = app.world_mut().spawn_empty().id();
+            let ff = order(app, &[placeholder], goal);
+            let mut ff = app.world_mut().get_mut::<FlowField>(ff).unwrap();
+            ff.add_unit(unit);
+            ff.set_unit_weight(unit, weight);
+        }
+        unit
+    }
+
+    #[test]
+    fn opposing_flowfields_cancel_out() {
+        let mut app = app();
+        let unit = spawn_torn(&mut app, 1.0, 1.0);
+        run(&mut app, 30);
+        let velocity = app.world().get::<FvoAgent>(unit).unwrap().velocity;
+        // the sampled flow leans slightly east in both fields, only the pulls along the goals cancel
+        assert!(velocity.z.abs() < 1e-3, "{velocity}");
+    }
+
+    #[test]
+    fn heavier_flowfield_wins_the_blend() {
+        let mut app = app();
+        let unit = spawn_torn(&mut app, 3.0, 1.0);
+        run(&mut app, 60);
+        // heading north, at no more than the blended (3 * 50 - 50) / 4 = 25
+        let velocity = app.world().get::<FvoAgent>(unit).unwrap().velocity;
+        assert!(velocity.z < -10.0 && velocity.z > -25.0, "{velocity}");
+        assert!(velocity.x.abs() < 0.5, "{velocity}");
+    }
+
+    // velocity of an agent walking at another one, still far outside its sensor range
+    fn head_on_velocity(min_neighbors: usize) -> Vec3 {
+        let mut app = app();
+        let settings = FvoSettings {
+            min_neighbors,
+            ..default()
+        };
+        let unit = spawn_agent(&mut app, Vec3::new(-30.0, 0.0, 2.0), settings);
+        let other = spawn_agent(&mut app, Vec3::new(30.0, 0.0, 2.0), settings);
+        order(&mut app, &[unit], Vec3::new(98.0, 0.0, 2.0));
+        order(&mut app, &[other], Vec3::new(-98.0, 0.0, 2.0));
+        run(&mut app, 30);
+        app.world().get::<FvoAgent>(unit).unwrap().velocity
+    }
+
+    #[test]
+    fn min_neighbors_reaches_beyond_the_sensor_range() {
+        let blind = head_on_velocity(0);
+        let aware = head_on_velocity(1);
+        // about 50 units apart, the weakened constraint still nudges the solve
+        assert_ne!(blind, aware);
+    }
+
+    #[test]
+    fn debug_info_describes_the_solve() {
+        let mut app = app();
+        let unit = spawn_agent(&mut app, Vec3::new(-38.0, 0.0, 2.0), default());
+        app.world_mut()
+            .entity_mut(unit)
+            .insert(FvoDebugInfo::default());
+        order(&mut app, &[unit], Vec3::new(98.0, 0.0, 2.0));
+        // one ahead, two behind on either side, all within the sensor range without touching
+        for position in [
+            Vec3::new(-29.0, 0.0, 2.0),
+            Vec3::new(-42.0, 0.0, 10.0),
+            Vec3::new(-42.0, 0.0, -6.0),
+        ] {
+            spawn_agent(&mut app, position, default());
+        }
+        run(&mut app, 2);
+
+        let info = *app.world().get::<FvoDebugInfo>(unit).unwrap();
+        assert_eq!(info.neighbor_count, 3, "{info:?}");
+        assert_eq!(info.constraint_count, 3, "{info:?}");
+        // east at the preferred speed, give or take the sampled flow
+        assert!(
+            (info.preferred_velocity - Vec3::X * 50.0).length() < 1.0,
+            "{info:?}"
+        );
+        // held back by the agent ahead, within the limits
+        let solved = info.solved_velocity;
+        assert!(
+            solved.x < 50.0 && solved.length() <= FvoSettings::default().max_speed,
+            "{info:?}"
+        );
+        assert_eq!(app.world().get::<FvoAgent>(unit).unwrap().velocity, solved);
+    }
+
+    #[test]
+    fn speed_multiplier_scales_the_preferred_speed() {
+        let mut app = app();
+        // the whole map is mud
+        let mut grid = app.world_mut().resource_mut::<Grid>();
+        for y in 0..grid.size.y {
+            for x in 0..grid.size.x {
+                grid.set_speed_multiplier(IVec2::new(x, y), 0.5);
+            }
+        }
+        let unit = spawn_agent(&mut app, Vec3::new(-90.0, 0.0, 2.0), default());
+        order(&mut app, &[unit], Vec3::new(90.0, 0.0, 2.0));
+
+        // up to speed, still far from the arrival radius
+        run(&mut app, 300);
+        let start = position(&app, unit);
+        run(&mut app, 30);
+        let speed = position(&app, unit).distance(start) / (30.0 * DT);
+        let half = FvoSettings::default().preferred_speed * 0.5;
+        assert!(
+            (speed - half).abs() < 0.5,
+            "moved at {speed}, expected {half}"
+        );
+    }
+
+    // peak sideways speed of an agent dodging another one head on, both with `steering_smoothing`
+    fn head_on_swerve(steering_smoothing: f32) -> f32 {
+        let mut app = app();
+        let settings = FvoSettings {
+            steering_smoothing,
+            ..default()
+        };
+        // slightly off center so they know which way to pass
+        let unit = spawn_agent(&mut app, Vec3::new(-20.0, 0.0, 2.5), settings);
+        let other = spawn_agent(&mut app, Vec3::new(20.0, 0.0, 1.5), settings);
+        order(&mut app, &[unit], Vec3::new(98.0, 0.0, 2.0));
+        order(&mut app, &[other], Vec3::new(-98.0, 0.0, 2.0));
+
+        let mut peak: f32 = 0.0;
+        for _ in 0..120 {
+            app.update();
+            peak = peak.max(app.world().get::<FvoAgent>(unit).unwrap().velocity.z.abs());
+        }
+
+        // they got past each other either way
+        assert!(position(&app, unit).x > position(&app, other).x);
+        peak
+    }
+
+    #[test]
+    fn steering_smoothing_damps_the_swerve() {
+        let raw = head_on_swerve(0.0);
+        let smoothed = head_on_swerve(0.5);
+        assert!(raw > 1.0, "no swerve to damp: {raw}");
+        assert!(smoothed < raw * 0.75, "{smoothed} smoothed vs {raw} raw");
+    }
 }
@@ -1,35 +1,110 @@
-use bevy::prelude::*;
-use std::collections::HashMap;
+use bevy::{
+    camera::primitives::{Aabb, MeshAabb},
+    image::TextureAccessError,
+    mesh::{PrimitiveTopology, VertexAttributeValues},
+    prelude::*,
+};
+use std::collections::{HashMap, VecDeque};
 
-use crate::{cell::Cell, components::Obstacle, events::UpdateCostEv, utils};
+use crate::{
+    cell::Cell,
+    components::{Gate, MapBase, NavLayer, Obstacle, ObstacleAuto, TerrainTag, TraversalProfile},
+    events::{CostFieldChangedEv, RebuildGridEv, UpdateCostEv},
+    grid_direction::GridDirection,
+    plane::Plane,
+    utils,
+};
 
 pub struct GridPlugin;
 
 impl Plugin for GridPlugin {
     fn build(&self, app: &mut App) {
-        app.register_type::<Grid>().add_systems(
-            Update,
-            (update_costfield_on_add, update_costfield_on_remove),
-        );
+        app.register_type::<Grid>()
+            .register_type::<CostMapping>()
+            .register_type::<SlopeCost>()
+            .register_type::<NavLayer>()
+            .register_type::<TraversalProfile>()
+            .register_type::<Cell>()
+            .register_type::<GridTerrain>()
+            .register_type::<GridImage>()
+            .register_type::<MapBase>()
+            .register_type::<Obstacle>()
+            .register_type::<ObstacleAuto>()
+            .register_type::<Gate>()
+            .add_observer(rebuild_grid)
+            .add_observer(carve_obstacle)
+            .add_observer(clear_obstacle)
+            .add_systems(PreStartup, insert_placeholder_grid)
+            .add_systems(
+                Update,
+                (
+                    build_grid_from_image.run_if(resource_exists::<GridImage>),
+                    sample_map_base_terrain.run_if(resource_exists::<GridTerrain>),
+                    derive_obstacle_size,
+                    toggle_gates,
+                ),
+            )
+            .add_systems(
+                PreUpdate,
+                sync_grid_plane.run_if(resource_exists::<Grid>.and(resource_exists::<Plane>)),
+            );
+
+        #[cfg(feature = "tilemap")]
+        app.register_type::<crate::tilemap::TilemapCosts>()
+            .add_systems(
+                Update,
+                (
+                    crate::tilemap::build_grid_from_tilemap,
+                    crate::tilemap::sync_tilemap_costs,
+                )
+                    .chain(),
+            );
     }
 }
 
 /// The Grid struct represents a grid of cells used for pathfinding.
+///
+/// The cells are laid out centered on the origin of the grid's local XZ plane, turned onto the [`Plane`] agents move
+/// on and placed in the world by [`Grid::transform`], so maps don't have to be centered at the world origin or
+/// axis-aligned.
+///
+/// With the `serialize` feature the grid can be baked and saved with the map, the per-entity bookkeeping is left
+/// out and rebuilt as obstacles are spawned.
 #[derive(Resource, Reflect)]
 #[reflect(Resource)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct Grid {
     /// The number of buckets for spatial partitioning.
     pub buckets: f32,
-    /// The radius of each cell in the grid.
-    pub cell_radius: f32,
-    /// The diameter of each cell in the grid.
-    pub cell_diameter: f32,
+    /// The size of each cell in world units, `x` along the grid's local X and `y` along its local Z.
+    pub cell_size: Vec2,
+    /// Places the grid in the world, set it with [`Grid::set_transform`] so the cells follow. Keep the scale at one,
+    /// cells are sized by `cell_size`.
+    pub transform: Transform,
+    /// Plane the grid lies on, kept in sync with the [`Plane`] resource. Set it with [`Grid::set_plane`] so the cells
+    /// follow.
+    pub plane: Plane,
     /// 2D vector of cells representing the grid.
     pub grid: Vec<Vec<Cell>>,
     /// The size of the grid in terms of rows and columns. 'x' represents rows, 'y' represents columns.
     pub size: IVec2,
     /// DONT SET. A HashMap that stores the occupied cells for each entity.
+    #[cfg_attr(feature = "serialize", serde(skip))]
     pub occupied_cells: HashMap<u32, Vec<IVec2>>,
+    /// DONT SET. Cost every cell under an obstacle had before it was covered, and how many footprints cover it. The
+    /// cost comes back once the last of them is removed.
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    pub covered_cells: HashMap<IVec2, (u8, u32)>,
+    /// DONT SET. Extra cost of every cell from the agents crowding it, row-major. Empty unless
+    /// [`FlowFieldConfig::congestion`](crate::flowfield::FlowFieldConfig::congestion) is set.
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    pub congestion: Vec<u8>,
+    /// Row-major costs of the painted [`NavLayer`]s other than `Ground`, whose costs are the cells' own. Paint them
+    /// with [`Grid::set_layer_cost`].
+    pub layers: HashMap<NavLayer, Vec<u8>>,
+    /// Row-major [`TerrainTag`] of every cell, empty until painted with [`Grid::set_terrain`]. Weighed by the
+    /// [`TraversalProfile`] of the units.
+    pub terrain: Vec<TerrainTag>,
 }
 
 impl Grid {
@@ -48,28 +123,45 @@ impl Grid {
     ///
     /// * `buckets`: The number of buckets for spatial partitioning.
     /// * `size`: The size of the grid in terms of rows and columns.
-    /// * `cell_diameter`: The diameter of each cell in the grid.
+    /// * `cell_diameter`: The width and depth of each (square) cell in the grid.
     pub fn new(buckets: f32, size: IVec2, cell_diameter: f32) -> Self {
+        Self::with_cell_size(buckets, size, Vec2::splat(cell_diameter))
+    }
+
+    /// Creates a grid of rectangular cells, `cell_size.x` wide along world X and `cell_size.y` deep along world Z.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // a 40 x 20 grid of 2:1 cells, covering a square 160 x 160 world
+    /// let grid = Grid::with_cell_size(10.0, IVec2::new(40, 20), Vec2::new(4.0, 8.0));
+    /// ```
+    pub fn with_cell_size(buckets: f32, size: IVec2, cell_size: Vec2) -> Self {
         let mut grid = Grid {
             buckets,
-            cell_diameter,
-            cell_radius: cell_diameter / 2.0,
+            cell_size,
+            transform: Transform::IDENTITY,
+            plane: Plane::default(),
             grid: Vec::default(),
             size,
             occupied_cells: HashMap::default(),
+            covered_cells: HashMap::default(),
+            congestion: Vec::new(),
+            layers: HashMap::default(),
+            terrain: Vec::new(),
         };
 
         // Calculate offsets for top-left alignment
-        let offset_x = -(grid.size.x as f32 * grid.cell_diameter) / 2.;
-        let offset_y = -(grid.size.y as f32 * grid.cell_diameter) / 2.;
+        let offset_x = -(grid.size.x as f32 * grid.cell_size.x) / 2.;
+        let offset_y = -(grid.size.y as f32 * grid.cell_size.y) / 2.;
 
         // Initialize Grid
         grid.grid = (0..grid.size.y)
             .map(|y| {
                 (0..grid.size.x)
                     .map(|x| {
-                        let x_pos = grid.cell_diameter * x as f32 + grid.cell_radius + offset_x;
-                        let y_pos = grid.cell_diameter * y as f32 + grid.cell_radius + offset_y;
+                        let x_pos = grid.cell_size.x * (x as f32 + 0.5) + offset_x;
+                        let y_pos = grid.cell_size.y * (y as f32 + 0.5) + offset_y;
                         let world_pos = Vec3::new(x_pos, 0.0, y_pos);
                         Cell::new(world_pos, IVec2::new(x, y))
                     })
@@ -80,42 +172,657 @@ impl Grid {
         grid
     }
 
+    /// Places the grid with `transform`, e.g. for maps that aren't centered at the origin or are rotated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let grid = Grid::new(10.0, IVec2::new(50, 50), 4.0).with_transform(
+    ///     Transform::from_xyz(100.0, 0.0, 100.0).with_rotation(Quat::from_rotation_y(FRAC_PI_4)),
+    /// );
+    /// ```
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.set_transform(transform);
+        self
+    }
+
+    /// Moves the grid and the world position of every cell to `transform`, cell heights are kept. Trigger an
+    /// [`UpdateCostEv`] afterwards so the flowfields pick up the new cell positions.
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.place(transform, self.plane);
+    }
+
+    /// Lays the grid on `plane`, see [`Grid::set_plane`].
+    pub fn with_plane(mut self, plane: Plane) -> Self {
+        self.set_plane(plane);
+        self
+    }
+
+    /// Turns the grid and the world position of every cell onto `plane`, cell heights are kept. Trigger an
+    /// [`UpdateCostEv`] afterwards so the flowfields pick up the new cell positions.
+    pub fn set_plane(&mut self, plane: Plane) {
+        self.place(self.transform, plane);
+    }
+
+    fn place(&mut self, transform: Transform, plane: Plane) {
+        let locals: Vec<Vec3> = self
+            .grid
+            .iter()
+            .flatten()
+            .map(|cell| self.world_to_local(cell.world_pos))
+            .collect();
+
+        self.transform = transform;
+        self.plane = plane;
+        for (cell, local) in self.grid.iter_mut().flatten().zip(locals) {
+            cell.world_pos = transform.transform_point(plane.from_plane(local));
+        }
+    }
+
+    /// Converts a world position into the grid's local space, where the cells are centered on the origin.
+    pub fn world_to_local(&self, world_pos: Vec3) -> Vec3 {
+        self.plane.to_plane(
+            self.transform.rotation.inverse() * (world_pos - self.transform.translation)
+                / self.transform.scale,
+        )
+    }
+
+    /// Converts a position in the grid's local space into world space.
+    pub fn local_to_world(&self, local_pos: Vec3) -> Vec3 {
+        self.transform
+            .transform_point(self.plane.from_plane(local_pos))
+    }
+
+    /// Rotation from the grid's local space into world space.
+    pub fn rotation(&self) -> Quat {
+        self.transform.rotation * self.plane.rotation()
+    }
+
+    /// Rotates a direction on the grid's local XZ plane, e.g. a cell's `best_direction`, into the XZ of plane space,
+    /// see [`Plane::to_plane`].
+    pub fn direction_to_world(&self, direction: Vec2) -> Vec2 {
+        self.plane
+            .to_2d(self.rotation() * Vec3::new(direction.x, 0.0, direction.y))
+    }
+
     pub fn get_cell_from_world_position(&self, world_pos: Vec3) -> Cell {
+        let world_pos = self.world_to_local(world_pos);
+
         // Calculate the offset for the grid's top-left corner
-        let adjusted_x = world_pos.x - (-self.size.x as f32 * self.cell_diameter / 2.0);
-        let adjusted_y = world_pos.z - (-self.size.y as f32 * self.cell_diameter / 2.0);
+        let adjusted_x = world_pos.x - (-self.size.x as f32 * self.cell_size.x / 2.0);
+        let adjusted_y = world_pos.z - (-self.size.y as f32 * self.cell_size.y / 2.0);
 
         // Calculate percentages within the grid
-        let percent_x = adjusted_x / (self.size.x as f32 * self.cell_diameter);
-        let percent_y = adjusted_y / (self.size.y as f32 * self.cell_diameter);
+        let percent_x = adjusted_x / (self.size.x as f32 * self.cell_size.x);
+        let percent_y = adjusted_y / (self.size.y as f32 * self.cell_size.y);
 
         let offset = Some(Vec2::new(percent_x, percent_y));
 
         utils::get_cell_from_world_position_helper(
             world_pos,
             self.size,
-            self.cell_diameter,
+            self.cell_size,
             &self.grid,
             offset,
         )
     }
 
+    /// Replaces every cell with a fresh grid of `size` cells of `cell_size` each and stamps `obstacles` into it.
+    /// Costs and terrain painted on the old grid, heights and congestion are dropped, `buckets` is kept. Trigger an
+    /// [`UpdateCostEv`] afterwards to rebuild the flowfields, or use [`RebuildGridEv`] which does both.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// fn regenerate(mut cmds: Commands, mut grid: ResMut<Grid>, q_obstacles: Query<(Entity, &Transform, &Obstacle)>) {
+    ///     grid.rebuild(IVec2::new(128, 64), Vec2::splat(8.0), q_obstacles.iter());
+    ///     cmds.trigger(UpdateCostEv);
+    /// }
+    /// ```
+    pub fn rebuild<'a>(
+        &mut self,
+        size: IVec2,
+        cell_size: Vec2,
+        obstacles: impl IntoIterator<Item = (Entity, &'a Transform, &'a Obstacle)>,
+    ) {
+        *self = Grid::with_cell_size(self.buckets, size, cell_size)
+            .with_plane(self.plane)
+            .with_transform(self.transform);
+        for (ent, transform, obstacle) in obstacles {
+            self.update_cell_costs(ent.index(), transform, obstacle);
+        }
+    }
+
+    /// Creates a grid with one cell per pixel of `image`, the cost of each cell painted as its brightness, see
+    /// [`CostMapping`]. Pixel rows run along the grid's `y` (world Z). Uses 10 spatial buckets, set `buckets`
+    /// afterwards if the map needs more. Fails for compressed or otherwise unreadable texture formats.
+    ///
+    /// Insert a [`GridImage`] to build the grid as soon as the image asset is loaded.
+    pub fn from_image(
+        image: &Image,
+        cell_size: Vec2,
+        mapping: CostMapping,
+    ) -> Result<Self, TextureAccessError> {
+        let size = IVec2::new(image.width() as i32, image.height() as i32);
+        let mut grid = Grid::with_cell_size(10.0, size, cell_size);
+
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let color = image.get_color_at(x as u32, y as u32)?.to_srgba();
+                let brightness = (color.red + color.green + color.blue) / 3.0;
+                grid.grid[y as usize][x as usize].cost = mapping.cost(brightness);
+            }
+        }
+
+        Ok(grid)
+    }
+
+    /// Sets the height of every cell from a grayscale heightmap stretched over the whole grid, black at `0.0` and
+    /// white at `height_scale`, then adds the slope cost. Fails for unreadable texture formats.
+    pub fn apply_heightmap(
+        &mut self,
+        heightmap: &Image,
+        height_scale: f32,
+        slope: SlopeCost,
+    ) -> Result<(), TextureAccessError> {
+        let (width, height) = (heightmap.width(), heightmap.height());
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                let px = (((x as f32 + 0.5) / self.size.x as f32) * width as f32) as u32;
+                let py = (((y as f32 + 0.5) / self.size.y as f32) * height as f32) as u32;
+                let color = heightmap
+                    .get_color_at(px.min(width - 1), py.min(height - 1))?
+                    .to_srgba();
+                let brightness = (color.red + color.green + color.blue) / 3.0;
+                self.set_cell_height(IVec2::new(x, y), brightness * height_scale);
+            }
+        }
+
+        self.apply_slope_costs(slope);
+        Ok(())
+    }
+
+    /// Sets the height of every cell to the highest point of `mesh` (placed by `transform`) above its center, then
+    /// adds the slope cost. Cells the mesh does not cover keep their height. Only triangle lists are read.
+    pub fn apply_terrain_mesh(&mut self, mesh: &Mesh, transform: &Transform, slope: SlopeCost) {
+        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+            return;
+        }
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            return;
+        };
+
+        let positions: Vec<Vec3> = positions
+            .iter()
+            .map(|p| self.world_to_local(transform.transform_point(Vec3::from_array(*p))))
+            .collect();
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(indices) => indices.iter().collect(),
+            None => (0..positions.len()).collect(),
+        };
+
+        let origin = self.cell_origin();
+        let mut heights = vec![f32::NEG_INFINITY; (self.size.x * self.size.y) as usize];
+        for tri in indices.chunks_exact(3) {
+            let [a, b, c] = [positions[tri[0]], positions[tri[1]], positions[tri[2]]];
+
+            // cells whose center lies in the triangle's footprint on the XZ plane
+            let min = (a.min(b).min(c).xz() - origin) / self.cell_size;
+            let max = (a.max(b).max(c).xz() - origin) / self.cell_size;
+            let min = min.ceil().as_ivec2().max(IVec2::ZERO);
+            let max = max.floor().as_ivec2().min(self.size - IVec2::ONE);
+
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let center = origin + IVec2::new(x, y).as_vec2() * self.cell_size;
+                    let Some(h) = triangle_height(center, a, b, c) else {
+                        continue;
+                    };
+
+                    let i = (y * self.size.x + x) as usize;
+                    heights[i] = heights[i].max(h);
+                }
+            }
+        }
+
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                let h = heights[(y * self.size.x + x) as usize];
+                if h.is_finite() {
+                    self.set_cell_height(IVec2::new(x, y), h);
+                }
+            }
+        }
+
+        self.apply_slope_costs(slope);
+    }
+
+    /// Adds the cost of the terrain slope to every cell, the steepest rise to one of its four neighbors. Cells steeper
+    /// than [`SlopeCost::max_slope`] become impassable. Apply it once per height change, the cost accumulates.
+    pub fn apply_slope_costs(&mut self, slope: SlopeCost) {
+        let mut costs = Vec::with_capacity((self.size.x * self.size.y) as usize);
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                let idx = IVec2::new(x, y);
+                let h = self.cell_height(idx);
+                let steepest = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y]
+                    .into_iter()
+                    .filter(|dir| {
+                        let n = idx + *dir;
+                        n.cmpge(IVec2::ZERO).all() && n.cmplt(self.size).all()
+                    })
+                    .map(|dir| {
+                        let n = idx + dir;
+                        let rise = (self.cell_height(n) - h).abs();
+                        let run = if dir.x != 0 {
+                            self.cell_size.x
+                        } else {
+                            self.cell_size.y
+                        };
+                        (rise / run).atan()
+                    })
+                    .fold(0.0, f32::max);
+                costs.push(slope.cost(steepest));
+            }
+        }
+
+        for cell in self.grid.iter_mut().flatten() {
+            let slope_cost = costs[(cell.idx.y * self.size.x + cell.idx.x) as usize];
+            if slope_cost == u8::MAX {
+                cell.cost = u8::MAX;
+            } else if cell.cost != u8::MAX {
+                cell.cost = cell.cost.saturating_add(slope_cost - 1).min(u8::MAX - 1);
+            }
+        }
+    }
+
+    /// Returns the terrain height at `world_pos` along [`Plane::up`], interpolated between the surrounding cell
+    /// centers. Use it to place agents on the surface.
+    pub fn height_at(&self, world_pos: Vec3) -> f32 {
+        let local = self.world_to_local(world_pos);
+        let max = (self.size - IVec2::ONE).as_vec2();
+        let f = ((local.xz() - self.cell_origin()) / self.cell_size).clamp(Vec2::ZERO, max);
+        let i0 = f.floor().as_ivec2();
+        let i1 = (i0 + IVec2::ONE).min(self.size - IVec2::ONE);
+        let t = f - i0.as_vec2();
+
+        let h = |x: i32, y: i32| self.cell_height(IVec2::new(x, y));
+        let top = h(i0.x, i0.y).lerp(h(i1.x, i0.y), t.x);
+        let bottom = h(i0.x, i1.y).lerp(h(i1.x, i1.y), t.x);
+        self.plane
+            .to_plane(self.local_to_world(local.with_y(top.lerp(bottom, t.y))))
+            .y
+    }
+
+    /// Whether any cell has a terrain height, i.e. a heightmap or terrain mesh was applied. Scans every cell.
+    pub fn has_heights(&self) -> bool {
+        self.grid
+            .iter()
+            .flatten()
+            .any(|cell| self.world_to_local(cell.world_pos).y.abs() > f32::EPSILON)
+    }
+
+    // height of the cell above the grid's local XZ plane
+    fn cell_height(&self, idx: IVec2) -> f32 {
+        self.world_to_local(self.grid[idx.y as usize][idx.x as usize].world_pos)
+            .y
+    }
+
+    fn set_cell_height(&mut self, idx: IVec2, height: f32) {
+        let local = self.world_to_local(self.grid[idx.y as usize][idx.x as usize].world_pos);
+        self.grid[idx.y as usize][idx.x as usize].world_pos =
+            self.local_to_world(local.with_y(height));
+    }
+
+    // local XZ position of the center of cell (0, 0)
+    fn cell_origin(&self) -> Vec2 {
+        (Vec2::ONE - self.size.as_vec2()) * self.cell_size / 2.0
+    }
+
+    /// Returns the extent of the whole grid in world units, `x` along its local X and `y` along its local Z.
+    pub fn world_size(&self) -> Vec2 {
+        self.size.as_vec2() * self.cell_size
+    }
+
+    /// Returns `true` if `world_pos` lies over the grid, its height is not checked.
+    pub fn contains(&self, world_pos: Vec3) -> bool {
+        let half = self.world_size() / 2.0;
+        self.world_to_local(world_pos).xz().abs().cmple(half).all()
+    }
+
+    /// Returns the closest point to `world_pos` over the grid, the height along [`Plane::up`] is kept.
+    pub fn clamp_position(&self, world_pos: Vec3) -> Vec3 {
+        let half = self.world_size() / 2.0;
+        let local = self.world_to_local(world_pos);
+        let xz = local.xz().clamp(-half, half);
+        let clamped = self.local_to_world(Vec3::new(xz.x, local.y, xz.y));
+        let up = self.plane.up();
+        clamped + up * (world_pos - clamped).dot(up)
+    }
+
+    /// Returns the cost of the cell at `idx` on `layer`, see [`NavLayer`] for the cost of unpainted layers.
+    pub fn layer_cost(&self, layer: NavLayer, idx: IVec2) -> u8 {
+        let i = (idx.y * self.size.x + idx.x) as usize;
+        let painted = |layer: NavLayer, unpainted: u8| {
+            self.layers
+                .get(&layer)
+                .and_then(|costs| costs.get(i))
+                .copied()
+                .unwrap_or(unpainted)
+        };
+
+        match layer {
+            NavLayer::Ground => self.grid[idx.y as usize][idx.x as usize].cost,
+            NavLayer::Water => painted(NavLayer::Water, u8::MAX),
+            NavLayer::Air => painted(NavLayer::Air, 1),
+            NavLayer::Amphibious => self
+                .layer_cost(NavLayer::Ground, idx)
+                .min(self.layer_cost(NavLayer::Water, idx)),
+        }
+    }
+
+    /// Sets the cost of the cell at `idx` on `layer`, `Ground` is the same as [`Grid::set_cost`] and `Amphibious`
+    /// can't be painted. Trigger a [`CostFieldChangedEv`] afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // a river running down column 12
+    /// for y in 0..grid.size.y {
+    ///     grid.set_layer_cost(NavLayer::Water, IVec2::new(12, y), 1);
+    ///     grid.set_cost(IVec2::new(12, y), u8::MAX);
+    /// }
+    /// ```
+    ///
+    /// [`CostFieldChangedEv`]: crate::events::CostFieldChangedEv
+    pub fn set_layer_cost(&mut self, layer: NavLayer, idx: IVec2, cost: u8) {
+        if idx.x < 0 || idx.y < 0 || idx.x >= self.size.x || idx.y >= self.size.y {
+            return;
+        }
+
+        let unpainted = match layer {
+            NavLayer::Ground => return self.set_cost(idx, cost),
+            NavLayer::Amphibious => return,
+            NavLayer::Water => u8::MAX,
+            NavLayer::Air => 1,
+        };
+
+        let cell_count = (self.size.x * self.size.y) as usize;
+        let costs = self
+            .layers
+            .entry(layer)
+            .or_insert_with(|| vec![unpainted; cell_count]);
+        costs[(idx.y * self.size.x + idx.x) as usize] = cost;
+    }
+
+    /// Returns the [`TerrainTag`] of the cell at `idx`, `0` until painted.
+    pub fn terrain(&self, idx: IVec2) -> TerrainTag {
+        self.terrain
+            .get((idx.y * self.size.x + idx.x) as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Tags the cell at `idx` with the terrain `tag`, see [`TraversalProfile`]. Trigger a [`CostFieldChangedEv`]
+    /// afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // a road along row 20, cheap for wheeled units
+    /// for x in 0..grid.size.x {
+    ///     grid.set_terrain(IVec2::new(x, 20), ROAD);
+    /// }
+    /// ```
+    ///
+    /// [`CostFieldChangedEv`]: crate::events::CostFieldChangedEv
+    pub fn set_terrain(&mut self, idx: IVec2, tag: TerrainTag) {
+        if idx.x < 0 || idx.y < 0 || idx.x >= self.size.x || idx.y >= self.size.y {
+            return;
+        }
+
+        let cell_count = (self.size.x * self.size.y) as usize;
+        if self.terrain.len() != cell_count {
+            self.terrain = vec![0; cell_count];
+        }
+        self.terrain[(idx.y * self.size.x + idx.x) as usize] = tag;
+    }
+
+    /// Returns the cost flowfields on `layer` integrate over at `idx`, the layer cost plus the congestion of the cell.
+    /// Walls stay impassable and congestion alone never makes a cell impassable.
+    pub fn integration_cost(&self, idx: IVec2, layer: NavLayer) -> u8 {
+        self.integration_cost_for(idx, layer, None)
+    }
+
+    /// Like [`Grid::integration_cost`], with the layer cost weighed by `profile` first.
+    pub fn integration_cost_for(
+        &self,
+        idx: IVec2,
+        layer: NavLayer,
+        profile: Option<&TraversalProfile>,
+    ) -> u8 {
+        let cost = self.layer_cost(layer, idx);
+        let cost = match profile {
+            Some(profile) => profile.cost(self.terrain(idx), cost),
+            None => cost,
+        };
+        if cost == u8::MAX {
+            return cost;
+        }
+
+        let congestion = self
+            .congestion
+            .get((idx.y * self.size.x + idx.x) as usize)
+            .copied()
+            .unwrap_or(0);
+        cost.saturating_add(congestion).min(u8::MAX - 1)
+    }
+
+    /// Returns a copy of the cells with [`Grid::integration_cost`] on `layer` as their cost, what flowfields are
+    /// built from.
+    pub fn integration_cells(&self, layer: NavLayer) -> Vec<Vec<Cell>> {
+        self.integration_cells_for(layer, 0, None)
+    }
+
+    /// Like [`Grid::integration_cells`], but cells with less than `min_clearance` (see [`Grid::clearance`]) are
+    /// impassable as well, so the flowfields of big units don't lead through gaps they can't fit through.
+    pub fn integration_cells_with_clearance(
+        &self,
+        layer: NavLayer,
+        min_clearance: u16,
+    ) -> Vec<Vec<Cell>> {
+        self.integration_cells_for(layer, min_clearance, None)
+    }
+
+    /// Like [`Grid::integration_cells_with_clearance`], with every cost weighed by `profile` as well, what the
+    /// flowfields of units with a [`TraversalProfile`] are built from.
+    pub fn integration_cells_for(
+        &self,
+        layer: NavLayer,
+        min_clearance: u16,
+        profile: Option<&TraversalProfile>,
+    ) -> Vec<Vec<Cell>> {
+        let mut cells = self.grid.clone();
+        if !self.congestion.is_empty() || layer != NavLayer::Ground || profile.is_some() {
+            for cell in cells.iter_mut().flatten() {
+                cell.cost = self.integration_cost_for(cell.idx, layer, profile);
+            }
+        }
+        if min_clearance <= 1 {
+            return cells;
+        }
+
+        let clearance = self.clearance(layer);
+        for cell in cells.iter_mut().flatten() {
+            if clearance[(cell.idx.y * self.size.x + cell.idx.x) as usize] < min_clearance {
+                cell.cost = u8::MAX;
+            }
+        }
+        cells
+    }
+
+    /// Returns the clearance of every cell on `layer`, row-major: the distance in cells (diagonal steps count as
+    /// one) to the closest impassable cell, computed by a brushfire from the walls. Impassable cells are `0`, and the
+    /// map edge counts as a wall, so open cells on the border are `1`.
+    pub fn clearance(&self, layer: NavLayer) -> Vec<u16> {
+        let (w, h) = (self.size.x, self.size.y);
+        let mut clearance = vec![u16::MAX; (w * h).max(0) as usize];
+        let mut queue: VecDeque<IVec2> = VecDeque::new();
+
+        // walls first, then the border, so the queue stays sorted by clearance
+        for y in 0..h {
+            for x in 0..w {
+                let idx = IVec2::new(x, y);
+                if self.layer_cost(layer, idx) == u8::MAX {
+                    clearance[(y * w + x) as usize] = 0;
+                    queue.push_back(idx);
+                }
+            }
+        }
+        for y in 0..h {
+            for x in 0..w {
+                let i = (y * w + x) as usize;
+                let border = x == 0 || y == 0 || x == w - 1 || y == h - 1;
+                if border && clearance[i] == u16::MAX {
+                    clearance[i] = 1;
+                    queue.push_back(IVec2::new(x, y));
+                }
+            }
+        }
+
+        while let Some(idx) = queue.pop_front() {
+            let next = clearance[(idx.y * w + idx.x) as usize] + 1;
+            for direction in GridDirection::cardinal_and_intercardinal_directions() {
+                let n = idx + direction.vector();
+                if n.x < 0 || n.y < 0 || n.x >= w || n.y >= h {
+                    continue;
+                }
+
+                let i = (n.y * w + n.x) as usize;
+                if next < clearance[i] {
+                    clearance[i] = next;
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        clearance
+    }
+
+    /// Returns the clearance an agent of `radius` needs to stand in the middle of a cell without overlapping a wall,
+    /// see [`Grid::clearance`]. `1`, i.e. any open cell, for agents no wider than a cell.
+    pub fn clearance_for_radius(&self, radius: f32) -> u16 {
+        let cell = self.cell_size.min_element().max(f32::EPSILON);
+        (radius.max(0.0) / cell + 0.5).ceil().max(1.0) as u16
+    }
+
+    /// Sets the speed multiplier of the cell at `idx`. Flowfields created afterwards pick it up.
+    pub fn set_speed_multiplier(&mut self, idx: IVec2, multiplier: f32) {
+        if idx.x < 0 || idx.y < 0 || idx.x >= self.size.x || idx.y >= self.size.y {
+            return;
+        }
+
+        self.grid[idx.y as usize][idx.x as usize].speed_multiplier = multiplier.max(0.0);
+    }
+
+    /// Sets the cost of the cell at `idx`, `u8::MAX` makes it impassable. Trigger a [`CostFieldChangedEv`] afterwards
+    /// so flowfields route around it.
+    ///
+    /// [`CostFieldChangedEv`]: crate::events::CostFieldChangedEv
+    pub fn set_cost(&mut self, idx: IVec2, cost: u8) {
+        if idx.x < 0 || idx.y < 0 || idx.x >= self.size.x || idx.y >= self.size.y {
+            return;
+        }
+
+        self.grid[idx.y as usize][idx.x as usize].cost = cost;
+    }
+
+    /// Sets the extra cost of stepping out of the cell at `idx` towards the cardinal `direction`, on top of the cost
+    /// of the cell stepped into. `u8::MAX` forbids the step, `0` clears it. Use it for down-only cliffs or slow
+    /// uphill ramps, and trigger a [`CostFieldChangedEv`] afterwards. Intercardinal directions are ignored, diagonal
+    /// steps pay the larger of their two cardinal exits.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // climbing the ramp north costs extra, going down is free
+    /// grid.set_exit_cost(IVec2::new(8, 12), GridDirection::North, 20);
+    /// ```
+    ///
+    /// [`CostFieldChangedEv`]: crate::events::CostFieldChangedEv
+    pub fn set_exit_cost(&mut self, idx: IVec2, direction: GridDirection, cost: u8) {
+        if idx.x < 0 || idx.y < 0 || idx.x >= self.size.x || idx.y >= self.size.y {
+            return;
+        }
+
+        let side = match direction {
+            GridDirection::North => 0,
+            GridDirection::East => 1,
+            GridDirection::South => 2,
+            GridDirection::West => 3,
+            _ => return,
+        };
+        self.grid[idx.y as usize][idx.x as usize].exit_costs[side] = cost;
+    }
+
+    /// Makes the cell at `idx` one-way, like a conveyor belt or a gate that only opens one way: units can cross it
+    /// towards the cardinal `direction` but neither enter it from that side nor leave it backwards. Trigger a
+    /// [`CostFieldChangedEv`] afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // a one-way bridge heading east over row 5
+    /// for x in 10..16 {
+    ///     grid.set_one_way(IVec2::new(x, 5), GridDirection::East);
+    /// }
+    /// ```
+    ///
+    /// [`CostFieldChangedEv`]: crate::events::CostFieldChangedEv
+    pub fn set_one_way(&mut self, idx: IVec2, direction: GridDirection) {
+        let back = direction.opposite();
+        self.set_exit_cost(idx, back, u8::MAX);
+        self.set_exit_cost(idx + direction.vector(), back, u8::MAX);
+    }
+
+    /// Adds `cost` to every cell inside `rect` (inclusive cell indices), saturating at impassable. Use it for
+    /// burning tiles or danger zones, and trigger a [`CostFieldChangedEv`] afterwards.
+    ///
+    /// [`CostFieldChangedEv`]: crate::events::CostFieldChangedEv
+    pub fn add_cost_region(&mut self, rect: IRect, cost: u8) {
+        let min = rect.min.max(IVec2::ZERO);
+        let max = rect.max.min(self.size - IVec2::ONE);
+
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                self.grid[y as usize][x as usize].increase_cost(cost);
+            }
+        }
+    }
+
+    /// Marks the cells covered by an obstacle as impassable. Returns the covered cell indices, `None` if the
+    /// obstacle lies outside the grid.
     pub fn update_cell_costs(
         &mut self,
         entity_id: u32,
         obj_transform: &Transform,
         obj_size: &Obstacle,
-    ) {
-        let cell_size = self.cell_diameter;
-        let grid_offset_x = -self.size.x as f32 * cell_size / 2.0;
-        let grid_offset_y = -self.size.y as f32 * cell_size / 2.0;
+    ) -> Option<IRect> {
+        let cell_size = self.cell_size;
+        let grid_offset_x = -self.size.x as f32 * cell_size.x / 2.0;
+        let grid_offset_y = -self.size.y as f32 * cell_size.y / 2.0;
 
-        let obj_pos = obj_transform.translation;
+        // footprints are stamped in the grid's local space
+        let obj_pos = self.world_to_local(obj_transform.translation);
         let obj_pos = Vec2::new(obj_pos.x, obj_pos.z);
         let half_extent = obj_size.0 / 2.0;
 
         // Obtain the rotation matrix from the object's rotation.
-        let rotation = Mat3::from_quat(obj_transform.rotation);
+        let rotation = Mat3::from_quat(
+            self.rotation().inverse() * obj_transform.rotation * self.plane.rotation(),
+        );
 
         // Compute the absolute value of each column of the rotation matrix.
         // This effectively gives the scaling of the half extents in world space.
@@ -129,68 +836,431 @@ impl Grid {
         let aabb_max = obj_pos + world_half_extent;
 
         // Calculate grid cell indices based on the object's AABB in the xz-plane.
-        let min_x = ((aabb_min.x - grid_offset_x) / cell_size).floor() as isize;
-        let max_x = ((aabb_max.x - grid_offset_x) / cell_size).floor() as isize;
-        let min_y = ((aabb_min.y - grid_offset_y) / cell_size).floor() as isize;
-        let max_y = ((aabb_max.y - grid_offset_y) / cell_size).floor() as isize;
+        let min_x = ((aabb_min.x - grid_offset_x) / cell_size.x).floor() as isize;
+        let max_x = ((aabb_max.x - grid_offset_x) / cell_size.x).floor() as isize;
+        let min_y = ((aabb_min.y - grid_offset_y) / cell_size.y).floor() as isize;
+        let max_y = ((aabb_max.y - grid_offset_y) / cell_size.y).floor() as isize;
 
         let mut occupied_cells = Vec::new();
         for y in min_y..=max_y {
             for x in min_x..=max_x {
                 if x >= 0 && x < self.size.x as isize && y >= 0 && y < self.size.y as isize {
-                    occupied_cells.push(IVec2::new(x as i32, y as i32));
-                    self.grid[y as usize][x as usize].cost = 255;
+                    let idx = IVec2::new(x as i32, y as i32);
+                    let cell = &mut self.grid[y as usize][x as usize];
+                    self.covered_cells.entry(idx).or_insert((cell.cost, 0)).1 += 1;
+                    cell.cost = 255;
+                    occupied_cells.push(idx);
                 }
             }
         }
 
+        let region = cells_region(&occupied_cells);
         self.occupied_cells
             .entry(entity_id)
             .and_modify(|cells| cells.extend(occupied_cells.iter().cloned()))
             .or_insert(occupied_cells);
+
+        region
     }
 
-    // TODO: Will eventually need rework. This is setting the cell cost back to 1. What if the cost was originally
-    // something different like rough terrain?
-    pub fn reset_cell_costs(&mut self, entities: Vec<Entity>) {
+    /// Frees the cells of the given obstacles, restoring the cost they had before they were covered. Cells still under
+    /// another obstacle stay impassable. Returns the freed cell indices, `None` if there were none.
+    pub fn reset_cell_costs(&mut self, entities: Vec<Entity>) -> Option<IRect> {
+        let mut region: Option<IRect> = None;
         for ent in entities.iter() {
             if let Some(occupied_cells) = self.occupied_cells.remove(&ent.index()) {
                 for cell in occupied_cells.iter() {
-                    self.grid[cell.y as usize][cell.x as usize].cost = 1;
+                    let Some((base, count)) = self.covered_cells.get_mut(cell) else {
+                        continue;
+                    };
+
+                    *count -= 1;
+                    if *count == 0 {
+                        self.grid[cell.y as usize][cell.x as usize].cost = *base;
+                        self.covered_cells.remove(cell);
+                    }
                 }
+                region = union_region(region, cells_region(&occupied_cells));
             }
         }
+
+        region
+    }
+}
+
+/// How [`Grid::from_image`] turns the brightness of a pixel into a cell cost. Black is impassable, white is free and
+/// the shades in between cost more the darker they are.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct CostMapping {
+    /// Pixels at or below this brightness (`0.0..=1.0`) are impassable.
+    pub wall_threshold: f32,
+    /// Cost of the darkest passable shade, white always costs `1`.
+    pub max_cost: u8,
+}
+
+impl Default for CostMapping {
+    fn default() -> Self {
+        Self {
+            wall_threshold: 0.05,
+            max_cost: 254,
+        }
+    }
+}
+
+impl CostMapping {
+    /// Returns the cell cost for a pixel `brightness` in `0.0..=1.0`.
+    pub fn cost(&self, brightness: f32) -> u8 {
+        if brightness <= self.wall_threshold {
+            return u8::MAX;
+        }
+
+        let darkness = (1.0 - brightness) / (1.0 - self.wall_threshold).max(f32::EPSILON);
+        let max_cost = self.max_cost.clamp(1, u8::MAX - 1) as f32;
+        (1.0 + darkness.clamp(0.0, 1.0) * (max_cost - 1.0)).round() as u8
+    }
+}
+
+/// How [`Grid::apply_slope_costs`] turns the steepness of the terrain into cell cost. Gentle slopes cost a little
+/// more than flat ground, cliffs are impassable.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct SlopeCost {
+    /// Steepest walkable slope in radians.
+    pub max_slope: f32,
+    /// Cost of a slope just below `max_slope`, flat ground costs `1`.
+    pub max_cost: u8,
+}
+
+impl Default for SlopeCost {
+    fn default() -> Self {
+        Self {
+            max_slope: 35f32.to_radians(),
+            max_cost: 20,
+        }
+    }
+}
+
+impl SlopeCost {
+    /// Returns the cell cost for a slope of `angle` radians.
+    pub fn cost(&self, angle: f32) -> u8 {
+        if angle > self.max_slope {
+            return u8::MAX;
+        }
+
+        let max_cost = self.max_cost.clamp(1, u8::MAX - 1) as f32;
+        (1.0 + (angle / self.max_slope.max(f32::EPSILON)) * (max_cost - 1.0)).round() as u8
     }
 }
 
-// detects if a new static object has been added and updates the costfield
-fn update_costfield_on_add(
+/// Samples the cell heights from the [`MapBase`] mesh and derives slope costs from them (see
+/// [`Grid::apply_terrain_mesh`]) once the mesh is loaded, and again whenever the grid is rebuilt.
+#[derive(Resource, Reflect, Debug, Clone, Copy, Default)]
+#[reflect(Resource)]
+pub struct GridTerrain {
+    pub slope: SlopeCost,
+}
+
+// keeps the cell heights in sync with the map mesh while `GridTerrain` is present
+fn sample_map_base_terrain(
     mut cmds: Commands,
+    terrain: Res<GridTerrain>,
+    meshes: Res<Assets<Mesh>>,
     mut grid: ResMut<Grid>,
-    q_objects: Query<(Entity, &Transform, &Obstacle), Added<Obstacle>>,
+    q_map: Query<(&Mesh3d, &Transform), With<MapBase>>,
+    mut sampled: Local<Option<(AssetId<Mesh>, usize)>>,
 ) {
-    let objects = q_objects.iter().collect::<Vec<_>>();
-    if objects.is_empty() {
+    let Ok((mesh_handle, tf)) = q_map.single() else {
+        return;
+    };
+
+    // the grid is rebuilt from scratch when its size changes, e.g. by a `GridImage`
+    let cells = (grid.size.x * grid.size.y) as usize;
+    let current = (mesh_handle.id(), cells);
+    if *sampled == Some(current) {
         return;
     }
 
-    for (ent, transform, size) in objects.iter() {
-        grid.update_cell_costs(ent.index(), transform, size);
+    let Some(mesh) = meshes.get(mesh_handle) else {
+        return;
+    };
+
+    grid.apply_terrain_mesh(mesh, tf, terrain.slope);
+    *sampled = Some(current);
+    cmds.trigger(UpdateCostEv);
+}
+
+/// Builds the [`Grid`] from a painted cost image (see [`Grid::from_image`]) once the asset is loaded, and again
+/// whenever it is hot-reloaded. Insert it instead of a `Grid`, a 1x1 placeholder grid stands in until the image is
+/// there. Obstacles already stamped into the grid are kept.
+///
+/// # Example
+///
+/// ```
+/// fn setup(mut cmds: Commands, assets: Res<AssetServer>) {
+///     cmds.insert_resource(GridImage::new(assets.load("maps/level1_cost.png"), Vec2::splat(10.0)));
+/// }
+/// ```
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct GridImage {
+    pub image: Handle<Image>,
+    /// Size of the cell each pixel becomes, see [`Grid::cell_size`].
+    pub cell_size: Vec2,
+    pub mapping: CostMapping,
+    /// Spatial buckets of the built grid, see [`Grid::buckets`].
+    pub buckets: f32,
+}
+
+impl GridImage {
+    pub fn new(image: Handle<Image>, cell_size: Vec2) -> Self {
+        Self {
+            image,
+            cell_size,
+            mapping: CostMapping::default(),
+            buckets: 10.0,
+        }
+    }
+
+    pub fn with_mapping(mut self, mapping: CostMapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+
+    pub fn with_buckets(mut self, buckets: f32) -> Self {
+        self.buckets = buckets;
+        self
+    }
+}
+
+// systems read the grid from the first frame on, give them something until the cost image is loaded
+fn insert_placeholder_grid(
+    mut cmds: Commands,
+    grid_image: Option<Res<GridImage>>,
+    grid: Option<Res<Grid>>,
+) {
+    if let (Some(grid_image), None) = (grid_image, grid) {
+        cmds.insert_resource(Grid::with_cell_size(
+            grid_image.buckets,
+            IVec2::ONE,
+            grid_image.cell_size,
+        ));
+    }
+}
+
+fn build_grid_from_image(
+    mut cmds: Commands,
+    grid_image: Res<GridImage>,
+    images: Res<Assets<Image>>,
+    mut asset_events: MessageReader<AssetEvent<Image>>,
+    grid: Option<ResMut<Grid>>,
+) {
+    let id = grid_image.image.id();
+    let loaded = asset_events.read().any(|ev| match ev {
+        AssetEvent::LoadedWithDependencies { id: loaded } | AssetEvent::Modified { id: loaded } => {
+            *loaded == id
+        }
+        _ => false,
+    });
+    if !loaded && !grid_image.is_changed() {
+        return;
+    }
+
+    let Some(image) = images.get(id) else {
+        return;
+    };
+
+    let mut built = match Grid::from_image(image, grid_image.cell_size, grid_image.mapping) {
+        Ok(built) => built,
+        Err(err) => {
+            warn!("Can't build the grid from the cost image: {err}");
+            return;
+        }
+    };
+    built.buckets = grid_image.buckets;
+
+    install_grid(&mut cmds, grid, built);
+}
+
+// replaces the grid with `built`, keeping the obstacles that were stamped into the previous one, and rebuilds the
+// flowfields
+pub(crate) fn install_grid(cmds: &mut Commands, grid: Option<ResMut<Grid>>, mut built: Grid) {
+    match grid {
+        Some(mut grid) => {
+            for cells in grid.occupied_cells.values() {
+                for &cell in cells {
+                    if cell.cmplt(built.size).all() {
+                        let cost = &mut built.grid[cell.y as usize][cell.x as usize].cost;
+                        built.covered_cells.entry(cell).or_insert((*cost, 0)).1 += 1;
+                        *cost = u8::MAX;
+                    }
+                }
+            }
+            built.occupied_cells = std::mem::take(&mut grid.occupied_cells);
+            built.set_plane(grid.plane);
+            built.set_transform(grid.transform);
+            if built.size == grid.size {
+                built.layers = std::mem::take(&mut grid.layers);
+                built.terrain = std::mem::take(&mut grid.terrain);
+            }
+            *grid = built;
+        }
+        None => cmds.insert_resource(built),
     }
 
     cmds.trigger(UpdateCostEv);
 }
 
-// detects if a static object has been removed and updates the costfield
-fn update_costfield_on_remove(
+// height of the triangle above `point` on the XZ plane, `None` if the point lies outside of it
+fn triangle_height(point: Vec2, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let (a2, b2, c2) = (a.xz(), b.xz(), c.xz());
+    let area = (b2 - a2).perp_dot(c2 - a2);
+    if area.abs() <= f32::EPSILON {
+        return None;
+    }
+
+    let wa = (b2 - point).perp_dot(c2 - point) / area;
+    let wb = (c2 - point).perp_dot(a2 - point) / area;
+    let wc = 1.0 - wa - wb;
+    if wa < -f32::EPSILON || wb < -f32::EPSILON || wc < -f32::EPSILON {
+        return None;
+    }
+
+    Some(a.y * wa + b.y * wb + c.y * wc)
+}
+
+// bounding rect of cell indices, inclusive
+fn cells_region(cells: &[IVec2]) -> Option<IRect> {
+    let first = *cells.first()?;
+    let (min, max) = cells
+        .iter()
+        .fold((first, first), |(min, max), &c| (min.min(c), max.max(c)));
+    Some(IRect::from_corners(min, max))
+}
+
+fn union_region(a: Option<IRect>, b: Option<IRect>) -> Option<IRect> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.union(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+type UnsizedObstacle = (
+    Entity,
+    &'static Transform,
+    Option<&'static Aabb>,
+    Option<&'static Mesh3d>,
+);
+
+// sizes every `ObstacleAuto` from its collider, bounds or mesh as soon as one of them is available
+fn derive_obstacle_size(
+    mut cmds: Commands,
+    plane: Option<Res<Plane>>,
+    meshes: Option<Res<Assets<Mesh>>>,
+    q_auto: Query<UnsizedObstacle, (With<ObstacleAuto>, Without<Obstacle>)>,
+    #[cfg(feature = "avian")] q_colliders: Query<&avian3d::prelude::Collider>,
+) {
+    let plane = plane.as_deref().copied().unwrap_or_default();
+    for (ent, tf, aabb, mesh) in q_auto.iter() {
+        #[cfg(feature = "avian")]
+        let collider_size = q_colliders.get(ent).ok().map(|collider| {
+            let aabb = collider.aabb(Vec3::ZERO, Quat::IDENTITY);
+            aabb.max - aabb.min
+        });
+        #[cfg(not(feature = "avian"))]
+        let collider_size: Option<Vec3> = None;
+
+        let size = collider_size
+            .or_else(|| aabb.map(|aabb| Vec3::from(aabb.half_extents) * 2.0))
+            .or_else(|| {
+                let aabb = meshes.as_ref()?.get(&mesh?.0)?.compute_aabb()?;
+                Some(Vec3::from(aabb.half_extents) * 2.0)
+            });
+
+        // the grid applies the rotation when carving the cells
+        if let Some(size) = size {
+            let size = Mat3::from_quat(plane.rotation().inverse()).abs() * (size * tf.scale);
+            cmds.entity(ent).insert(Obstacle(Vec2::new(size.x, size.z)));
+        }
+    }
+}
+
+// stamps an obstacle into the costfield as soon as it is placed, re-inserting it moves or resizes the footprint
+fn carve_obstacle(
+    trigger: On<Insert, Obstacle>,
     mut cmds: Commands,
     mut grid: ResMut<Grid>,
-    mut removed: RemovedComponents<Obstacle>,
+    q_obstacles: Query<(&Transform, &Obstacle, Option<&Gate>)>,
 ) {
-    let objs: Vec<Entity> = removed.read().collect();
+    let ent = trigger.entity;
+    let Ok((transform, obstacle, gate)) = q_obstacles.get(ent) else {
+        return;
+    };
 
-    if !objs.is_empty() {
-        grid.reset_cell_costs(objs);
+    // only the covered cells and the paths through them get re-integrated, once per frame
+    if let Some(region) = restamp_obstacle(&mut grid, ent, transform, obstacle, gate) {
+        cmds.trigger(CostFieldChangedEv { region });
+    }
+}
+
+// frees or carves the footprint of every gate that was opened or closed, or became a gate
+fn toggle_gates(
+    mut cmds: Commands,
+    mut grid: ResMut<Grid>,
+    q_gates: Query<(Entity, &Transform, Ref<Obstacle>, &Gate), Changed<Gate>>,
+) {
+    for (ent, transform, obstacle, gate) in q_gates.iter() {
+        // a freshly inserted obstacle was already stamped with its gate by `carve_obstacle`
+        if obstacle.is_added() {
+            continue;
+        }
+
+        if let Some(region) = restamp_obstacle(&mut grid, ent, transform, &obstacle, Some(gate)) {
+            cmds.trigger(CostFieldChangedEv { region });
+        }
+    }
+}
+
+// frees the old footprint and carves the new one, open gates stay free. Returns the touched cells.
+fn restamp_obstacle(
+    grid: &mut Grid,
+    ent: Entity,
+    transform: &Transform,
+    obstacle: &Obstacle,
+    gate: Option<&Gate>,
+) -> Option<IRect> {
+    let freed = grid.reset_cell_costs(vec![ent]);
+    let carved = match gate {
+        Some(gate) if gate.open => None,
+        _ => grid.update_cell_costs(ent.index(), transform, obstacle),
+    };
+    union_region(freed, carved)
+}
+
+fn rebuild_grid(
+    trigger: On<RebuildGridEv>,
+    mut cmds: Commands,
+    mut grid: ResMut<Grid>,
+    q_obstacles: Query<(Entity, &Transform, &Obstacle, Option<&Gate>)>,
+) {
+    let ev = trigger.event();
+    let closed = q_obstacles
+        .iter()
+        .filter(|(.., gate)| !gate.is_some_and(|gate| gate.open))
+        .map(|(ent, transform, obstacle, _)| (ent, transform, obstacle));
+    grid.rebuild(ev.size, ev.cell_size, closed);
+    cmds.trigger(UpdateCostEv);
+}
+
+// turns the grid onto the plane the agents move on, see `FvoPlugin::with_plane`
+fn sync_grid_plane(mut cmds: Commands, plane: Res<Plane>, mut grid: ResMut<Grid>) {
+    if grid.plane != *plane {
+        grid.set_plane(*plane);
         cmds.trigger(UpdateCostEv);
     }
 }
+
+// frees the cells of an obstacle as soon as it is removed or despawned, e.g. a destroyed building
+fn clear_obstacle(trigger: On<Remove, Obstacle>, mut cmds: Commands, mut grid: ResMut<Grid>) {
+    if let Some(region) = grid.reset_cell_costs(vec![trigger.entity]) {
+        cmds.trigger(CostFieldChangedEv { region });
+    }
+}
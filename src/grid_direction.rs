@@ -15,6 +15,7 @@ const DIRECTIONS: [GridDirection; 9] = [
 ];
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub enum GridDirection {
     #[default]
     None,
@@ -43,6 +44,21 @@ impl GridDirection {
         }
     }
 
+    /// The direction pointing the other way, `None` stays `None`.
+    pub fn opposite(self) -> GridDirection {
+        match self {
+            GridDirection::None => GridDirection::None,
+            GridDirection::North => GridDirection::South,
+            GridDirection::South => GridDirection::North,
+            GridDirection::East => GridDirection::West,
+            GridDirection::West => GridDirection::East,
+            GridDirection::NorthEast => GridDirection::SouthWest,
+            GridDirection::NorthWest => GridDirection::SouthEast,
+            GridDirection::SouthEast => GridDirection::NorthWest,
+            GridDirection::SouthWest => GridDirection::NorthEast,
+        }
+    }
+
     pub fn print_short(&self) {
         match self {
             GridDirection::None => print!("X , "),
@@ -0,0 +1,149 @@
+use bevy::prelude::*;
+
+/// Balanced 2D kd-tree over agent positions on the XZ plane.
+///
+/// Unlike the [`SpatialHash`](crate::spatial_hash::SpatialHash) it adapts to the agent distribution, so a dense blob
+/// on a huge, mostly empty map stays cheap to query. Built from scratch every frame when
+/// [`NeighborSearch::KdTree`](crate::fvo::NeighborSearch::KdTree) is selected.
+#[derive(Debug, Default, Clone)]
+pub struct KdTree {
+    // implicit tree: the median of every slice is its node, left half <= node < right half on the split axis
+    points: Vec<(Vec2, Entity)>,
+}
+
+impl KdTree {
+    pub fn build(points: impl IntoIterator<Item = (Vec3, Entity)>) -> Self {
+        let mut points: Vec<(Vec2, Entity)> = points
+            .into_iter()
+            .map(|(pos, ent)| (Vec2::new(pos.x, pos.z), ent))
+            .collect();
+        build_recursive(&mut points, 0);
+
+        Self { points }
+    }
+
+    /// Like [`KdTree::build`], but reuses the allocation of the previous tree.
+    pub fn rebuild(&mut self, points: impl IntoIterator<Item = (Vec3, Entity)>) {
+        self.points.clear();
+        self.points.extend(
+            points
+                .into_iter()
+                .map(|(pos, ent)| (Vec2::new(pos.x, pos.z), ent)),
+        );
+        build_recursive(&mut self.points, 0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Calls `f` for every entity within `range` of `pos`, with its squared distance.
+    pub fn within(&self, pos: Vec3, range: f32, mut f: impl FnMut(Entity, f32)) {
+        let center = Vec2::new(pos.x, pos.z);
+        within_recursive(&self.points, 0, center, range, &mut f);
+    }
+
+    /// Returns up to `k` entities closest to `pos` that pass `filter`, nearest first, with their squared distance.
+    pub fn nearest(
+        &self,
+        pos: Vec3,
+        k: usize,
+        filter: impl Fn(Entity) -> bool,
+    ) -> Vec<(f32, Entity)> {
+        let mut best = Vec::with_capacity(k + 1);
+        if k > 0 {
+            let center = Vec2::new(pos.x, pos.z);
+            nearest_recursive(&self.points, 0, center, k, &filter, &mut best);
+        }
+        best
+    }
+}
+
+fn axis(p: Vec2, depth: usize) -> f32 {
+    if depth.is_multiple_of(2) {
+        p.x
+    } else {
+        p.y
+    }
+}
+
+fn build_recursive(points: &mut [(Vec2, Entity)], depth: usize) {
+    if points.len() <= 1 {
+        return;
+    }
+
+    let mid = points.len() / 2;
+    points.select_nth_unstable_by(mid, |a, b| axis(a.0, depth).total_cmp(&axis(b.0, depth)));
+
+    let (left, right) = points.split_at_mut(mid);
+    build_recursive(left, depth + 1);
+    build_recursive(&mut right[1..], depth + 1);
+}
+
+fn within_recursive(
+    points: &[(Vec2, Entity)],
+    depth: usize,
+    center: Vec2,
+    range: f32,
+    f: &mut impl FnMut(Entity, f32),
+) {
+    if points.is_empty() {
+        return;
+    }
+
+    let mid = points.len() / 2;
+    let (p, ent) = points[mid];
+    let dist_sq = p.distance_squared(center);
+    if dist_sq <= range * range {
+        f(ent, dist_sq);
+    }
+
+    let diff = axis(center, depth) - axis(p, depth);
+    if diff <= range {
+        within_recursive(&points[..mid], depth + 1, center, range, f);
+    }
+    if diff >= -range {
+        within_recursive(&points[mid + 1..], depth + 1, center, range, f);
+    }
+}
+
+fn nearest_recursive(
+    points: &[(Vec2, Entity)],
+    depth: usize,
+    center: Vec2,
+    k: usize,
+    filter: &impl Fn(Entity) -> bool,
+    best: &mut Vec<(f32, Entity)>,
+) {
+    if points.is_empty() {
+        return;
+    }
+
+    let mid = points.len() / 2;
+    let (p, ent) = points[mid];
+    if filter(ent) {
+        let dist_sq = p.distance_squared(center);
+        if best.len() < k || dist_sq < best[best.len() - 1].0 {
+            let idx = best.partition_point(|(d, _)| *d <= dist_sq);
+            best.insert(idx, (dist_sq, ent));
+            best.truncate(k);
+        }
+    }
+
+    // visit the side containing the center first, the other only if it can still hold a closer point
+    let diff = axis(center, depth) - axis(p, depth);
+    let (near, far) = if diff <= 0.0 {
+        (&points[..mid], &points[mid + 1..])
+    } else {
+        (&points[mid + 1..], &points[..mid])
+    };
+
+    nearest_recursive(near, depth + 1, center, k, filter, best);
+    if best.len() < k || diff * diff < best[best.len() - 1].0 {
+        nearest_recursive(far, depth + 1, center, k, filter, best);
+    }
+}
@@ -2,36 +2,70 @@ use bevy::prelude::*;
 
 #[cfg(feature = "debug")]
 use crate::debug::DebugPlugin;
+#[cfg(feature = "debug")]
 use crate::events::*;
+#[cfg(feature = "debug")]
 use crate::resources::*;
 
+#[cfg(feature = "avian")]
+pub mod avian;
 pub mod fvo;
+pub mod cache;
 mod cell;
+pub mod congestion;
 pub mod components;
+#[cfg(feature = "debug")]
 pub mod debug;
+pub mod diagnostics;
 pub mod events;
+#[cfg(feature = "debug")]
+pub mod export;
 pub mod flowfield;
+pub mod formation;
 pub mod grid;
 pub mod grid_direction;
+pub mod kd_tree;
+pub mod lod;
+pub mod plane;
+pub mod preferred;
+#[cfg(feature = "rapier")]
+pub mod rapier;
+#[cfg(feature = "debug")]
 pub mod resources;
+#[cfg(feature = "render")]
+pub mod rts_input;
+pub mod sectors;
+pub mod smoothing;
+pub mod spawn;
+pub mod spatial_hash;
+pub mod steering;
+pub mod stuck;
+#[cfg(feature = "tilemap")]
+pub mod tilemap;
+pub mod trajectory;
+#[cfg(feature = "ron")]
+pub mod tuning;
 pub mod utils;
 
 use fvo::FvoPlugin;
 use flowfield::FlowfieldPlugin;
 use grid::GridPlugin;
+#[cfg(feature = "debug")]
 use resources::ResourcesPlugin;
 
 pub struct BevyPathfindingPlugin;
 
 impl Plugin for BevyPathfindingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((
-            FvoPlugin,
-            FlowfieldPlugin,
-            ResourcesPlugin,
-            GridPlugin,
-            #[cfg(feature = "debug")]
-            DebugPlugin,
-        ));
+        // a custom configured FvoPlugin may have been added by the user already
+        if !app.is_plugin_added::<FvoPlugin>() {
+            app.add_plugins(FvoPlugin::default());
+        }
+
+        app.add_plugins((FlowfieldPlugin, GridPlugin));
+
+        // rendering and the debug overlays stay out of headless builds
+        #[cfg(feature = "debug")]
+        app.add_plugins((ResourcesPlugin, DebugPlugin));
     }
 }
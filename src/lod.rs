@@ -0,0 +1,132 @@
+//! Solve-rate level of detail. In a big battle only a fraction of the agents is seen up close, the others are solved
+//! every few ticks and keep their last velocity in between.
+
+use bevy::{
+    camera::primitives::{Frustum, Sphere},
+    prelude::*,
+};
+
+use crate::components::FvoAgent;
+
+/// A distance band of [`FvoLodSettings`]: agents at least `distance` away from the closest camera are solved every
+/// `interval` ticks.
+#[derive(Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct LodLevel {
+    pub distance: f32,
+    pub interval: u32,
+}
+
+impl LodLevel {
+    pub fn new(distance: f32, interval: u32) -> Self {
+        Self { distance, interval }
+    }
+}
+
+/// Insert this resource to solve far and off-screen agents less often. Agents are spread over the ticks of their
+/// interval so the load stays even, and skipped agents keep moving at their last velocity and are still avoided by
+/// everyone else. Without an active camera, e.g. on a headless server, every agent is solved every tick.
+///
+/// # Example
+///
+/// ```
+/// app.insert_resource(FvoLodSettings {
+///     levels: vec![LodLevel::new(100.0, 2), LodLevel::new(250.0, 6)],
+///     offscreen_interval: Some(8),
+/// });
+/// ```
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct FvoLodSettings {
+    /// Distance bands, the farthest band an agent is in applies.
+    pub levels: Vec<LodLevel>,
+    /// Interval of agents outside of the view of every camera, `None` to only go by distance.
+    pub offscreen_interval: Option<u32>,
+}
+
+impl Default for FvoLodSettings {
+    fn default() -> Self {
+        Self {
+            levels: vec![LodLevel::new(150.0, 2), LodLevel::new(300.0, 4)],
+            offscreen_interval: Some(4),
+        }
+    }
+}
+
+impl FvoLodSettings {
+    /// Interval of an agent `distance` away from the closest camera.
+    pub fn interval(&self, distance: f32, visible: bool) -> u32 {
+        let by_distance = self
+            .levels
+            .iter()
+            .filter(|level| distance >= level.distance)
+            .map(|level| level.interval)
+            .max()
+            .unwrap_or(1);
+        let by_visibility = self.offscreen_interval.filter(|_| !visible).unwrap_or(1);
+
+        by_distance.max(by_visibility).max(1)
+    }
+}
+
+/// Solve rate of an agent, picked by the LOD every tick.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+#[reflect(Component)]
+pub struct FvoLod {
+    /// The agent is solved every `interval` ticks, `1` for every tick.
+    pub interval: u32,
+    /// Whether the agent is solved this tick.
+    pub solve: bool,
+}
+
+impl Default for FvoLod {
+    fn default() -> Self {
+        Self {
+            interval: 1,
+            solve: true,
+        }
+    }
+}
+
+/// Picks the [`FvoLod`] of every agent by its distance to and visibility from the active cameras.
+pub fn update_lod(
+    settings: Option<Res<FvoLodSettings>>,
+    mut tick: Local<u32>,
+    q_cameras: Query<(&Camera, &GlobalTransform, &Frustum)>,
+    mut q_agents: Query<(Entity, &Transform, &FvoAgent, &mut FvoLod)>,
+) {
+    *tick = tick.wrapping_add(1);
+    let cameras: Vec<(Vec3, &Frustum)> = q_cameras
+        .iter()
+        .filter(|(camera, ..)| camera.is_active)
+        .map(|(_, tf, frustum)| (tf.translation(), frustum))
+        .collect();
+
+    let Some(settings) = settings.filter(|_| !cameras.is_empty()) else {
+        for (.., mut lod) in q_agents.iter_mut() {
+            if *lod != FvoLod::default() {
+                *lod = FvoLod::default();
+            }
+        }
+        return;
+    };
+
+    for (ent, tf, agent, mut lod) in q_agents.iter_mut() {
+        let pos = tf.translation;
+        let distance = cameras
+            .iter()
+            .map(|(camera, _)| camera.distance(pos))
+            .fold(f32::INFINITY, f32::min);
+        let sphere = Sphere {
+            center: pos.into(),
+            radius: agent.settings.radius,
+        };
+        let visible = cameras
+            .iter()
+            .any(|(_, frustum)| frustum.intersects_sphere(&sphere, true));
+
+        let interval = settings.interval(distance, visible);
+        // the entity index staggers the agents of an interval over its ticks
+        let solve = (tick.wrapping_add(ent.index())) % interval == 0;
+        *lod = FvoLod { interval, solve };
+    }
+}
@@ -0,0 +1,115 @@
+//! The plane agents move on. The grid, the flowfields and the solver work on XZ with Y up, positions and directions
+//! are projected in and out of that space through the [`Plane`], so Y-up, Z-up and tilted worlds share the same
+//! solver code.
+
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+
+/// The plane agents move on, inserted as a resource by the [`FvoPlugin`](crate::fvo::FvoPlugin), see
+/// [`FvoPlugin::with_plane`](crate::fvo::FvoPlugin::with_plane). Transforms, velocities and event positions stay in
+/// world space, the [`Grid`](crate::grid::Grid) follows the plane on its own.
+///
+/// # Example
+///
+/// ```
+/// // a 2D game with sprites on XY
+/// app.add_plugins((FvoPlugin::new().with_plane(Plane::XY), BevyPathfindingPlugin));
+///
+/// // a world built with Z up
+/// app.add_plugins((FvoPlugin::new().with_plane(Plane::from_up(Vec3::Z)), BevyPathfindingPlugin));
+/// ```
+#[derive(Resource, Reflect, Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[reflect(Resource)]
+pub enum Plane {
+    /// 3D with Y up, agents move on the ground.
+    #[default]
+    XZ,
+    /// Z up, e.g. 2D with sprites on XY and Z toward the camera. Grid rows run down the screen (along -Y), like the
+    /// rows of a cost image.
+    XY,
+    /// Any orientation, `rotation` turns plane space (XZ with Y up) into world space.
+    Custom { rotation: Quat },
+}
+
+impl Plane {
+    /// The plane whose up axis is `up`, [`Plane::XZ`] for `Y` and a [`Plane::Custom`] for anything else. Models
+    /// facing their heading keep looking along their -Z, see [`Plane::facing`].
+    pub fn from_up(up: Vec3) -> Self {
+        let up = up.normalize_or(Vec3::Y);
+        if up == Vec3::Y {
+            return Plane::XZ;
+        }
+
+        Plane::Custom {
+            rotation: Quat::from_rotation_arc(Vec3::Y, up),
+        }
+    }
+
+    /// Turns a world position or direction into plane space, where agents move on XZ and Y is up.
+    pub fn to_plane(self, world: Vec3) -> Vec3 {
+        match self {
+            Plane::XZ => world,
+            Plane::XY => Vec3::new(world.x, world.z, -world.y),
+            Plane::Custom { rotation } => rotation.inverse() * world,
+        }
+    }
+
+    /// Turns a position or direction in plane space back into world space, the inverse of [`Plane::to_plane`].
+    pub fn from_plane(self, plane: Vec3) -> Vec3 {
+        match self {
+            Plane::XZ => plane,
+            Plane::XY => Vec3::new(plane.x, -plane.z, plane.y),
+            Plane::Custom { rotation } => rotation * plane,
+        }
+    }
+
+    /// Projects a world position or direction onto the plane, the 2D coordinates the solver works with.
+    pub fn to_2d(self, world: Vec3) -> Vec2 {
+        self.to_plane(world).xz()
+    }
+
+    /// Turns 2D coordinates on the plane back into world space, at height zero.
+    pub fn from_2d(self, plane: Vec2) -> Vec3 {
+        self.from_plane(Vec3::new(plane.x, 0.0, plane.y))
+    }
+
+    /// Rotation from plane space into world space.
+    pub fn rotation(self) -> Quat {
+        match self {
+            Plane::XZ => Quat::IDENTITY,
+            Plane::XY => Quat::from_rotation_x(FRAC_PI_2),
+            Plane::Custom { rotation } => rotation,
+        }
+    }
+
+    /// The world direction pointing away from the plane, `Y` for [`Plane::XZ`] and `Z` for [`Plane::XY`].
+    pub fn up(self) -> Vec3 {
+        self.from_plane(Vec3::Y)
+    }
+
+    /// Drops the part of a world vector pointing away from the plane.
+    pub fn flatten(self, world: Vec3) -> Vec3 {
+        let up = self.up();
+        world - up * world.dot(up)
+    }
+
+    /// Rotation of an entity facing the world direction `heading`. Models look along their -Z with their Y up, sprites
+    /// on [`Plane::XY`] turn around Z with their top (+Y) ahead.
+    pub fn facing(self, heading: Vec3) -> Quat {
+        match self {
+            Plane::XZ => Transform::IDENTITY.looking_to(heading, Vec3::Y).rotation,
+            Plane::XY => Quat::from_rotation_z((-heading.x).atan2(heading.y)),
+            Plane::Custom { rotation } => rotation * Plane::XZ.facing(self.to_plane(heading)),
+        }
+    }
+
+    /// The world direction an entity with `rotation` faces, see [`Plane::facing`].
+    pub fn forward(self, rotation: Quat) -> Vec3 {
+        match self {
+            Plane::XZ | Plane::Custom { .. } => rotation * Vec3::NEG_Z,
+            Plane::XY => rotation * Vec3::Y,
+        }
+    }
+}
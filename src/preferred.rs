@@ -0,0 +1,165 @@
+//! Where agents want to go, kept apart from how the solver gets them there.
+//!
+//! Every tick the [`PreferredVelocity`] of each agent is cleared, then the systems in
+//! [`FvoSystemSet::PreferredVelocity`](crate::fvo::FvoSystemSet::PreferredVelocity) add to it: the flowfield lookup,
+//! [`Steering`](crate::steering::Steering), [`PreferredVelocityFn`] closures and any [`PreferredVelocityProvider`] of
+//! your own, e.g. a navmesh agent or an AI controller. The solver only reads the blend, so it doesn't care where it
+//! came from.
+
+use bevy::{ecs::component::Mutable, prelude::*};
+
+use crate::components::FvoAgent;
+
+/// The velocity an agent would take without anyone in its way, the input of the avoidance solve. Providers add their
+/// desired velocity with a weight and the solver steers toward the weighted blend. Agents nothing was provided for in
+/// a tick are left alone by the solver and keep their last velocity.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq)]
+#[reflect(Component)]
+pub struct PreferredVelocity {
+    sum: Vec3,
+    weight: f32,
+    provided: bool,
+}
+
+impl PreferredVelocity {
+    /// Adds a desired velocity, blended with the others by `weight`.
+    pub fn add(&mut self, velocity: Vec3, weight: f32) {
+        let weight = weight.max(0.0);
+        self.sum += velocity * weight;
+        self.weight += weight;
+        self.provided = true;
+    }
+
+    /// Replaces everything provided so far, for providers that refine the blend of the others.
+    pub fn set(&mut self, velocity: Vec3) {
+        self.sum = velocity;
+        self.weight = 1.0;
+        self.provided = true;
+    }
+
+    /// The weighted blend of this tick, `None` when nothing was provided.
+    pub fn get(&self) -> Option<Vec3> {
+        if !self.provided {
+            None
+        } else if self.weight > f32::EPSILON {
+            Some(self.sum / self.weight)
+        } else {
+            Some(Vec3::ZERO)
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// A component that decides where its agent wants to go. Run [`provide_preferred_velocity`] for it in
+/// [`FvoSystemSet::PreferredVelocity`](crate::fvo::FvoSystemSet::PreferredVelocity) and the solver blends its output
+/// with every other provider of the agent.
+///
+/// # Example
+///
+/// ```
+/// #[derive(Component)]
+/// struct Chase(Entity);
+///
+/// impl PreferredVelocityProvider for Chase {
+///     fn preferred_velocity(&mut self, _: Entity, tf: &Transform, agent: &FvoAgent) -> Option<Vec3> {
+///         // look up the prey, or keep a copy of its position up to date in another system
+///         ...
+///     }
+/// }
+///
+/// app.add_systems(Update, provide_preferred_velocity::<Chase>.in_set(FvoSystemSet::PreferredVelocity));
+/// ```
+pub trait PreferredVelocityProvider: Component<Mutability = Mutable> {
+    /// The desired velocity of the agent, `None` to leave it to the other providers this tick.
+    fn preferred_velocity(
+        &mut self,
+        entity: Entity,
+        transform: &Transform,
+        agent: &FvoAgent,
+    ) -> Option<Vec3>;
+
+    /// Weight of the output against the other providers of the agent.
+    fn weight(&self) -> f32 {
+        1.0
+    }
+}
+
+/// Adds the output of every `P` to the [`PreferredVelocity`] of its agent.
+pub fn provide_preferred_velocity<P: PreferredVelocityProvider>(
+    mut q_agents: Query<(
+        Entity,
+        &Transform,
+        &FvoAgent,
+        &mut P,
+        &mut PreferredVelocity,
+    )>,
+) {
+    for (ent, tf, agent, mut provider, mut preferred) in q_agents.iter_mut() {
+        if let Some(velocity) = provider.preferred_velocity(ent, tf, agent) {
+            preferred.add(velocity, provider.weight());
+        }
+    }
+}
+
+type ProviderFn = dyn FnMut(Entity, &Transform, &FvoAgent) -> Option<Vec3> + Send + Sync;
+
+/// A [`PreferredVelocityProvider`] from a closure, for one-off behavior that doesn't deserve a type.
+///
+/// # Example
+///
+/// ```
+/// // circle the origin
+/// cmds.spawn((
+///     FvoAgent::default(),
+///     PreferredVelocityFn::new(|_, tf, agent| {
+///         let tangent = Vec3::Y.cross(tf.translation).normalize_or_zero();
+///         Some(tangent * agent.settings.preferred_speed)
+///     }),
+/// ));
+/// ```
+#[derive(Component)]
+pub struct PreferredVelocityFn {
+    provider: Box<ProviderFn>,
+    pub weight: f32,
+}
+
+impl PreferredVelocityFn {
+    pub fn new(
+        provider: impl FnMut(Entity, &Transform, &FvoAgent) -> Option<Vec3> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            provider: Box::new(provider),
+            weight: 1.0,
+        }
+    }
+
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+impl PreferredVelocityProvider for PreferredVelocityFn {
+    fn preferred_velocity(
+        &mut self,
+        entity: Entity,
+        transform: &Transform,
+        agent: &FvoAgent,
+    ) -> Option<Vec3> {
+        (self.provider)(entity, transform, agent)
+    }
+
+    fn weight(&self) -> f32 {
+        self.weight
+    }
+}
+
+// runs before the providers so every tick starts from nothing
+pub(crate) fn clear_preferred_velocities(mut q_agents: Query<&mut PreferredVelocity>) {
+    for mut preferred in q_agents.iter_mut() {
+        preferred.clear();
+    }
+}
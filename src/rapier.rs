@@ -0,0 +1,78 @@
+//! Hands the solved velocities to `bevy_rapier3d` instead of moving transforms directly. Enabled with the `rapier`
+//! feature and wired up by the [`FvoPlugin`](crate::fvo::FvoPlugin).
+//!
+//! Agents with a [`Velocity`] (dynamic or velocity-based kinematic bodies) get the solved velocity written into
+//! `linvel`, agents with a [`KinematicCharacterController`] get it as `translation`. The velocity the physics step
+//! actually produced is read back into [`FvoAgent::velocity`] before the next solve, so neighbors see where the body
+//! really went. Do not also enable [`FvoPlugin::apply_translation`](crate::fvo::FvoPlugin::apply_translation), that
+//! would move the agents twice.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::components::*;
+use crate::fvo::{solver_dt, FvoConfig};
+use crate::plane::Plane;
+
+/// Replaces [`FvoAgent::velocity`] with the physics-corrected velocity of the last step.
+pub fn read_rapier_velocity(
+    time: Res<Time>,
+    fixed_time: Res<Time<Fixed>>,
+    config: Res<FvoConfig>,
+    plane: Res<Plane>,
+    mut q_bodies: Query<(&mut FvoAgent, &Velocity)>,
+    mut q_controllers: Query<
+        (&mut FvoAgent, &KinematicCharacterControllerOutput),
+        Without<Velocity>,
+    >,
+) {
+    for (mut agent, velocity) in q_bodies.iter_mut() {
+        agent.velocity = planar(&agent, *plane, velocity.linvel);
+    }
+
+    let dt = solver_dt(&config, &time, &fixed_time);
+    if dt <= f32::EPSILON {
+        return;
+    }
+
+    for (mut agent, output) in q_controllers.iter_mut() {
+        agent.velocity = planar(&agent, *plane, output.effective_translation / dt);
+    }
+}
+
+/// Writes the solved velocity into the rigid body or character controller of every agent that still has a
+/// [`Destination`] or settled with [`Arrived`]. Planar agents keep the velocity of their body away from the [`Plane`],
+/// so gravity still applies.
+pub fn write_rapier_velocity(
+    time: Res<Time>,
+    fixed_time: Res<Time<Fixed>>,
+    config: Res<FvoConfig>,
+    plane: Res<Plane>,
+    mut q_bodies: Query<(&FvoAgent, &mut Velocity), Or<(With<Destination>, With<Arrived>)>>,
+    mut q_controllers: Query<
+        (&FvoAgent, &mut KinematicCharacterController),
+        (Or<(With<Destination>, With<Arrived>)>, Without<Velocity>),
+    >,
+) {
+    for (agent, mut velocity) in q_bodies.iter_mut() {
+        velocity.linvel = match agent.space {
+            NavigationSpace::Planar => {
+                let up = plane.up();
+                plane.flatten(agent.velocity) + up * velocity.linvel.dot(up)
+            }
+            NavigationSpace::Volumetric => agent.velocity,
+        };
+    }
+
+    let dt = solver_dt(&config, &time, &fixed_time);
+    for (agent, mut controller) in q_controllers.iter_mut() {
+        controller.translation = Some(agent.velocity * dt);
+    }
+}
+
+fn planar(agent: &FvoAgent, plane: Plane, velocity: Vec3) -> Vec3 {
+    match agent.space {
+        NavigationSpace::Planar => plane.flatten(velocity),
+        NavigationSpace::Volumetric => velocity,
+    }
+}
@@ -0,0 +1,258 @@
+//! Opt-in RTS controls for prototypes: drag a box or click to select agents, right-click the map to move them. Add
+//! [`FvoRtsInputPlugin`] next to the [`BevyPathfindingPlugin`](crate::BevyPathfindingPlugin), the cursor is cast from
+//! the [`GameCamera`] onto the plane of the [`MapBase`].
+
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{
+    color::palettes::css::LIME, ecs::system::SystemParam, prelude::*, window::PrimaryWindow,
+};
+
+#[cfg(feature = "debug")]
+use crate::debug::resources::DbgOptions;
+use crate::{
+    components::{FvoAgent, GameCamera, MapBase},
+    events::MoveOrderEvent,
+    plane::Plane,
+};
+
+pub struct FvoRtsInputPlugin;
+
+impl Plugin for FvoRtsInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FvoRtsInput>()
+            .register_type::<FvoRtsInput>()
+            .register_type::<Selected>()
+            .add_systems(Startup, spawn_selection_box)
+            .add_systems(
+                Update,
+                (select_agents, order_selected, draw_selected).chain(),
+            );
+    }
+}
+
+/// Buttons and look of the [`FvoRtsInputPlugin`].
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct FvoRtsInput {
+    /// Selects by click or drag.
+    pub select_button: MouseButton,
+    /// Orders the selection to the clicked point.
+    pub order_button: MouseButton,
+    /// Held to add to the selection instead of replacing it.
+    pub additive_key: KeyCode,
+    /// Pixels the cursor has to travel before a press becomes a box selection.
+    pub drag_threshold: f32,
+    /// Color of the box and of the rings under selected agents.
+    pub color: Color,
+}
+
+impl Default for FvoRtsInput {
+    fn default() -> Self {
+        Self {
+            select_button: MouseButton::Left,
+            order_button: MouseButton::Right,
+            additive_key: KeyCode::ShiftLeft,
+            drag_threshold: 4.0,
+            color: LIME.into(),
+        }
+    }
+}
+
+/// Marks an agent selected by the [`FvoRtsInputPlugin`]. Insert or remove it yourself to select from code.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct Selected;
+
+#[derive(Component)]
+struct SelectionBox;
+
+fn spawn_selection_box(mut cmds: Commands, input: Res<FvoRtsInput>) {
+    cmds.spawn((
+        SelectionBox,
+        Node {
+            position_type: PositionType::Absolute,
+            display: Display::None,
+            border: UiRect::all(Val::Px(1.0)),
+            ..default()
+        },
+        BackgroundColor(input.color.with_alpha(0.1)),
+        BorderColor::from(input.color),
+        Name::new("SelectionBox"),
+    ));
+}
+
+#[derive(SystemParam)]
+struct Cursor<'w, 's> {
+    q_window: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    plane: Res<'w, Plane>,
+    #[cfg(feature = "debug")]
+    dbg: Option<Res<'w, DbgOptions>>,
+}
+
+impl Cursor<'_, '_> {
+    // in viewport coordinates
+    fn position(&self) -> Option<Vec2> {
+        self.q_window.single().ok()?.cursor_position()
+    }
+
+    // clicks on the debug UI are not meant for the map
+    fn over_ui(&self) -> bool {
+        #[cfg(feature = "debug")]
+        if self.dbg.as_ref().is_some_and(|dbg| dbg.hover) {
+            return true;
+        }
+
+        false
+    }
+}
+
+// click picks the agent under the cursor, drag picks every agent inside the box
+#[allow(clippy::too_many_arguments)]
+fn select_agents(
+    mut cmds: Commands,
+    input: Res<FvoRtsInput>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Cursor,
+    mut drag_start: Local<Option<Vec2>>,
+    q_cam: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
+    q_map: Query<&GlobalTransform, With<MapBase>>,
+    q_agents: Query<(Entity, &GlobalTransform, &FvoAgent)>,
+    q_selected: Query<Entity, With<Selected>>,
+    mut q_box: Query<&mut Node, With<SelectionBox>>,
+) {
+    let position = cursor.position();
+    if mouse.just_pressed(input.select_button) && !cursor.over_ui() {
+        *drag_start = position;
+    }
+
+    let Some(start) = *drag_start else {
+        return;
+    };
+    let end = position.unwrap_or(start);
+    let rect = Rect::from_corners(start, end);
+    let dragging = start.distance(end) >= input.drag_threshold;
+
+    if let Ok(mut node) = q_box.single_mut() {
+        node.display = if dragging {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        node.left = Val::Px(rect.min.x);
+        node.top = Val::Px(rect.min.y);
+        node.width = Val::Px(rect.width());
+        node.height = Val::Px(rect.height());
+    }
+
+    if !mouse.just_released(input.select_button) {
+        return;
+    }
+    *drag_start = None;
+
+    let Ok((cam, cam_tf)) = q_cam.single() else {
+        return;
+    };
+
+    let picked: Vec<Entity> = if dragging {
+        q_agents
+            .iter()
+            .filter(|(_, tf, _)| {
+                cam.world_to_viewport(cam_tf, tf.translation())
+                    .is_ok_and(|pos| rect.contains(pos))
+            })
+            .map(|(ent, ..)| ent)
+            .collect()
+    } else {
+        // the closest agent whose footprint the cursor is on
+        let ground = q_map
+            .single()
+            .ok()
+            .and_then(|map_tf| ray_on_map(cam, cam_tf, map_tf, end, *cursor.plane));
+        ground
+            .and_then(|ground| {
+                q_agents
+                    .iter()
+                    .map(|(ent, tf, agent)| {
+                        let distance = cursor.plane.flatten(tf.translation() - ground).length();
+                        (ent, distance, agent.settings.radius)
+                    })
+                    .filter(|(_, distance, radius)| distance <= radius)
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+            })
+            .map(|(ent, ..)| ent)
+            .into_iter()
+            .collect()
+    };
+
+    if !keys.pressed(input.additive_key) {
+        for ent in q_selected.iter() {
+            cmds.entity(ent).remove::<Selected>();
+        }
+    }
+
+    for ent in picked {
+        cmds.entity(ent).insert(Selected);
+    }
+}
+
+// sends the selection to the point on the map under the cursor
+fn order_selected(
+    mut cmds: Commands,
+    input: Res<FvoRtsInput>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    cursor: Cursor,
+    q_cam: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
+    q_map: Query<&GlobalTransform, With<MapBase>>,
+    q_selected: Query<Entity, (With<Selected>, With<FvoAgent>)>,
+) {
+    if !mouse.just_pressed(input.order_button) || q_selected.is_empty() || cursor.over_ui() {
+        return;
+    }
+
+    let Some(position) = cursor.position() else {
+        return;
+    };
+
+    let (Ok((cam, cam_tf)), Ok(map_tf)) = (q_cam.single(), q_map.single()) else {
+        return;
+    };
+
+    let Some(destination) = ray_on_map(cam, cam_tf, map_tf, position, *cursor.plane) else {
+        return;
+    };
+
+    cmds.trigger(MoveOrderEvent {
+        units: q_selected.iter().collect(),
+        destination,
+    });
+}
+
+// rings on the ground under every selected agent
+fn draw_selected(
+    mut gizmos: Gizmos,
+    input: Res<FvoRtsInput>,
+    plane: Res<Plane>,
+    q_selected: Query<(&GlobalTransform, &FvoAgent), With<Selected>>,
+) {
+    let rot = plane.rotation() * Quat::from_rotation_x(FRAC_PI_2);
+    for (tf, agent) in q_selected.iter() {
+        let iso = Isometry3d::new(tf.translation(), rot);
+        gizmos.circle(iso, agent.settings.radius * 1.2, input.color);
+    }
+}
+
+// where the ray through `cursor` meets the plane of the map, unlike `utils::get_world_pos` `None` when it misses
+fn ray_on_map(
+    cam: &Camera,
+    cam_tf: &GlobalTransform,
+    map_tf: &GlobalTransform,
+    cursor: Vec2,
+    plane: Plane,
+) -> Option<Vec3> {
+    let ray = cam.viewport_to_world(cam_tf, cursor).ok()?;
+    let plane = InfinitePlane3d::new(map_tf.rotation() * plane.up());
+    let distance = ray.intersect_plane(map_tf.translation(), plane)?;
+    Some(ray.get_point(distance))
+}
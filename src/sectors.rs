@@ -0,0 +1,259 @@
+use bevy::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::{grid::Grid, grid_direction::GridDirection};
+
+/// Sector-and-portal hierarchy over the [`Grid`], used to scale flowfields to large maps.
+///
+/// The grid is split into square sectors of [`FlowFieldConfig::sector_size`] cells. Every run of walkable cells along
+/// the border of two sectors becomes a portal, and portals of the same sector are linked by their path cost inside
+/// it. A coarse search over this graph picks the sectors a route passes through, and only those get a fine
+/// integration field. Rebuilt by the [`FlowfieldPlugin`] whenever the grid changes while
+/// [`FlowFieldConfig::hierarchical`] is set.
+///
+/// [`FlowFieldConfig::sector_size`]: crate::flowfield::FlowFieldConfig::sector_size
+/// [`FlowFieldConfig::hierarchical`]: crate::flowfield::FlowFieldConfig::hierarchical
+/// [`FlowfieldPlugin`]: crate::flowfield::FlowfieldPlugin
+#[derive(Resource, Debug, Default, Clone)]
+pub struct SectorGraph {
+    sector_size: i32,
+    grid_size: IVec2,
+    /// Portal cells, one node on each side of a border.
+    nodes: Vec<IVec2>,
+    /// Outgoing links of every node with their cost.
+    edges: Vec<Vec<(usize, u32)>>,
+    sector_nodes: HashMap<IVec2, Vec<usize>>,
+}
+
+impl SectorGraph {
+    pub fn build(grid: &Grid, sector_size: i32) -> Self {
+        let sector_size = sector_size.max(1);
+        let mut graph = Self {
+            sector_size,
+            grid_size: grid.size,
+            ..default()
+        };
+
+        let sectors = graph.sector_count();
+        for sy in 0..sectors.y {
+            for sx in 0..sectors.x {
+                let sector = IVec2::new(sx, sy);
+                if sx + 1 < sectors.x {
+                    graph.add_border_portals(grid, sector, IVec2::X);
+                }
+                if sy + 1 < sectors.y {
+                    graph.add_border_portals(grid, sector, IVec2::Y);
+                }
+            }
+        }
+
+        // link the portals of every sector by their cost through it
+        let sector_nodes: Vec<(IVec2, Vec<usize>)> = graph
+            .sector_nodes
+            .iter()
+            .map(|(sector, nodes)| (*sector, nodes.clone()))
+            .collect();
+        for (sector, nodes) in sector_nodes {
+            for &from in &nodes {
+                let costs = graph.sector_costs(grid, sector, graph.nodes[from]);
+                for &to in nodes.iter().filter(|&&to| to != from) {
+                    if let Some(&cost) = costs.get(&graph.nodes[to]) {
+                        graph.edges[from].push((to, cost));
+                    }
+                }
+            }
+        }
+
+        graph
+    }
+
+    pub fn sector_size(&self) -> i32 {
+        self.sector_size
+    }
+
+    pub fn grid_size(&self) -> IVec2 {
+        self.grid_size
+    }
+
+    /// Returns the sector containing the cell `idx`.
+    pub fn sector_of(&self, idx: IVec2) -> IVec2 {
+        idx.div_euclid(IVec2::splat(self.sector_size))
+    }
+
+    /// Returns the sectors a cheapest route from `start` to the closest of `goals` passes through, all cell indices.
+    /// `None` if no goal can be reached.
+    pub fn route(&self, grid: &Grid, start: IVec2, goals: &[IVec2]) -> Option<HashSet<IVec2>> {
+        let start_sector = self.sector_of(start);
+        let start_costs = self.sector_costs(grid, start_sector, start);
+
+        // a goal in the start sector reachable without leaving it needs no portals
+        let direct = goals
+            .iter()
+            .filter(|goal| self.sector_of(**goal) == start_sector)
+            .filter_map(|goal| start_costs.get(goal))
+            .min()
+            .copied();
+
+        // cost from every portal of a goal sector to its closest goal
+        let mut exit_costs: HashMap<usize, u32> = HashMap::new();
+        for &goal in goals {
+            let goal_costs = self.sector_costs(grid, self.sector_of(goal), goal);
+            for &node in self.nodes_of(self.sector_of(goal)) {
+                if let Some(&cost) = goal_costs.get(&self.nodes[node]) {
+                    let entry = exit_costs.entry(node).or_insert(u32::MAX);
+                    *entry = (*entry).min(cost);
+                }
+            }
+        }
+
+        // Dijkstra over the portals, seeded from the ones reachable inside the start sector
+        let mut dist: HashMap<usize, u32> = HashMap::new();
+        let mut prev: HashMap<usize, usize> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        for &node in self.nodes_of(start_sector) {
+            if let Some(&cost) = start_costs.get(&self.nodes[node]) {
+                dist.insert(node, cost);
+                heap.push(Reverse((cost, node)));
+            }
+        }
+
+        let mut best: Option<(u32, usize)> = None;
+        while let Some(Reverse((cost, node))) = heap.pop() {
+            if dist.get(&node).is_some_and(|&d| d < cost) {
+                continue;
+            }
+            if best.is_some_and(|(best_cost, _)| cost >= best_cost) {
+                break;
+            }
+
+            if let Some(&exit) = exit_costs.get(&node) {
+                let total = cost.saturating_add(exit);
+                if best.is_none_or(|(best_cost, _)| total < best_cost) {
+                    best = Some((total, node));
+                }
+            }
+
+            for &(next, edge_cost) in &self.edges[node] {
+                let next_cost = cost.saturating_add(edge_cost);
+                if dist.get(&next).is_none_or(|&d| next_cost < d) {
+                    dist.insert(next, next_cost);
+                    prev.insert(next, node);
+                    heap.push(Reverse((next_cost, next)));
+                }
+            }
+        }
+
+        let mut sectors = HashSet::from([start_sector]);
+        match (best, direct) {
+            (Some((cost, _)), Some(direct_cost)) if direct_cost <= cost => {}
+            (None, Some(_)) => {}
+            (Some((_, mut node)), _) => {
+                sectors.insert(self.sector_of(self.nodes[node]));
+                while let Some(&from) = prev.get(&node) {
+                    node = from;
+                    sectors.insert(self.sector_of(self.nodes[node]));
+                }
+            }
+            (None, None) => return None,
+        }
+
+        Some(sectors)
+    }
+
+    fn sector_count(&self) -> IVec2 {
+        (self.grid_size + IVec2::splat(self.sector_size - 1)) / self.sector_size
+    }
+
+    fn nodes_of(&self, sector: IVec2) -> &[usize] {
+        self.sector_nodes
+            .get(&sector)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    fn add_node(&mut self, idx: IVec2) -> usize {
+        let node = self.nodes.len();
+        self.nodes.push(idx);
+        self.edges.push(Vec::new());
+        self.sector_nodes
+            .entry(self.sector_of(idx))
+            .or_default()
+            .push(node);
+        node
+    }
+
+    // one portal per run of walkable cell pairs along the border between `sector` and its neighbor along `axis`
+    fn add_border_portals(&mut self, grid: &Grid, sector: IVec2, axis: IVec2) {
+        let along = IVec2::new(axis.y, axis.x);
+        let origin = sector * self.sector_size;
+        let last = origin + axis * (self.sector_size - 1);
+
+        let mut run: Vec<IVec2> = Vec::new();
+        for i in 0..=self.sector_size {
+            let a = last + along * i;
+            let open = i < self.sector_size
+                && self.in_grid(a)
+                && self.in_grid(a + axis)
+                && cost(grid, a) != u8::MAX
+                && cost(grid, a + axis) != u8::MAX;
+
+            if open {
+                run.push(a);
+                continue;
+            }
+
+            if let Some(&a) = run.get(run.len() / 2) {
+                let b = a + axis;
+                let node_a = self.add_node(a);
+                let node_b = self.add_node(b);
+                self.edges[node_a].push((node_b, cost(grid, b) as u32));
+                self.edges[node_b].push((node_a, cost(grid, a) as u32));
+            }
+            run.clear();
+        }
+    }
+
+    // path costs from `from` to every cell of `sector` reachable without leaving it
+    fn sector_costs(&self, grid: &Grid, sector: IVec2, from: IVec2) -> HashMap<IVec2, u32> {
+        let min = sector * self.sector_size;
+        let max = (min + IVec2::splat(self.sector_size)).min(self.grid_size);
+
+        let mut costs = HashMap::from([(from, 0)]);
+        let mut heap = BinaryHeap::from([Reverse((0u32, from.x, from.y))]);
+        while let Some(Reverse((cur_cost, x, y))) = heap.pop() {
+            let cur = IVec2::new(x, y);
+            if costs.get(&cur).is_some_and(|&c| c < cur_cost) {
+                continue;
+            }
+
+            for direction in GridDirection::cardinal_directions() {
+                let next = cur + direction.vector();
+                if next.cmplt(min).any() || next.cmpge(max).any() {
+                    continue;
+                }
+
+                let step = cost(grid, next);
+                if step == u8::MAX {
+                    continue;
+                }
+
+                let next_cost = cur_cost + step as u32;
+                if costs.get(&next).is_none_or(|&c| next_cost < c) {
+                    costs.insert(next, next_cost);
+                    heap.push(Reverse((next_cost, next.x, next.y)));
+                }
+            }
+        }
+
+        costs
+    }
+
+    fn in_grid(&self, idx: IVec2) -> bool {
+        idx.cmpge(IVec2::ZERO).all() && idx.cmplt(self.grid_size).all()
+    }
+}
+
+fn cost(grid: &Grid, idx: IVec2) -> u8 {
+    grid.grid[idx.y as usize][idx.x as usize].cost
+}
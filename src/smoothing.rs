@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+
+use crate::{components::Arrived, flowfield::FlowField, grid::Grid};
+
+/// Opt-in straight paths for agents that would otherwise zigzag along the 8 directions of the flowfield. The path to
+/// the goal is traced through the field of the agent's flowfield and straightened by string pulling (see
+/// [`FlowField::smoothed_path`]), then the agent heads for one waypoint after the other instead of following the
+/// flow. Avoidance still applies on top, and near the goal the flow and goal slots take over again.
+///
+/// Tracing costs more than sampling the field, add it to a handful of units like heroes or vehicles rather than to a
+/// whole army.
+///
+/// # Example
+///
+/// ```
+/// cmds.spawn((FvoAgent::default(), PathSmoothing::default()));
+/// ```
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct PathSmoothing {
+    /// Seconds between two traces, so the path follows agents pushed off it and fields that changed.
+    pub repath_interval: f32,
+    /// Distance on the XZ plane at which a waypoint counts as reached.
+    pub waypoint_radius: f32,
+    waypoints: Vec<Vec3>,
+    next: usize,
+    flowfield: Option<Entity>,
+    since_repath: f32,
+}
+
+impl Default for PathSmoothing {
+    fn default() -> Self {
+        Self::new(1.0, 2.0)
+    }
+}
+
+impl PathSmoothing {
+    pub fn new(repath_interval: f32, waypoint_radius: f32) -> Self {
+        Self {
+            repath_interval,
+            waypoint_radius,
+            waypoints: Vec::new(),
+            next: 0,
+            flowfield: None,
+            since_repath: 0.0,
+        }
+    }
+
+    /// The traced waypoints, including the ones already reached.
+    pub fn waypoints(&self) -> &[Vec3] {
+        &self.waypoints
+    }
+
+    /// The waypoint the agent heads for, `None` once the path is done.
+    pub fn waypoint(&self) -> Option<Vec3> {
+        self.waypoints.get(self.next).copied()
+    }
+
+    /// The flowfield the path was traced through.
+    pub fn flowfield(&self) -> Option<Entity> {
+        self.flowfield
+    }
+
+    /// Traces the path again with the next update instead of waiting for the `repath_interval`.
+    pub fn repath(&mut self) {
+        self.since_repath = f32::INFINITY;
+    }
+}
+
+// re-traces the paths that are due and advances past reached waypoints
+pub(crate) fn update_path_smoothing(
+    time: Res<Time>,
+    grid: Res<Grid>,
+    mut q_agents: Query<(Entity, &Transform, &mut PathSmoothing, Has<Arrived>)>,
+    q_ff: Query<(Entity, &FlowField)>,
+) {
+    for (unit, tf, mut path, arrived) in q_agents.iter_mut() {
+        let pos = tf.translation;
+        let ff = q_ff.iter().find(|(_, ff)| ff.units.contains(&unit));
+
+        let Some((ff_ent, ff)) = ff.filter(|_| !arrived) else {
+            if path.flowfield.is_some() {
+                *path = PathSmoothing::new(path.repath_interval, path.waypoint_radius);
+            }
+            continue;
+        };
+
+        path.since_repath += time.delta_secs();
+        if path.flowfield != Some(ff_ent) || path.since_repath >= path.repath_interval {
+            path.waypoints = ff.smoothed_path(pos, &grid);
+            path.next = 0;
+            path.flowfield = Some(ff_ent);
+            path.since_repath = 0.0;
+        }
+
+        let radius_sq = path.waypoint_radius * path.waypoint_radius;
+        while path.waypoint().is_some_and(|waypoint| {
+            grid.plane.flatten(waypoint - pos).length_squared() <= radius_sq
+        }) {
+            path.next += 1;
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use crate::grid::Grid;
+
+/// Uniform bucket grid over the XZ plane holding every [`FvoAgent`](crate::components::FvoAgent). Positions are in
+/// plane space, see [`Plane::to_plane`](crate::plane::Plane::to_plane).
+///
+/// Kept up to date incrementally from moved, added and removed agents by the
+/// [`FvoPlugin`](crate::fvo::FvoPlugin), and shared by the solver and the flowfield stop logic. Positions are the ones
+/// seen at the last update, see [`FvoConfig::rebuild_interval`](crate::fvo::FvoConfig::rebuild_interval). Agents off
+/// the grid are filed under the closest edge bucket, or left out depending on
+/// [`FvoConfig::out_of_bounds`](crate::fvo::FvoConfig::out_of_bounds).
+#[derive(Resource, Debug, Clone)]
+pub struct SpatialHash {
+    origin: Vec2,
+    bucket_size: Vec2,
+    /// Inclusive range of bucket keys covering the grid, `None` for an unbounded hash.
+    bounds: Option<IRect>,
+    buckets: HashMap<(i32, i32), Vec<Entity>>,
+    entries: HashMap<Entity, ((i32, i32), Vec3)>,
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        Self::new(Vec2::ZERO, Vec2::ONE)
+    }
+}
+
+impl SpatialHash {
+    pub fn new(origin: Vec2, bucket_size: Vec2) -> Self {
+        Self {
+            origin,
+            bucket_size: bucket_size.max(Vec2::splat(f32::EPSILON)),
+            bounds: None,
+            buckets: HashMap::default(),
+            entries: HashMap::default(),
+        }
+    }
+
+    /// Creates an empty hash of square buckets, `grid.buckets` of them along the longer side of the grid.
+    pub fn from_grid(grid: &Grid) -> Self {
+        let bucket_size = Vec2::splat(grid.world_size().max_element() / grid.buckets);
+
+        let origin = grid
+            .grid
+            .get(grid.grid.len() / 2)
+            .and_then(|row| row.get(row.len() / 2))
+            .map(|cell| grid.plane.to_2d(cell.world_pos))
+            .unwrap_or_default();
+
+        let mut hash = Self::new(origin, bucket_size);
+        // bounds of the possibly rotated grid
+        let half = grid.world_size() / 2.0;
+        let (min, max) = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .map(|(x, y)| {
+                let corner = grid.local_to_world(Vec3::new(half.x * x, 0.0, half.y * y));
+                grid.plane.to_plane(corner)
+            })
+            .into_iter()
+            .fold((Vec3::MAX, Vec3::MIN), |(min, max), p| {
+                (min.min(p), max.max(p))
+            });
+        let (min_x, min_y) = hash.key(min);
+        let (max_x, max_y) = hash.key(max - Vec3::splat(f32::EPSILON));
+        hash.bounds = Some(IRect::new(min_x, min_y, max_x, max_y));
+        hash
+    }
+
+    pub fn bucket_size(&self) -> Vec2 {
+        self.bucket_size
+    }
+
+    /// Returns the bucket key `pos` falls into, the closest bucket on the grid for positions off it.
+    pub fn key(&self, pos: Vec3) -> (i32, i32) {
+        let key = IVec2::new(
+            ((pos.x - self.origin.x) / self.bucket_size.x).floor() as i32,
+            ((pos.z - self.origin.y) / self.bucket_size.y).floor() as i32,
+        );
+        let key = match self.bounds {
+            Some(bounds) => key.clamp(bounds.min, bounds.max),
+            None => key,
+        };
+        (key.x, key.y)
+    }
+
+    /// Inserts `entity` or moves it to the bucket of `pos`.
+    pub fn insert(&mut self, entity: Entity, pos: Vec3) {
+        let key = self.key(pos);
+        if let Some((old_key, old_pos)) = self.entries.get_mut(&entity) {
+            *old_pos = pos;
+            if *old_key == key {
+                return;
+            }
+
+            let old_key = std::mem::replace(old_key, key);
+            self.remove_from_bucket(old_key, entity);
+        } else {
+            self.entries.insert(entity, (key, pos));
+        }
+
+        self.buckets.entry(key).or_default().push(entity);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some((key, _)) = self.entries.remove(&entity) {
+            self.remove_from_bucket(key, entity);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the position `entity` had when it was last inserted.
+    pub fn position(&self, entity: Entity) -> Option<Vec3> {
+        self.entries.get(&entity).map(|(_, pos)| *pos)
+    }
+
+    /// Returns every entity with the position it had when it was last inserted.
+    pub fn positions(&self) -> impl Iterator<Item = (Entity, Vec3)> + '_ {
+        self.entries
+            .iter()
+            .map(|(&entity, (_, pos))| (entity, *pos))
+    }
+
+    /// Returns the entities filed under `key`.
+    pub fn bucket(&self, key: (i32, i32)) -> &[Entity] {
+        self.buckets.get(&key).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Returns every entity whose stored position lies within `range` of `pos` on the XZ plane.
+    pub fn neighbors_within(&self, pos: Vec3, range: f32) -> impl Iterator<Item = Entity> + '_ {
+        let (bx, by) = self.key(pos);
+        let rx = (range / self.bucket_size.x).ceil() as i32;
+        let ry = (range / self.bucket_size.y).ceil() as i32;
+        let range_sq = range * range;
+        let center = Vec2::new(pos.x, pos.z);
+
+        (-rx..=rx)
+            .flat_map(move |dx| (-ry..=ry).map(move |dy| (bx + dx, by + dy)))
+            .flat_map(move |key| self.bucket(key).iter().copied())
+            .filter(move |entity| {
+                self.entries
+                    .get(entity)
+                    .is_some_and(|(_, p)| Vec2::new(p.x, p.z).distance_squared(center) <= range_sq)
+            })
+    }
+
+    fn remove_from_bucket(&mut self, key: (i32, i32), entity: Entity) {
+        if let Some(bucket) = self.buckets.get_mut(&key) {
+            bucket.retain(|&e| e != entity);
+            if bucket.is_empty() {
+                self.buckets.remove(&key);
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Shortcuts for spawning agents with everything they need, so a forgotten `Transform` or layer doesn't silently
+//! leave a unit standing around.
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{AgentState, AvoidanceLayers, FvoAgent, FvoSettings, NavLayer},
+    lod::FvoLod,
+    preferred::PreferredVelocity,
+};
+
+/// The components of a ready-to-move agent. Add a mesh, a [`Team`](crate::components::Team) or anything else of your
+/// own next to it.
+///
+/// # Example
+///
+/// ```
+/// cmds.spawn((
+///     FvoAgentBundle::new(pos, settings).with_nav_layer(NavLayer::Water),
+///     Mesh3d(boat_mesh),
+///     MeshMaterial3d(boat_material),
+/// ));
+/// ```
+#[derive(Bundle, Default)]
+pub struct FvoAgentBundle {
+    pub agent: FvoAgent,
+    pub transform: Transform,
+    pub state: AgentState,
+    pub layers: AvoidanceLayers,
+    pub nav_layer: NavLayer,
+    pub preferred: PreferredVelocity,
+    pub lod: FvoLod,
+}
+
+impl FvoAgentBundle {
+    /// A planar agent at `position`.
+    pub fn new(position: Vec3, settings: FvoSettings) -> Self {
+        Self {
+            agent: FvoAgent::new(settings),
+            transform: Transform::from_translation(position),
+            ..default()
+        }
+    }
+
+    /// A flying agent at `position` that avoids others in full 3D.
+    pub fn volumetric(position: Vec3, settings: FvoSettings) -> Self {
+        Self {
+            agent: FvoAgent::volumetric(settings),
+            ..Self::new(position, settings)
+        }
+    }
+
+    pub fn with_layers(mut self, layers: AvoidanceLayers) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn with_nav_layer(mut self, nav_layer: NavLayer) -> Self {
+        self.nav_layer = nav_layer;
+        self
+    }
+}
+
+/// Spawns agents straight from [`Commands`].
+pub trait FvoCommandsExt {
+    /// Spawns a planar [`FvoAgentBundle`] at `position`, insert the rest on the returned entity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// cmds.spawn_fvo_agent(pos, settings)
+    ///     .insert((Mesh3d(mesh.clone()), MeshMaterial3d(material.clone()), Name::new("Unit")));
+    /// ```
+    fn spawn_fvo_agent(&mut self, position: Vec3, settings: FvoSettings) -> EntityCommands<'_>;
+}
+
+impl FvoCommandsExt for Commands<'_, '_> {
+    fn spawn_fvo_agent(&mut self, position: Vec3, settings: FvoSettings) -> EntityCommands<'_> {
+        self.spawn(FvoAgentBundle::new(position, settings))
+    }
+}
+
+/// Turns an existing entity, e.g. a unit spawned from a scene, into an agent.
+pub trait FvoEntityCommandsExt {
+    /// Inserts a planar [`FvoAgent`] with everything it needs. Components the entity already has, like its
+    /// `Transform` or [`AvoidanceLayers`], are kept.
+    fn insert_fvo_agent(&mut self, settings: FvoSettings) -> &mut Self;
+}
+
+impl FvoEntityCommandsExt for EntityCommands<'_> {
+    fn insert_fvo_agent(&mut self, settings: FvoSettings) -> &mut Self {
+        self.insert(FvoAgent::new(settings)).insert_if_new((
+            Transform::default(),
+            AvoidanceLayers::default(),
+            NavLayer::default(),
+        ))
+    }
+}
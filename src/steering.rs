@@ -0,0 +1,276 @@
+//! Classic steering behaviors for agents without a flowfield, or on top of one.
+//!
+//! Every behavior of a [`Steering`] outputs a desired velocity, their weighted sum becomes the preferred velocity of
+//! the agent and goes through the same avoidance solve as the flow. An agent that also follows flowfields blends
+//! [`Steering::velocity`] with them by [`Steering::weight`], like another flowfield membership.
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{ArrivalSettings, FvoAgent},
+    fvo::{solver_dt, FvoConfig},
+    plane::Plane,
+    preferred::PreferredVelocityProvider,
+};
+
+/// What a behavior steers relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub enum SteeringTarget {
+    Position(Vec3),
+    /// The translation of the entity, e.g. another unit. Behaviors toward a despawned entity output nothing.
+    Entity(Entity),
+}
+
+impl From<Vec3> for SteeringTarget {
+    fn from(position: Vec3) -> Self {
+        SteeringTarget::Position(position)
+    }
+}
+
+impl From<Entity> for SteeringTarget {
+    fn from(entity: Entity) -> Self {
+        SteeringTarget::Entity(entity)
+    }
+}
+
+/// A single behavior, see [`Steering`].
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub enum SteeringBehavior {
+    /// Heads for the target at full speed, overshooting it.
+    Seek(SteeringTarget),
+    /// Moves away from the target at full speed while it is within `radius`.
+    Flee { target: SteeringTarget, radius: f32 },
+    /// Heads for the target and slows down to stop on it.
+    Arrive {
+        target: SteeringTarget,
+        arrival: ArrivalSettings,
+    },
+    /// Strolls around aimlessly: a point on a circle of `radius`, `distance` ahead of the agent, jitters by up to
+    /// `jitter` radians per second and the agent heads for it.
+    Wander {
+        radius: f32,
+        distance: f32,
+        jitter: f32,
+        /// Where on the circle the point currently is, in radians.
+        angle: f32,
+    },
+    /// Visits the waypoints in order, arriving at the last one unless `looping` starts over at the first.
+    FollowPath {
+        waypoints: Vec<Vec3>,
+        /// Distance on the XZ plane at which a waypoint counts as reached.
+        waypoint_radius: f32,
+        looping: bool,
+        /// Index of the waypoint the agent heads for.
+        next: usize,
+    },
+}
+
+impl SteeringBehavior {
+    pub fn seek(target: impl Into<SteeringTarget>) -> Self {
+        SteeringBehavior::Seek(target.into())
+    }
+
+    pub fn flee(target: impl Into<SteeringTarget>, radius: f32) -> Self {
+        SteeringBehavior::Flee {
+            target: target.into(),
+            radius,
+        }
+    }
+
+    pub fn arrive(target: impl Into<SteeringTarget>, arrival: ArrivalSettings) -> Self {
+        SteeringBehavior::Arrive {
+            target: target.into(),
+            arrival,
+        }
+    }
+
+    pub fn wander(radius: f32, distance: f32, jitter: f32) -> Self {
+        SteeringBehavior::Wander {
+            radius,
+            distance,
+            jitter,
+            angle: 0.0,
+        }
+    }
+
+    pub fn follow_path(waypoints: Vec<Vec3>, waypoint_radius: f32, looping: bool) -> Self {
+        SteeringBehavior::FollowPath {
+            waypoints,
+            waypoint_radius,
+            looping,
+            next: 0,
+        }
+    }
+}
+
+/// Weighted steering behaviors of an agent. Their output is summed by weight and capped at
+/// [`FvoSettings::preferred_speed`](crate::components::FvoSettings::preferred_speed).
+///
+/// # Example
+///
+/// ```
+/// // patrol the outpost but keep away from the dragon
+/// cmds.spawn((
+///     FvoAgent::default(),
+///     Steering::new()
+///         .with(SteeringBehavior::follow_path(patrol_route, 2.0, true), 1.0)
+///         .with(SteeringBehavior::flee(dragon, 30.0), 3.0),
+/// ));
+/// ```
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Steering {
+    pub behaviors: Vec<(SteeringBehavior, f32)>,
+    /// Weight of the steering output against the agent's flowfield memberships, see
+    /// [`FlowField::set_unit_weight`](crate::flowfield::FlowField::set_unit_weight).
+    pub weight: f32,
+    /// Preferred velocity of the last update, provided to the solver.
+    pub velocity: Vec3,
+}
+
+impl Default for Steering {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Steering {
+    pub fn new() -> Self {
+        Self {
+            behaviors: Vec::new(),
+            weight: 1.0,
+            velocity: Vec3::ZERO,
+        }
+    }
+
+    pub fn with(mut self, behavior: SteeringBehavior, weight: f32) -> Self {
+        self.behaviors.push((behavior, weight));
+        self
+    }
+
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+impl PreferredVelocityProvider for Steering {
+    fn preferred_velocity(&mut self, _: Entity, _: &Transform, _: &FvoAgent) -> Option<Vec3> {
+        Some(self.velocity)
+    }
+
+    fn weight(&self) -> f32 {
+        self.weight
+    }
+}
+
+// runs the behaviors of every agent into `Steering::velocity`
+pub(crate) fn update_steering(
+    time: Res<Time>,
+    fixed_time: Res<Time<Fixed>>,
+    config: Res<FvoConfig>,
+    plane: Res<Plane>,
+    mut q_agents: Query<(Entity, &Transform, &FvoAgent, &mut Steering)>,
+    q_targets: Query<&Transform>,
+) {
+    let dt = solver_dt(&config, &time, &fixed_time);
+    let elapsed = time.elapsed().as_nanos() as u64;
+
+    for (ent, tf, agent, mut steering) in q_agents.iter_mut() {
+        let pos = tf.translation;
+        let speed = agent.settings.preferred_speed;
+        let heading = plane
+            .flatten(agent.heading)
+            .normalize_or(plane.forward(Quat::IDENTITY));
+        let position_of = |target: SteeringTarget| match target {
+            SteeringTarget::Position(position) => Some(position),
+            SteeringTarget::Entity(target) => q_targets.get(target).ok().map(|tf| tf.translation),
+        };
+        let toward = |to: Vec3| plane.flatten(to - pos);
+
+        let steering = &mut *steering;
+        let mut velocity = Vec3::ZERO;
+        for (i, (behavior, weight)) in steering.behaviors.iter_mut().enumerate() {
+            let desired = match behavior {
+                SteeringBehavior::Seek(target) => position_of(*target)
+                    .map(|to| toward(to).normalize_or_zero() * speed)
+                    .unwrap_or_default(),
+                SteeringBehavior::Flee { target, radius } => position_of(*target)
+                    .map(toward)
+                    .filter(|offset| offset.length_squared() < *radius * *radius)
+                    .map(|offset| -offset.normalize_or_zero() * speed)
+                    .unwrap_or_default(),
+                SteeringBehavior::Arrive { target, arrival } => position_of(*target)
+                    .map(|to| {
+                        let offset = toward(to);
+                        offset.normalize_or_zero()
+                            * speed
+                            * arrival.speed_scale(offset.length(), agent.settings.sensor_range)
+                    })
+                    .unwrap_or_default(),
+                SteeringBehavior::Wander {
+                    radius,
+                    distance,
+                    jitter,
+                    angle,
+                } => {
+                    let seed = ent.to_bits() ^ elapsed ^ (i as u64).rotate_left(32);
+                    *angle += (unit_noise(seed) * 2.0 - 1.0) * *jitter * dt;
+
+                    // the circle turns with the agent
+                    let (sin, cos) = angle.sin_cos();
+                    let right = heading.cross(plane.up());
+                    let point = heading * *distance + (heading * cos + right * sin) * *radius;
+                    point.normalize_or_zero() * speed
+                }
+                SteeringBehavior::FollowPath {
+                    waypoints,
+                    waypoint_radius,
+                    looping,
+                    next,
+                } => {
+                    let radius_sq = *waypoint_radius * *waypoint_radius;
+                    let reached = |next: usize| {
+                        waypoints
+                            .get(next)
+                            .is_some_and(|&to| toward(to).length_squared() <= radius_sq)
+                    };
+                    // skip every waypoint already reached, at most one lap
+                    for _ in 0..waypoints.len() {
+                        if !reached(*next) || (!*looping && *next + 1 >= waypoints.len()) {
+                            break;
+                        }
+                        *next = (*next + 1) % waypoints.len();
+                    }
+
+                    match waypoints.get(*next) {
+                        // stop on the last waypoint like any other goal
+                        Some(&to) if !*looping && *next + 1 == waypoints.len() => {
+                            let offset = toward(to);
+                            let scale = agent
+                                .settings
+                                .arrival
+                                .speed_scale(offset.length(), agent.settings.sensor_range);
+                            offset.normalize_or_zero() * speed * scale
+                        }
+                        Some(&to) => toward(to).normalize_or_zero() * speed,
+                        None => Vec3::ZERO,
+                    }
+                }
+            };
+
+            velocity += desired * *weight;
+        }
+
+        steering.velocity = velocity.clamp_length_max(speed);
+    }
+}
+
+// hash of `seed` mapped to `[0, 1)`
+fn unit_noise(seed: u64) -> f32 {
+    let mut x = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+    (x >> 40) as f32 / (1u64 << 24) as f32
+}
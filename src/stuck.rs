@@ -0,0 +1,175 @@
+//! Notices agents that barely make progress although they want to move, e.g. wedged in a crowd or caught on a corner,
+//! and helps them out.
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{Arrived, FvoAgent},
+    events::AgentStuckEvent,
+    fvo::{solver_dt, FvoConfig},
+    plane::Plane,
+    preferred::PreferredVelocity,
+    smoothing::PathSmoothing,
+};
+
+/// What a [`StuckDetection`] does once its agent got stuck, on top of triggering an [`AgentStuckEvent`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Reflect)]
+pub enum StuckRecovery {
+    /// Only trigger the event.
+    #[default]
+    None,
+    /// Sidestep for `duration` seconds: a velocity of `strength` times the preferred speed is added across the
+    /// preferred velocity. The side alternates every time the agent gets stuck, so it tries both ways around.
+    LateralBias { strength: f32, duration: f32 },
+    /// Trace the path of the agent's [`PathSmoothing`] again right away. Agents without it only trigger the event.
+    Repath,
+}
+
+/// Opt-in stuck detection. Every `window` seconds the distance the agent covered is compared to the distance its
+/// preferred velocity asked for, and if it made less than `min_progress` of it, it counts as stuck: an
+/// [`AgentStuckEvent`] is triggered and the `recovery` applied.
+///
+/// # Example
+///
+/// ```
+/// cmds.spawn((
+///     FvoAgent::default(),
+///     StuckDetection::default().with_recovery(StuckRecovery::LateralBias {
+///         strength: 0.5,
+///         duration: 1.0,
+///     }),
+/// ));
+/// ```
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct StuckDetection {
+    /// Seconds over which the progress is measured.
+    pub window: f32,
+    /// Share of the expected distance an agent has to cover per window, in `0.0..=1.0`.
+    pub min_progress: f32,
+    pub recovery: StuckRecovery,
+    start: Option<Vec3>,
+    elapsed: f32,
+    expected: f32,
+    side: f32,
+    bias_left: f32,
+}
+
+impl Default for StuckDetection {
+    fn default() -> Self {
+        Self::new(2.0, 0.25)
+    }
+}
+
+impl StuckDetection {
+    pub fn new(window: f32, min_progress: f32) -> Self {
+        Self {
+            window,
+            min_progress,
+            recovery: StuckRecovery::None,
+            start: None,
+            elapsed: 0.0,
+            expected: 0.0,
+            side: 1.0,
+            bias_left: 0.0,
+        }
+    }
+
+    pub fn with_recovery(mut self, recovery: StuckRecovery) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// Whether a [`StuckRecovery::LateralBias`] is currently pushing the agent sideways.
+    pub fn is_recovering(&self) -> bool {
+        self.bias_left > 0.0
+    }
+}
+
+type WatchedAgent = (
+    Entity,
+    &'static Transform,
+    &'static PreferredVelocity,
+    &'static mut StuckDetection,
+    Option<&'static mut PathSmoothing>,
+    Has<Arrived>,
+);
+
+// measures the progress of every agent after it moved and starts the recovery of stuck ones
+pub(crate) fn update_stuck_detection(
+    mut cmds: Commands,
+    time: Res<Time>,
+    fixed_time: Res<Time<Fixed>>,
+    config: Res<FvoConfig>,
+    plane: Res<Plane>,
+    mut q_agents: Query<WatchedAgent>,
+) {
+    let dt = solver_dt(&config, &time, &fixed_time);
+
+    for (ent, tf, preferred, mut detection, path, arrived) in q_agents.iter_mut() {
+        let pos = tf.translation;
+        detection.bias_left = (detection.bias_left - dt).max(0.0);
+
+        let Some(start) = detection.start.filter(|_| !arrived) else {
+            detection.start = Some(pos);
+            detection.elapsed = 0.0;
+            detection.expected = 0.0;
+            continue;
+        };
+
+        detection.elapsed += dt;
+        detection.expected += preferred.get().map_or(0.0, Vec3::length) * dt;
+        if detection.elapsed < detection.window {
+            continue;
+        }
+
+        let displacement = plane.flatten(pos - start).length();
+        let expected = detection.expected;
+        detection.start = Some(pos);
+        detection.elapsed = 0.0;
+        detection.expected = 0.0;
+
+        if expected <= f32::EPSILON || displacement >= expected * detection.min_progress {
+            continue;
+        }
+
+        cmds.trigger(AgentStuckEvent {
+            agent: ent,
+            displacement,
+            expected,
+        });
+
+        match detection.recovery {
+            StuckRecovery::None => {}
+            StuckRecovery::LateralBias { duration, .. } => {
+                detection.bias_left = duration;
+                detection.side = -detection.side;
+            }
+            StuckRecovery::Repath => {
+                if let Some(mut path) = path {
+                    path.repath();
+                }
+            }
+        }
+    }
+}
+
+// sidesteps recovering agents on top of what every other provider wants
+pub(crate) fn stuck_preferred_velocity(
+    plane: Res<Plane>,
+    mut q_agents: Query<(&FvoAgent, &StuckDetection, &mut PreferredVelocity)>,
+) {
+    for (agent, detection, mut preferred) in q_agents.iter_mut() {
+        let StuckRecovery::LateralBias { strength, .. } = detection.recovery else {
+            continue;
+        };
+        let Some(velocity) = preferred.get().filter(|_| detection.is_recovering()) else {
+            continue;
+        };
+
+        let forward = plane.flatten(velocity).normalize_or_zero();
+        let right = forward.cross(plane.up());
+        let bias = right * detection.side * strength * agent.settings.preferred_speed;
+        preferred.set(velocity + bias);
+    }
+}
@@ -0,0 +1,151 @@
+//! Builds the [`Grid`] from a `bevy_ecs_tilemap` layer. Enabled with the `tilemap` feature and wired up by the
+//! [`GridPlugin`](crate::grid::GridPlugin).
+//!
+//! Add [`TilemapCosts`] to the tilemap entity of the layer that describes the terrain. The grid is rebuilt with one
+//! cell per tile, sized by [`TilemapTileSize`], whenever the costs or the tile storage change, and tiles whose
+//! [`TileTextureIndex`] changes later update their cell in place. Tile `(x, y)` becomes cell `(x, y)`, and the grid
+//! stays centered on the world origin as usual.
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use std::collections::HashMap;
+
+use crate::{
+    events::CostFieldChangedEv,
+    grid::{install_grid, Grid},
+};
+
+/// Maps the tile texture indices of a tilemap layer to cell costs. Only one layer should carry it.
+///
+/// # Example
+///
+/// ```
+/// cmds.entity(tilemap).insert(
+///     TilemapCosts::default()
+///         .with_cost(WATER, u8::MAX)
+///         .with_cost(MUD, 5),
+/// );
+/// ```
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct TilemapCosts {
+    /// Cost of each tile texture index, `u8::MAX` for impassable.
+    pub costs: HashMap<u32, u8>,
+    /// Cost of tiles missing from `costs` and of empty tile positions.
+    pub default_cost: u8,
+    /// The number of buckets for spatial partitioning, see [`Grid::buckets`].
+    pub buckets: f32,
+}
+
+impl Default for TilemapCosts {
+    fn default() -> Self {
+        Self {
+            costs: HashMap::new(),
+            default_cost: 1,
+            buckets: 10.0,
+        }
+    }
+}
+
+impl TilemapCosts {
+    pub fn with_cost(mut self, texture_index: u32, cost: u8) -> Self {
+        self.costs.insert(texture_index, cost);
+        self
+    }
+
+    pub fn with_default_cost(mut self, cost: u8) -> Self {
+        self.default_cost = cost;
+        self
+    }
+
+    /// Returns the cost of a tile with `texture_index`.
+    pub fn cost(&self, texture_index: u32) -> u8 {
+        self.costs
+            .get(&texture_index)
+            .copied()
+            .unwrap_or(self.default_cost)
+    }
+}
+
+/// Rebuilds the [`Grid`] from the layer with [`TilemapCosts`] when it is added or its tiles are replaced.
+pub fn build_grid_from_tilemap(
+    mut cmds: Commands,
+    q_layers: Query<
+        (&TilemapCosts, &TileStorage, &TilemapSize, &TilemapTileSize),
+        Or<(Changed<TilemapCosts>, Changed<TileStorage>)>,
+    >,
+    q_tiles: Query<&TileTextureIndex>,
+    grid: Option<ResMut<Grid>>,
+) {
+    let Some((costs, storage, size, tile_size)) = q_layers.iter().next() else {
+        return;
+    };
+
+    let size = IVec2::new(size.x as i32, size.y as i32);
+    let cell_size = Vec2::new(tile_size.x, tile_size.y);
+    let mut built = Grid::with_cell_size(costs.buckets, size, cell_size);
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let cost = storage
+                .get(&TilePos::new(x as u32, y as u32))
+                .and_then(|tile| q_tiles.get(tile).ok())
+                .map_or(costs.default_cost, |texture| costs.cost(texture.0));
+            built.grid[y as usize][x as usize].cost = cost;
+        }
+    }
+
+    install_grid(&mut cmds, grid, built);
+}
+
+/// Updates the cells of tiles whose texture changed and triggers a [`CostFieldChangedEv`] for them.
+pub fn sync_tilemap_costs(
+    mut cmds: Commands,
+    grid: Option<ResMut<Grid>>,
+    q_layers: Query<(Ref<TilemapCosts>, Ref<TileStorage>)>,
+    q_tiles: Query<(&TilePos, &TileTextureIndex, &TilemapId), Changed<TileTextureIndex>>,
+) {
+    let Some(mut grid) = grid else {
+        return;
+    };
+
+    let mut region: Option<IRect> = None;
+    for (pos, texture, tilemap) in q_tiles.iter() {
+        let Ok((costs, storage)) = q_layers.get(tilemap.0) else {
+            continue;
+        };
+
+        // a rebuilt layer already read every tile
+        if costs.is_changed() || storage.is_changed() {
+            continue;
+        }
+
+        let idx = IVec2::new(pos.x as i32, pos.y as i32);
+        if idx.cmpge(grid.size).any() {
+            continue;
+        }
+
+        // obstacles keep their cells blocked
+        if grid
+            .occupied_cells
+            .values()
+            .any(|cells| cells.contains(&idx))
+        {
+            continue;
+        }
+
+        let cost = costs.cost(texture.0);
+        if grid.grid[idx.y as usize][idx.x as usize].cost == cost {
+            continue;
+        }
+
+        grid.set_cost(idx, cost);
+        region = Some(match region {
+            Some(region) => region.union_point(idx),
+            None => IRect::from_corners(idx, idx),
+        });
+    }
+
+    if let Some(region) = region {
+        cmds.trigger(CostFieldChangedEv { region });
+    }
+}
@@ -0,0 +1,220 @@
+//! Records the trajectories of every agent and plays them back, e.g. to reproduce a jam at a gate tick by tick.
+//!
+//! Recording starts once a [`TrajectoryRecorder`] is inserted and is run by the [`FvoPlugin`](crate::fvo::FvoPlugin)
+//! right after [`FvoSystemSet::ApplySteering`](crate::fvo::FvoSystemSet::ApplySteering), so every sample holds the
+//! moved position and the velocity that moved it.
+
+#[cfg(feature = "render")]
+use bevy::color::palettes::css::AQUA;
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::components::FvoAgent;
+
+/// State of a single agent in a [`TrajectoryFrame`].
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrajectorySample {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub radius: f32,
+}
+
+/// Every agent at one tick of the solver schedule.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrajectoryFrame {
+    /// Number of ticks recorded before this one, counting the frames already dropped from the buffer.
+    pub tick: u64,
+    pub agents: Vec<TrajectorySample>,
+}
+
+/// How a recording is played back, see [`TrajectoryRecorder::start_replay`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum ReplayMode {
+    /// Draws every recorded agent as a gizmo sphere with its velocity, next to the live simulation. Shows nothing
+    /// without the `render` feature.
+    #[default]
+    Ghosts,
+    /// Writes the recorded position and velocity back into the agents that still exist. The solver keeps running,
+    /// the recording overrides its result every tick.
+    Transforms,
+}
+
+/// Ring buffer of the last `capacity` ticks of every [`FvoAgent`]. Insert it to start recording.
+///
+/// # Example
+///
+/// ```
+/// // keep the last 10 seconds at 60 ticks per second
+/// app.insert_resource(TrajectoryRecorder::new(600));
+///
+/// fn replay_on_f9(input: Res<ButtonInput<KeyCode>>, mut recorder: ResMut<TrajectoryRecorder>) {
+///     if input.just_pressed(KeyCode::F9) {
+///         recorder.start_replay(ReplayMode::Ghosts, true);
+///     }
+/// }
+/// ```
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct TrajectoryRecorder {
+    /// Ticks kept, older ones are dropped first.
+    pub capacity: usize,
+    /// Pauses the recording without clearing it.
+    pub recording: bool,
+    frames: VecDeque<TrajectoryFrame>,
+    next_tick: u64,
+    replay: Option<Replay>,
+}
+
+#[derive(Debug, Clone, Copy, Reflect)]
+struct Replay {
+    mode: ReplayMode,
+    frame: usize,
+    looping: bool,
+}
+
+impl Default for TrajectoryRecorder {
+    fn default() -> Self {
+        Self::new(600)
+    }
+}
+
+impl TrajectoryRecorder {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            recording: true,
+            frames: VecDeque::new(),
+            next_tick: 0,
+            replay: None,
+        }
+    }
+
+    /// Recorded frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &TrajectoryFrame> {
+        self.frames.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Plays the recording back from its oldest frame, one frame per tick, recording pauses meanwhile. `looping` starts
+    /// over after the last frame instead of stopping, until [`TrajectoryRecorder::stop_replay`].
+    pub fn start_replay(&mut self, mode: ReplayMode, looping: bool) {
+        self.replay = Some(Replay {
+            mode,
+            frame: 0,
+            looping,
+        });
+    }
+
+    /// Ends the replay and resumes recording, appending to the frames recorded before.
+    pub fn stop_replay(&mut self) {
+        self.replay = None;
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// The frame shown by the running replay.
+    pub fn replay_frame(&self) -> Option<&TrajectoryFrame> {
+        self.replay.and_then(|replay| self.frames.get(replay.frame))
+    }
+
+    fn push(&mut self, agents: Vec<TrajectorySample>) {
+        self.frames.push_back(TrajectoryFrame {
+            tick: self.next_tick,
+            agents,
+        });
+        self.next_tick += 1;
+
+        while self.frames.len() > self.capacity {
+            self.frames.pop_front();
+        }
+    }
+}
+
+/// Appends the current state of every agent to the [`TrajectoryRecorder`], unless it is paused or replaying.
+pub fn record_trajectories(
+    mut recorder: ResMut<TrajectoryRecorder>,
+    q_agents: Query<(Entity, &Transform, &FvoAgent)>,
+) {
+    if !recorder.recording || recorder.is_replaying() {
+        return;
+    }
+
+    let mut agents: Vec<TrajectorySample> = q_agents
+        .iter()
+        .map(|(entity, tf, agent)| TrajectorySample {
+            entity,
+            position: tf.translation,
+            velocity: agent.velocity,
+            radius: agent.settings.radius,
+        })
+        .collect();
+    // stable order, so two recordings of a deterministic run compare equal
+    agents.sort_unstable_by_key(|sample| sample.entity);
+
+    recorder.push(agents);
+}
+
+/// Shows the current replay frame and advances it, see [`ReplayMode`].
+pub fn replay_trajectories(
+    mut recorder: ResMut<TrajectoryRecorder>,
+    mut q_agents: Query<(&mut Transform, &mut FvoAgent)>,
+    #[cfg(feature = "render")] mut gizmos: Gizmos,
+) {
+    let Some(replay) = recorder.replay else {
+        return;
+    };
+
+    let Some(frame) = recorder.frames.get(replay.frame) else {
+        recorder.replay = None;
+        return;
+    };
+
+    for sample in &frame.agents {
+        match replay.mode {
+            #[cfg(feature = "render")]
+            ReplayMode::Ghosts => {
+                let iso = Isometry3d::from_translation(sample.position);
+                gizmos.sphere(iso, sample.radius, AQUA);
+                gizmos.arrow(
+                    sample.position,
+                    sample.position + sample.velocity * 0.1,
+                    AQUA,
+                );
+            }
+            #[cfg(not(feature = "render"))]
+            ReplayMode::Ghosts => {}
+            ReplayMode::Transforms => {
+                if let Ok((mut tf, mut agent)) = q_agents.get_mut(sample.entity) {
+                    tf.translation = sample.position;
+                    agent.velocity = sample.velocity;
+                }
+            }
+        }
+    }
+
+    let next = replay.frame + 1;
+    recorder.replay = match (next < recorder.frames.len(), replay.looping) {
+        (true, _) => Some(Replay {
+            frame: next,
+            ..replay
+        }),
+        (false, true) => Some(Replay { frame: 0, ..replay }),
+        (false, false) => None,
+    };
+}
@@ -0,0 +1,173 @@
+//! Solver and debug tuning values read from a RON file, so tuning sessions don't need a recompile. The file is applied
+//! once it is loaded and again on every hot reload (Bevy's `file_watcher` feature).
+//!
+//! ```ron
+//! (
+//!     settings: (
+//!         preferred_speed: 40.0,
+//!         max_speed: 55.0,
+//!         radius: 2.0,
+//!         arrival: (slow_radius: Some(12.0), stop_radius: 1.5, curve: SmoothStep),
+//!     ),
+//!     debug: Some((
+//!         draw_radius: true,
+//!         draw_velocities: true,
+//!         draw_mode_1: Heatmap,
+//!     )),
+//! )
+//! ```
+//!
+//! Fields left out keep their defaults, `debug` is ignored without the `debug` feature.
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    prelude::*,
+};
+use std::fmt;
+
+#[cfg(feature = "debug")]
+use crate::debug::resources::DbgOptions;
+use crate::{
+    components::{FvoAgent, FvoSettings, FvoUpdaterSync},
+    events::FvoTuningAppliedEv,
+};
+
+/// Registers the [`FvoTuning`] asset and applies the file of the [`FvoTuningFile`] resource. Needs Bevy's
+/// `AssetPlugin`.
+///
+/// # Example
+///
+/// ```
+/// app.add_plugins((BevyPathfindingPlugin, FvoTuningPlugin))
+///     .add_systems(Startup, |mut cmds: Commands, assets: Res<AssetServer>| {
+///         cmds.insert_resource(FvoTuningFile::new(assets.load("fvo_config.ron")));
+///     });
+/// ```
+pub struct FvoTuningPlugin;
+
+impl Plugin for FvoTuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<FvoTuning>()
+            .init_asset_loader::<FvoTuningLoader>()
+            .add_systems(
+                Update,
+                apply_fvo_tuning.run_if(resource_exists::<FvoTuningFile>),
+            );
+    }
+}
+
+/// Contents of a tuning file, see the [module docs](self).
+#[derive(Asset, TypePath, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct FvoTuning {
+    /// Written to every agent with [`FvoUpdaterSync`] and kept in [`FvoTuningFile::settings`] for agents spawned
+    /// later.
+    pub settings: FvoSettings,
+    /// Replaces the [`DbgOptions`] flags and draw modes, `None` leaves them alone.
+    #[cfg(feature = "debug")]
+    pub debug: Option<DbgOptions>,
+}
+
+/// The tuning file to apply. Insert it with the handle of a `.ron` file to start following the file.
+#[derive(Resource, Debug, Clone)]
+pub struct FvoTuningFile {
+    pub handle: Handle<FvoTuning>,
+    /// Settings of the last applied file, [`FvoSettings::default`] until it is loaded. Spawn agents with them to
+    /// pick up the tuned values.
+    pub settings: FvoSettings,
+}
+
+impl FvoTuningFile {
+    pub fn new(handle: Handle<FvoTuning>) -> Self {
+        Self {
+            handle,
+            settings: FvoSettings::default(),
+        }
+    }
+}
+
+/// Reads [`FvoTuning`] from RON.
+#[derive(Default, TypePath)]
+pub struct FvoTuningLoader;
+
+impl AssetLoader for FvoTuningLoader {
+    type Asset = FvoTuning;
+    type Settings = ();
+    type Error = FvoTuningLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<FvoTuning, FvoTuningLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+#[derive(Debug)]
+pub enum FvoTuningLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for FvoTuningLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FvoTuningLoaderError::Io(err) => write!(f, "failed to read the tuning file: {err}"),
+            FvoTuningLoaderError::Ron(err) => write!(f, "failed to parse the tuning file: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for FvoTuningLoaderError {}
+
+impl From<std::io::Error> for FvoTuningLoaderError {
+    fn from(err: std::io::Error) -> Self {
+        FvoTuningLoaderError::Io(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for FvoTuningLoaderError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        FvoTuningLoaderError::Ron(err)
+    }
+}
+
+fn apply_fvo_tuning(
+    mut cmds: Commands,
+    mut tuning_file: ResMut<FvoTuningFile>,
+    tunings: Res<Assets<FvoTuning>>,
+    mut asset_events: MessageReader<AssetEvent<FvoTuning>>,
+    mut q_agents: Query<&mut FvoAgent, With<FvoUpdaterSync>>,
+) {
+    let id = tuning_file.handle.id();
+    let loaded = asset_events.read().any(|ev| match ev {
+        AssetEvent::LoadedWithDependencies { id: loaded } | AssetEvent::Modified { id: loaded } => {
+            *loaded == id
+        }
+        _ => false,
+    });
+    // a new handle was inserted that may already be loaded
+    if !loaded && !tuning_file.is_changed() {
+        return;
+    }
+
+    let Some(tuning) = tunings.get(id) else {
+        return;
+    };
+
+    // keeps the write from counting as a newly inserted file
+    tuning_file.bypass_change_detection().settings = tuning.settings;
+    for mut agent in q_agents.iter_mut() {
+        agent.settings = tuning.settings;
+    }
+
+    cmds.trigger(FvoTuningAppliedEv(id));
+}
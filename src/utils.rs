@@ -36,7 +36,7 @@ pub fn to_viewport_coords(
 pub fn get_cell_from_world_position_helper(
     position: Vec3,
     grid_size: IVec2,
-    cell_diameter: f32,
+    cell_size: Vec2,
     grid: &Vec<Vec<Cell>>,
     offset: Option<Vec2>,
 ) -> Cell {
@@ -46,8 +46,8 @@ pub fn get_cell_from_world_position_helper(
         x = ((grid_size.x as f32) * offset.x).floor() as usize;
         y = ((grid_size.y as f32) * offset.y).floor() as usize;
     } else {
-        x = (position.x / cell_diameter).floor() as usize;
-        y = (position.z / cell_diameter).floor() as usize;
+        x = (position.x / cell_size.x).floor() as usize;
+        y = (position.z / cell_size.y).floor() as usize;
     }
 
     x = x.min(grid[0].len() - 1);